@@ -21,7 +21,7 @@ use {json, Error, crypto};
 use crypto::Keccak256;
 use random::Random;
 use smallvec::SmallVec;
-use account::{Cipher, Kdf, Aes128Ctr, Pbkdf2, Prf};
+use account::{Cipher, Kdf, KdfParams, Aes128Ctr, Pbkdf2, Scrypt, Prf};
 
 /// Encrypted data
 #[derive(Debug, PartialEq, Clone)]
@@ -74,19 +74,39 @@ impl From<Crypto> for String {
 
 impl Crypto {
 	/// Encrypt account secret
-	pub fn with_secret(secret: &Secret, password: &Password, iterations: u32) -> Result<Self, crypto::Error> {
-		Crypto::with_plain(secret.as_ref(), password, iterations)
+	pub fn with_secret(secret: &Secret, password: &Password, kdf: KdfParams) -> Result<Self, crypto::Error> {
+		Crypto::with_plain(secret.as_ref(), password, kdf)
 	}
 
 	/// Encrypt custom plain data
-	pub fn with_plain(plain: &[u8], password: &Password, iterations: u32) -> Result<Self, crypto::Error> {
+	pub fn with_plain(plain: &[u8], password: &Password, kdf: KdfParams) -> Result<Self, crypto::Error> {
 		let salt: [u8; 32] = Random::random();
 		let iv: [u8; 16] = Random::random();
 
 		// two parts of derived key
 		// DK = [ DK[0..15] DK[16..31] ] = [derived_left_bits, derived_right_bits]
-		let (derived_left_bits, derived_right_bits) =
-			crypto::derive_key_iterations(password.as_bytes(), &salt, iterations);
+		let ((derived_left_bits, derived_right_bits), kdf) = match kdf {
+			KdfParams::Pbkdf2 { iterations } => (
+				crypto::derive_key_iterations(password.as_bytes(), &salt, iterations),
+				Kdf::Pbkdf2(Pbkdf2 {
+					dklen: crypto::KEY_LENGTH as u32,
+					salt: salt.to_vec(),
+					c: iterations,
+					prf: Prf::HmacSha256,
+				}),
+			),
+			// a longer scrypt key only appends bytes, the first 32 are the same
+			KdfParams::Scrypt { n, r, p, dklen } => (
+				crypto::scrypt::derive_key(password.as_bytes(), &salt, n, p, r)?,
+				Kdf::Scrypt(Scrypt {
+					dklen: dklen,
+					p: p,
+					n: n,
+					r: r,
+					salt: salt.to_vec(),
+				}),
+			),
+		};
 
 		// preallocated (on-stack in case of `Secret`) buffer to hold cipher
 		// length = length(plain) as we are using CTR-approach
@@ -104,12 +124,7 @@ impl Crypto {
 				iv: iv,
 			}),
 			ciphertext: ciphertext.into_vec(),
-			kdf: Kdf::Pbkdf2(Pbkdf2 {
-				dklen: crypto::KEY_LENGTH as u32,
-				salt: salt.to_vec(),
-				c: iterations,
-				prf: Prf::HmacSha256,
-			}),
+			kdf: kdf,
 			mac: mac,
 		})
 	}
@@ -160,13 +175,16 @@ impl Crypto {
 #[cfg(test)]
 mod tests {
 	use crypto::publickey::{Generator, Random};
+	use account::{Kdf, KdfParams};
 	use super::{Crypto, Error};
 
+	const KDF: KdfParams = KdfParams::Pbkdf2 { iterations: 10240 };
+
 	#[test]
 	fn crypto_with_secret_create() {
 		let keypair = Random.generate();
 		let passwd = "this is sparta".into();
-		let crypto = Crypto::with_secret(keypair.secret(), &passwd, 10240).unwrap();
+		let crypto = Crypto::with_secret(keypair.secret(), &passwd, KDF).unwrap();
 		let secret = crypto.secret(&passwd).unwrap();
 		assert_eq!(keypair.secret(), &secret);
 	}
@@ -174,7 +192,7 @@ mod tests {
 	#[test]
 	fn crypto_with_secret_invalid_password() {
 		let keypair = Random.generate();
-		let crypto = Crypto::with_secret(keypair.secret(), &"this is sparta".into(), 10240).unwrap();
+		let crypto = Crypto::with_secret(keypair.secret(), &"this is sparta".into(), KDF).unwrap();
 		assert_matches!(crypto.secret(&"this is sparta!".into()), Err(Error::InvalidPassword))
 	}
 
@@ -182,7 +200,7 @@ mod tests {
 	fn crypto_with_null_plain_data() {
 		let original_data = b"";
 		let passwd = "this is sparta".into();
-		let crypto = Crypto::with_plain(&original_data[..], &passwd, 10240).unwrap();
+		let crypto = Crypto::with_plain(&original_data[..], &passwd, KDF).unwrap();
 		let decrypted_data = crypto.decrypt(&passwd).unwrap();
 		assert_eq!(original_data[..], *decrypted_data);
 	}
@@ -191,7 +209,7 @@ mod tests {
 	fn crypto_with_tiny_plain_data() {
 		let original_data = b"{}";
 		let passwd = "this is sparta".into();
-		let crypto = Crypto::with_plain(&original_data[..], &passwd, 10240).unwrap();
+		let crypto = Crypto::with_plain(&original_data[..], &passwd, KDF).unwrap();
 		let decrypted_data = crypto.decrypt(&passwd).unwrap();
 		assert_eq!(original_data[..], *decrypted_data);
 	}
@@ -200,8 +218,24 @@ mod tests {
 	fn crypto_with_huge_plain_data() {
 		let original_data: Vec<_> = (1..65536).map(|i| (i % 256) as u8).collect();
 		let passwd = "this is sparta".into();
-		let crypto = Crypto::with_plain(&original_data, &passwd, 10240).unwrap();
+		let crypto = Crypto::with_plain(&original_data, &passwd, KDF).unwrap();
 		let decrypted_data = crypto.decrypt(&passwd).unwrap();
 		assert_eq!(&original_data, &decrypted_data);
 	}
+
+	#[test]
+	fn crypto_with_scrypt() {
+		let keypair = Random.generate();
+		let passwd = "this is sparta".into();
+		let kdf = KdfParams::Scrypt { n: 1024, r: 8, p: 1, dklen: 32 };
+		let crypto = Crypto::with_secret(keypair.secret(), &passwd, kdf).unwrap();
+		match crypto.kdf {
+			Kdf::Scrypt(ref params) => assert_eq!((params.n, params.r, params.p, params.dklen), (1024, 8, 1, 32)),
+			_ => panic!("expected scrypt kdf"),
+		}
+
+		let crypto: Crypto = String::from(crypto).parse().unwrap();
+		assert_eq!(crypto.secret(&passwd).unwrap(), *keypair.secret());
+		assert_matches!(crypto.secret(&"this is sparta!".into()), Err(Error::InvalidPassword));
+	}
 }
@@ -14,6 +14,9 @@
 // You should have received a copy of the GNU General Public License
 // along with Open Ethereum.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::fmt;
+use std::str::FromStr;
+use crypto::{KEY_ITERATIONS, KEY_LENGTH};
 use json;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -44,6 +47,118 @@ pub enum Kdf {
 	Scrypt(Scrypt),
 }
 
+/// Key derivation function and cost used to encrypt new keys, without the per-key salt.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KdfParams {
+	/// PBKDF2 with HMAC-SHA256.
+	Pbkdf2 {
+		/// Number of iterations
+		iterations: u32,
+	},
+	/// Scrypt, the default of geth.
+	Scrypt {
+		/// CPU and memory cost, a power of two
+		n: u32,
+		/// Block size
+		r: u32,
+		/// Parallelization
+		p: u32,
+		/// Length of the derived key in bytes, at least 32
+		dklen: u32,
+	},
+}
+
+impl Default for KdfParams {
+	fn default() -> Self {
+		KdfParams::Pbkdf2 { iterations: KEY_ITERATIONS as u32 }
+	}
+}
+
+impl KdfParams {
+	/// Scrypt parameters geth uses for new keys.
+	pub fn scrypt() -> Self {
+		KdfParams::Scrypt { n: 262144, r: 8, p: 1, dklen: KEY_LENGTH as u32 }
+	}
+
+	/// Checks that keys written with these parameters can be read back, by geth as well.
+	pub fn validate(&self) -> Result<(), String> {
+		match *self {
+			KdfParams::Pbkdf2 { iterations: 0 } => Err("pbkdf2 needs at least one iteration".into()),
+			KdfParams::Pbkdf2 { .. } => Ok(()),
+			KdfParams::Scrypt { n, .. } if n < 2 || !n.is_power_of_two() => Err(format!("scrypt n must be a power of two above 1, not {}", n)),
+			KdfParams::Scrypt { r: 0, .. } | KdfParams::Scrypt { p: 0, .. } => Err("scrypt r and p must be at least 1".into()),
+			KdfParams::Scrypt { dklen, .. } if dklen < KEY_LENGTH as u32 => Err(format!("scrypt dklen must be at least {}, not {}", KEY_LENGTH, dklen)),
+			KdfParams::Scrypt { .. } => Ok(()),
+		}
+	}
+
+	/// Whether keys written with these parameters are cheaper to attack than with `target`:
+	/// fewer PBKDF2 iterations, less scrypt memory or work, or PBKDF2 when scrypt is wanted.
+	/// Scrypt keys are never weaker than a PBKDF2 target.
+	pub fn is_weaker_than(&self, target: &KdfParams) -> bool {
+		match (*self, *target) {
+			(KdfParams::Pbkdf2 { iterations }, KdfParams::Pbkdf2 { iterations: target }) => iterations < target,
+			(KdfParams::Pbkdf2 { .. }, KdfParams::Scrypt { .. }) => true,
+			(KdfParams::Scrypt { .. }, KdfParams::Pbkdf2 { .. }) => false,
+			(KdfParams::Scrypt { n, r, p, .. }, KdfParams::Scrypt { n: target_n, r: target_r, p: target_p, .. }) => {
+				let memory = n as u64 * r as u64;
+				let target_memory = target_n as u64 * target_r as u64;
+				memory < target_memory || memory * (p as u64) < target_memory * (target_p as u64)
+			},
+		}
+	}
+}
+
+impl<'a> From<&'a Kdf> for KdfParams {
+	fn from(kdf: &'a Kdf) -> Self {
+		match *kdf {
+			Kdf::Pbkdf2(ref params) => KdfParams::Pbkdf2 { iterations: params.c },
+			Kdf::Scrypt(ref params) => KdfParams::Scrypt { n: params.n, r: params.r, p: params.p, dklen: params.dklen },
+		}
+	}
+}
+
+impl fmt::Display for KdfParams {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			KdfParams::Pbkdf2 { iterations } => write!(f, "pbkdf2:c={}", iterations),
+			KdfParams::Scrypt { n, r, p, dklen } => write!(f, "scrypt:n={},r={},p={},dklen={}", n, r, p, dklen),
+		}
+	}
+}
+
+/// Parses `pbkdf2`, `scrypt` or either followed by parameters overriding the defaults,
+/// e.g. `pbkdf2:c=262144` or `scrypt:n=4096,p=6`.
+impl FromStr for KdfParams {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		let mut parts = value.splitn(2, ':');
+		let mut kdf = match parts.next() {
+			Some("pbkdf2") => KdfParams::default(),
+			Some("scrypt") => KdfParams::scrypt(),
+			_ => return Err(format!("unknown KDF '{}', expected pbkdf2 or scrypt", value)),
+		};
+		for param in parts.next().into_iter().flat_map(|params| params.split(',')) {
+			let mut pair = param.splitn(2, '=');
+			let (name, number) = match (pair.next(), pair.next().map(str::parse::<u32>)) {
+				(Some(name), Some(Ok(number))) => (name, number),
+				_ => return Err(format!("KDF parameter '{}' must be name=number", param)),
+			};
+			match (&mut kdf, name) {
+				(KdfParams::Pbkdf2 { iterations }, "c") => *iterations = number,
+				(KdfParams::Scrypt { n, .. }, "n") => *n = number,
+				(KdfParams::Scrypt { r, .. }, "r") => *r = number,
+				(KdfParams::Scrypt { p, .. }, "p") => *p = number,
+				(KdfParams::Scrypt { dklen, .. }, "dklen") => *dklen = number,
+				_ => return Err(format!("unknown KDF parameter '{}' in '{}'", name, value)),
+			}
+		}
+		kdf.validate()?;
+		Ok(kdf)
+	}
+}
+
 impl From<json::Prf> for Prf {
 	fn from(json: json::Prf) -> Self {
 		match json {
@@ -123,3 +238,36 @@ impl Into<json::Kdf> for Kdf {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::KdfParams;
+
+	#[test]
+	fn kdf_params_parse() {
+		assert_eq!("pbkdf2".parse(), Ok(KdfParams::default()));
+		assert_eq!("pbkdf2:c=262144".parse(), Ok(KdfParams::Pbkdf2 { iterations: 262144 }));
+		assert_eq!("scrypt".parse(), Ok(KdfParams::scrypt()));
+		assert_eq!("scrypt:n=4096,p=6".parse(), Ok(KdfParams::Scrypt { n: 4096, r: 8, p: 6, dklen: 32 }));
+		assert_eq!(KdfParams::scrypt().to_string().parse(), Ok(KdfParams::scrypt()));
+	}
+
+	#[test]
+	fn kdf_params_strength() {
+		let weak = KdfParams::Pbkdf2 { iterations: 1024 };
+		let light = KdfParams::Scrypt { n: 4096, r: 8, p: 6, dklen: 32 };
+		assert!(weak.is_weaker_than(&KdfParams::default()));
+		assert!(!KdfParams::default().is_weaker_than(&KdfParams::default()));
+		assert!(KdfParams::default().is_weaker_than(&light));
+		assert!(!light.is_weaker_than(&KdfParams::Pbkdf2 { iterations: 1000000 }));
+		assert!(light.is_weaker_than(&KdfParams::scrypt()));
+		assert!(!KdfParams::scrypt().is_weaker_than(&light));
+	}
+
+	#[test]
+	fn kdf_params_parse_invalid() {
+		for value in &["argon2", "pbkdf2:c=0", "pbkdf2:n=1024", "scrypt:n=1000", "scrypt:dklen=16", "scrypt:r", "scrypt:p=x"] {
+			assert!(value.parse::<KdfParams>().is_err(), "{}", value);
+		}
+	}
+}
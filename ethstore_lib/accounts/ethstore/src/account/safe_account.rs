@@ -18,7 +18,7 @@ use crypto::publickey::{KeyPair, sign, Address, Signature, Message, Public, Secr
 use ethkey::Password;
 use crypto::publickey::ecdh::agree;
 use {json, Error};
-use account::Version;
+use account::{KdfParams, Version};
 use crypto;
 use super::crypto::Crypto;
 
@@ -60,14 +60,14 @@ impl SafeAccount {
 		keypair: &KeyPair,
 		id: [u8; 16],
 		password: &Password,
-		iterations: u32,
+		kdf: KdfParams,
 		name: String,
 		meta: String
 	) -> Result<Self, crypto::Error> {
 		Ok(SafeAccount {
 			id: id,
 			version: Version::V3,
-			crypto: Crypto::with_secret(keypair.secret(), password, iterations)?,
+			crypto: Crypto::with_secret(keypair.secret(), password, kdf)?,
 			address: keypair.address(),
 			filename: None,
 			name: name,
@@ -136,14 +136,14 @@ impl SafeAccount {
 	}
 
 	/// Create a new `VaultKeyFile` from the given `self`
-	pub fn into_vault_file(self, iterations: u32, password: &Password) -> Result<json::VaultKeyFile, Error> {
+	pub fn into_vault_file(self, kdf: KdfParams, password: &Password) -> Result<json::VaultKeyFile, Error> {
 		let meta_plain = json::VaultKeyMeta {
 			address: self.address.into(),
 			name: Some(self.name),
 			meta: Some(self.meta),
 		};
 		let meta_plain = meta_plain.write().map_err(|e| Error::Custom(format!("{:?}", e)))?;
-		let meta_crypto = Crypto::with_plain(&meta_plain, password, iterations)?;
+		let meta_crypto = Crypto::with_plain(&meta_plain, password, kdf)?;
 
 		Ok(json::VaultKeyFile {
 			id: self.id.into(),
@@ -178,12 +178,12 @@ impl SafeAccount {
 	}
 
 	/// Change account's password.
-	pub fn change_password(&self, old_password: &Password, new_password: &Password, iterations: u32) -> Result<Self, Error> {
+	pub fn change_password(&self, old_password: &Password, new_password: &Password, kdf: KdfParams) -> Result<Self, Error> {
 		let secret = self.crypto.secret(old_password)?;
 		let result = SafeAccount {
 			id: self.id.clone(),
 			version: self.version.clone(),
-			crypto: Crypto::with_secret(&secret, new_password, iterations)?,
+			crypto: Crypto::with_secret(&secret, new_password, kdf)?,
 			address: self.address.clone(),
 			filename: self.filename.clone(),
 			name: self.name.clone(),
@@ -201,6 +201,7 @@ impl SafeAccount {
 #[cfg(test)]
 mod tests {
 	use crypto::publickey::{Generator, Random, verify_public};
+	use account::{Kdf, KdfParams};
 	use super::SafeAccount;
 
 	#[test]
@@ -208,7 +209,7 @@ mod tests {
 		let keypair = Random.generate();
 		let password = "hello world".into();
 		let message = [1u8; 32].into();
-		let account = SafeAccount::create(&keypair, [0u8; 16], &password, 10240, "Test".to_owned(), "{}".to_owned());
+		let account = SafeAccount::create(&keypair, [0u8; 16], &password, KdfParams::Pbkdf2 { iterations: 10240 }, "Test".to_owned(), "{}".to_owned());
 		let signature = account.unwrap().sign(&password, &message).unwrap();
 		assert!(verify_public(keypair.public(), &signature, &message).unwrap());
 	}
@@ -218,7 +219,7 @@ mod tests {
 		let keypair = Random.generate();
 		let first_password = "hello world".into();
 		let sec_password = "this is sparta".into();
-		let i = 10240;
+		let i = KdfParams::Pbkdf2 { iterations: 10240 };
 		let message = [1u8; 32].into();
 		let account = SafeAccount::create(&keypair, [0u8; 16], &first_password, i, "Test".to_owned(), "{}".to_owned()).unwrap();
 		let new_account = account.change_password(&first_password, &sec_password, i).unwrap();
@@ -227,4 +228,18 @@ mod tests {
 		assert!(new_account.sign(&first_password, &message).is_err());
 		assert!(new_account.sign(&sec_password, &message).is_ok());
 	}
+
+	#[test]
+	fn change_password_to_scrypt() {
+		let keypair = Random.generate();
+		let first_password = "hello world".into();
+		let sec_password = "this is sparta".into();
+		let message = [1u8; 32].into();
+		let account = SafeAccount::create(&keypair, [0u8; 16], &first_password, KdfParams::Pbkdf2 { iterations: 1024 }, "Test".to_owned(), "{}".to_owned()).unwrap();
+		let scrypt = KdfParams::Scrypt { n: 1024, r: 8, p: 1, dklen: 32 };
+		let new_account = account.change_password(&first_password, &sec_password, scrypt).unwrap();
+		assert_matches!(new_account.crypto.kdf, Kdf::Scrypt(_));
+		assert_eq!(new_account.address, account.address);
+		assert!(new_account.sign(&sec_password, &message).is_ok());
+	}
 }
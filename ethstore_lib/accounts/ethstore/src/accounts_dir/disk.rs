@@ -25,7 +25,7 @@ use super::{KeyDirectory, VaultKeyDirectory, VaultKeyDirectoryProvider, VaultKey
 use super::vault::{VAULT_FILE_NAME, VaultDiskDirectory};
 use ethkey::Password;
 
-const IGNORED_FILES: &'static [&'static str] = &[
+pub(super) const IGNORED_FILES: &'static [&'static str] = &[
 	"thumbs.db",
 	"address_book.json",
 	"dapps_policy.json",
@@ -98,6 +98,83 @@ pub fn replace_file_with_permissions_to_owner(file_path: &Path) -> io::Result<fs
 	fs::File::create(file_path)
 }
 
+/// Flush the entries of a directory to disk, so that files created, renamed or removed in it survive a crash.
+#[cfg(unix)]
+pub fn sync_dir(dir_path: &Path) -> io::Result<()> {
+	fs::File::open(dir_path)?.sync_all()
+}
+
+/// Flush the entries of a directory to disk, so that files created, renamed or removed in it survive a crash.
+#[cfg(not(unix))]
+pub fn sync_dir(_dir_path: &Path) -> io::Result<()> {
+	Ok(())
+}
+
+/// Take an exclusive lock on the file `filename` in `dir_path`, creating it if needed, and wait while
+/// another process holds it. The lock is released when the returned file is dropped.
+#[cfg(unix)]
+pub fn lock_file_exclusive(dir_path: &Path, filename: &str) -> io::Result<fs::File> {
+	use std::os::unix::fs::OpenOptionsExt;
+	use std::os::unix::io::AsRawFd;
+
+	let file = fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.mode((libc::S_IWUSR | libc::S_IRUSR) as u32)
+		.open(dir_path.join(filename))?;
+	loop {
+		if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+			return Ok(file);
+		}
+		let err = io::Error::last_os_error();
+		if err.kind() != io::ErrorKind::Interrupted {
+			return Err(err);
+		}
+	}
+}
+
+/// Open the lock file `filename` in `dir_path`, creating it if needed. Other processes are not
+/// locked out on this platform.
+#[cfg(not(unix))]
+pub fn lock_file_exclusive(dir_path: &Path, filename: &str) -> io::Result<fs::File> {
+	fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.open(dir_path.join(filename))
+}
+
+/// Write a file with permissions to owner only, so that a crash leaves either the old or the new file:
+/// the content goes to a hidden temporary file which is synced, renamed over the file and the directory
+/// is synced. Unless `replace` is set, the temporary file is hard linked instead, which errors if the file
+/// already exists.
+pub fn write_file_atomically<F>(dir_path: &Path, filename: &str, replace: bool, write: F) -> Result<(), Error> where F: FnOnce(&mut fs::File) -> Result<(), Error> {
+	let file_path = dir_path.join(filename);
+	let temp_filename = find_unique_filename_using_random_suffix(dir_path, &format!(".{}.tmp", filename))?;
+	let temp_path = dir_path.join(temp_filename);
+
+	let result = create_new_file_with_permissions_to_owner(&temp_path)
+		.map_err(Into::into)
+		.and_then(|mut file| {
+			write(&mut file)?;
+			file.flush()?;
+			file.sync_all()?;
+			Ok(())
+		})
+		.and_then(|_| if replace {
+			fs::rename(&temp_path, &file_path).map_err(Into::into)
+		} else {
+			// unlike rename, linking fails if the file exists, even when it is created concurrently
+			fs::hard_link(&temp_path, &file_path).map_err(Into::into)
+		});
+	// once linked, the temporary file is only a second name of the file
+	if result.is_err() || !replace {
+		let _ = fs::remove_file(&temp_path);
+	}
+	result?;
+
+	sync_dir(dir_path).map_err(Into::into)
+}
+
 /// Root keys directory implementation
 pub type RootDiskDirectory = DiskDirectory<DiskKeyFileManager>;
 
@@ -216,28 +293,15 @@ impl<T> DiskDirectory<T> where T: KeyFileManager {
 			filename = find_unique_filename_using_random_suffix(&self.path, &filename)?;
 		}
 
-		// path to keyfile
-		let keyfile_path = self.path.join(filename.as_str());
-
 		// update account filename
 		let original_account = account.clone();
 		let mut account = account;
-		account.filename = Some(filename);
+		account.filename = Some(filename.clone());
 
-		{
-			// save the file
-			let mut file = if dedup {
-				create_new_file_with_permissions_to_owner(&keyfile_path)?
-			} else {
-				replace_file_with_permissions_to_owner(&keyfile_path)?
-			};
-
-			// write key content
-			self.key_manager.write(original_account, &mut file).map_err(|e| Error::Custom(format!("{:?}", e)))?;
-
-			file.flush()?;
-			file.sync_all()?;
-		}
+		// save the file; an existing one is only replaced by updates
+		write_file_atomically(&self.path, &filename, !dedup, |file| {
+			self.key_manager.write(original_account, file).map_err(|e| Error::Custom(format!("{:?}", e)))
+		})?;
 
 		Ok(account)
 	}
@@ -278,7 +342,10 @@ impl<T> KeyDirectory for DiskDirectory<T> where T: KeyFileManager {
 		// remove it
 		match to_remove {
 			None => Err(Error::InvalidAccount),
-			Some((path, _)) => fs::remove_file(path).map_err(From::from)
+			Some((path, _)) => {
+				fs::remove_file(path)?;
+				sync_dir(&self.path).map_err(From::from)
+			}
 		}
 	}
 
@@ -316,12 +383,26 @@ impl<T> VaultKeyDirectoryProvider for DiskDirectory<T> where T: KeyFileManager {
 					None
 				}
 			})
+			// vaults being deleted are hidden
+			.filter(|name| !name.starts_with("."))
 			.collect())
 	}
 
 	fn vault_meta(&self, name: &str) -> Result<String, Error> {
 		VaultDiskDirectory::meta_at(&self.path, name)
 	}
+
+	fn delete(&self, name: &str, key: VaultKey, force: bool) -> Result<(), Error> {
+		VaultDiskDirectory::delete_at(&self.path, name, &key, force)
+	}
+
+	fn rename(&self, name: &str, new_name: &str, key: VaultKey) -> Result<(), Error> {
+		VaultDiskDirectory::rename_at(&self.path, name, new_name, &key)
+	}
+
+	fn recover(&self) -> Result<(), Error> {
+		VaultDiskDirectory::recover_at(&self.path)
+	}
 }
 
 impl KeyFileManager for DiskKeyFileManager {
@@ -354,8 +435,9 @@ mod test {
 	extern crate tempfile;
 
 	use std::{env, fs};
-	use super::{KeyDirectory, RootDiskDirectory, VaultKey};
-	use account::SafeAccount;
+	use std::io::Write;
+	use super::{KeyDirectory, RootDiskDirectory, VaultKey, write_file_atomically};
+	use account::{KdfParams, SafeAccount};
 	use crypto::publickey::{Random, Generator};
 	use self::tempfile::TempDir;
 
@@ -369,7 +451,7 @@ mod test {
 		let directory = RootDiskDirectory::create(dir.clone()).unwrap();
 
 		// when
-		let account = SafeAccount::create(&keypair, [0u8; 16], &password, 1024, "Test".to_owned(), "{}".to_owned());
+		let account = SafeAccount::create(&keypair, [0u8; 16], &password, KdfParams::Pbkdf2 { iterations: 1024 }, "Test".to_owned(), "{}".to_owned());
 		let res = directory.insert(account.unwrap());
 
 		// then
@@ -390,7 +472,7 @@ mod test {
 		let directory = RootDiskDirectory::create(dir.clone()).unwrap();
 
 		// when
-		let account = SafeAccount::create(&keypair, [0u8; 16], &password, 1024, "Test".to_owned(), "{}".to_owned()).unwrap();
+		let account = SafeAccount::create(&keypair, [0u8; 16], &password, KdfParams::Pbkdf2 { iterations: 1024 }, "Test".to_owned(), "{}".to_owned()).unwrap();
 		let filename = "test".to_string();
 		let dedup = true;
 
@@ -412,6 +494,52 @@ mod test {
 		let _ = fs::remove_dir_all(dir);
 	}
 
+	#[test]
+	fn should_replace_updated_key_file() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let keypair = Random.generate();
+		let password = "hello world".into();
+		let directory = RootDiskDirectory::create(temp_path.path()).unwrap();
+		let account = SafeAccount::create(&keypair, [0u8; 16], &password, KdfParams::Pbkdf2 { iterations: 1024 }, "Test".to_owned(), "{}".to_owned()).unwrap();
+		let account = directory.insert(account).unwrap();
+
+		// when
+		let updated = account.change_password(&password, &"new password".into(), KdfParams::Pbkdf2 { iterations: 2048 }).unwrap();
+		let updated = directory.update(updated).unwrap();
+
+		// then
+		assert_eq!(updated.filename, account.filename);
+		assert_eq!(fs::read_dir(temp_path.path()).unwrap().count(), 1);
+		let loaded = directory.load().unwrap();
+		assert_eq!(loaded.len(), 1);
+		assert!(loaded[0].check_password(&"new password".into()));
+	}
+
+	#[test]
+	fn should_not_replace_existing_file_unless_asked() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		fs::write(temp_path.path().join("file"), "old").unwrap();
+
+		// when
+		let result = write_file_atomically(temp_path.path(), "file", false, |file| file.write_all(b"new").map_err(Into::into));
+
+		// then
+		assert!(result.is_err());
+		assert_eq!(fs::read_to_string(temp_path.path().join("file")).unwrap(), "old");
+		assert_eq!(fs::read_dir(temp_path.path()).unwrap().count(), 1);
+
+		// and when
+		write_file_atomically(temp_path.path(), "file", true, |file| file.write_all(b"new").map_err(Into::into)).unwrap();
+		write_file_atomically(temp_path.path(), "other", false, |file| file.write_all(b"other").map_err(Into::into)).unwrap();
+
+		// then
+		assert_eq!(fs::read_to_string(temp_path.path().join("file")).unwrap(), "new");
+		assert_eq!(fs::read_to_string(temp_path.path().join("other")).unwrap(), "other");
+		assert_eq!(fs::read_dir(temp_path.path()).unwrap().count(), 2);
+	}
+
 	#[test]
 	fn should_manage_vaults() {
 		// given
@@ -426,7 +554,7 @@ mod test {
 
 		// and when
 		let before_root_items_count = fs::read_dir(&dir).unwrap().count();
-		let vault = directory.as_vault_provider().unwrap().create(vault_name, VaultKey::new(&password, 1024));
+		let vault = directory.as_vault_provider().unwrap().create(vault_name, VaultKey::new(&password, KdfParams::Pbkdf2 { iterations: 1024 }));
 
 		// then
 		assert!(vault.is_ok());
@@ -434,7 +562,7 @@ mod test {
 		assert!(after_root_items_count > before_root_items_count);
 
 		// and when
-		let vault = directory.as_vault_provider().unwrap().open(vault_name, VaultKey::new(&password, 1024));
+		let vault = directory.as_vault_provider().unwrap().open(vault_name, VaultKey::new(&password, KdfParams::Pbkdf2 { iterations: 1024 }));
 
 		// then
 		assert!(vault.is_ok());
@@ -451,8 +579,8 @@ mod test {
 		let temp_path = TempDir::new().unwrap();
 		let directory = RootDiskDirectory::create(&temp_path).unwrap();
 		let vault_provider = directory.as_vault_provider().unwrap();
-		vault_provider.create("vault1", VaultKey::new(&"password1".into(), 1)).unwrap();
-		vault_provider.create("vault2", VaultKey::new(&"password2".into(), 1)).unwrap();
+		vault_provider.create("vault1", VaultKey::new(&"password1".into(), KdfParams::Pbkdf2 { iterations: 1 })).unwrap();
+		vault_provider.create("vault2", VaultKey::new(&"password2".into(), KdfParams::Pbkdf2 { iterations: 1 })).unwrap();
 
 		// then
 		let vaults = vault_provider.list_vaults().unwrap();
@@ -474,7 +602,7 @@ mod test {
 
 		let keypair = Random.generate();
 		let password = "test pass".into();
-		let account = SafeAccount::create(&keypair, [0u8; 16], &password, 1024, "Test".to_owned(), "{}".to_owned());
+		let account = SafeAccount::create(&keypair, [0u8; 16], &password, KdfParams::Pbkdf2 { iterations: 1024 }, "Test".to_owned(), "{}".to_owned());
 		directory.insert(account.unwrap()).expect("Account should be inserted ok");
 
 		let new_hash = directory.files_hash().expect("New files hash should be calculated ok");
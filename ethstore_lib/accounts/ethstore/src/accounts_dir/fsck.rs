@@ -0,0 +1,550 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of Open Ethereum.
+
+// Open Ethereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Open Ethereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Open Ethereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Integrity check of a keys directory and its vaults.
+
+use std::collections::{BTreeMap, HashMap};
+use std::{fmt, fs, io};
+use std::path::{Path, PathBuf};
+use crypto::publickey::{Address, KeyPair};
+use ethkey::Password;
+use account::Crypto;
+use {json, Error, KdfParams, SafeAccount};
+use super::VaultKey;
+use super::disk::{self, IGNORED_FILES};
+use super::vault::{self, VAULT_FILE_NAME, VAULT_TEMP_FILE_NAME};
+
+/// How serious an anomaly is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	/// Nothing is wrong, but the check was incomplete
+	Info,
+	/// Keys can be used, but something needs attention
+	Warning,
+	/// File cannot be used as it is
+	Error,
+}
+
+/// Kind of anomaly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+	/// File is not a valid key file
+	Unreadable,
+	/// Declared address differs from the address of the decrypted secret
+	AddressMismatch,
+	/// Key file has no address, it can only be loaded with its password
+	MissingAddress,
+	/// None of the given passwords passes the MAC check of the key
+	PasswordMismatch,
+	/// Another file holds a key for the same address
+	DuplicateAddress,
+	/// Temporary file or vault left by an interrupted write
+	Leftover,
+	/// Other users may read the file
+	Permissions,
+	/// Vault file is unreadable or does not open with the given password
+	Vault,
+	/// Vault keys were only checked for structure, no vault password was given
+	VaultSkipped,
+}
+
+impl AnomalyKind {
+	/// Whether `quarantine` moves files with this anomaly.
+	pub fn is_quarantined(&self) -> bool {
+		match *self {
+			AnomalyKind::Unreadable | AnomalyKind::AddressMismatch | AnomalyKind::Leftover => true,
+			_ => false,
+		}
+	}
+}
+
+/// Problem found in a keys directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+	/// File or directory the anomaly is about
+	pub path: PathBuf,
+	/// How serious it is
+	pub severity: Severity,
+	/// What is wrong
+	pub kind: AnomalyKind,
+	/// Human readable details
+	pub message: String,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Severity::Info => write!(f, "info"),
+			Severity::Warning => write!(f, "warning"),
+			Severity::Error => write!(f, "error"),
+		}
+	}
+}
+
+impl fmt::Display for AnomalyKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			AnomalyKind::Unreadable => "unreadable",
+			AnomalyKind::AddressMismatch => "address-mismatch",
+			AnomalyKind::MissingAddress => "missing-address",
+			AnomalyKind::PasswordMismatch => "password-mismatch",
+			AnomalyKind::DuplicateAddress => "duplicate-address",
+			AnomalyKind::Leftover => "leftover",
+			AnomalyKind::Permissions => "permissions",
+			AnomalyKind::Vault => "vault",
+			AnomalyKind::VaultSkipped => "vault-skipped",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/// Where the files being checked live
+#[derive(Clone, Copy)]
+enum Location<'a> {
+	Root,
+	Vault(Option<&'a Password>),
+}
+
+struct Checker<'a> {
+	passwords: &'a [Password],
+	anomalies: Vec<Anomaly>,
+	addresses: BTreeMap<Address, Vec<PathBuf>>,
+}
+
+/// Checks every file of the keys directory at `root` and of its vaults. Keys are decrypted
+/// with each of `passwords` to verify their MAC and address; vault keys are only read when
+/// `vault_passwords` has the vault's password. Nothing is modified.
+pub fn check<P>(root: P, passwords: &[Password], vault_passwords: &HashMap<String, Password>) -> Result<Vec<Anomaly>, Error> where P: AsRef<Path> {
+	let root = root.as_ref();
+	let mut checker = Checker {
+		passwords: passwords,
+		anomalies: Vec::new(),
+		addresses: BTreeMap::new(),
+	};
+
+	checker.check_dir(root, Location::Root)?;
+	let interrupted = vault::hidden_vault_entries(root)?;
+	for entry in fs::read_dir(root)? {
+		let path = entry?.path();
+		if !path.is_dir() {
+			continue;
+		}
+		let name = file_name(&path);
+		if let Some(&(ref vault, _)) = interrupted.iter().find(|&&(ref vault, ref suffix)| name == format!(".{}.{}", vault, suffix)) {
+			checker.push(&path, Severity::Warning, AnomalyKind::Vault, format!("left by an interrupted change of vault '{}', recovered when the keystore is next opened", vault));
+			continue;
+		}
+		if !path.join(VAULT_FILE_NAME).is_file() {
+			if fs::read_dir(&path)?.next().is_some() {
+				checker.push(&path, Severity::Warning, AnomalyKind::Vault, "directory has no vault.json, its keys are not loaded".into());
+			}
+			continue;
+		}
+		if let Some(vault) = temp_vault_of(root, &name) {
+			checker.push(&path, Severity::Warning, AnomalyKind::Leftover, format!("copy of vault '{}' left by an interrupted password change", vault));
+			continue;
+		}
+		checker.check_vault(&path, vault_passwords.get(&name))?;
+	}
+	checker.push_duplicates();
+
+	let mut anomalies = checker.anomalies;
+	anomalies.sort_by(|a, b| a.path.cmp(&b.path).then(b.severity.cmp(&a.severity)));
+	Ok(anomalies)
+}
+
+/// Failure of `quarantine`, with the files it moved before the error.
+#[derive(Debug)]
+pub struct QuarantineError {
+	/// Files and directories already moved, as `(from, to)` pairs
+	pub moved: Vec<(PathBuf, PathBuf)>,
+	/// What stopped the quarantine
+	pub error: Error,
+}
+
+impl fmt::Display for QuarantineError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.moved.is_empty() {
+			return write!(f, "{}", self.error);
+		}
+		let moved: Vec<_> = self.moved.iter().map(|&(ref from, ref to)| format!("{} to {}", from.display(), to.display())).collect();
+		write!(f, "quarantine failed after moving {}: {}", moved.join(", "), self.error)
+	}
+}
+
+/// Moves the files and directories with quarantined anomalies from `root` to the same
+/// relative path under `target`, and returns where each of them went. `target` may be on
+/// another filesystem, entries are then copied, synced and removed. On error, the entries
+/// moved so far are returned with it.
+pub fn quarantine<P, Q>(root: P, anomalies: &[Anomaly], target: Q) -> Result<Vec<(PathBuf, PathBuf)>, QuarantineError> where P: AsRef<Path>, Q: AsRef<Path> {
+	let (root, target) = (root.as_ref(), target.as_ref());
+	let mut moved: Vec<(PathBuf, PathBuf)> = Vec::new();
+	for anomaly in anomalies.iter().filter(|anomaly| anomaly.kind.is_quarantined()) {
+		if moved.iter().any(|&(ref from, _)| *from == anomaly.path) {
+			continue;
+		}
+		match quarantine_path(root, &anomaly.path, target) {
+			Ok(destination) => moved.push((anomaly.path.clone(), destination)),
+			Err(error) => return Err(QuarantineError { moved, error }),
+		}
+	}
+	Ok(moved)
+}
+
+fn quarantine_path(root: &Path, path: &Path, target: &Path) -> Result<PathBuf, Error> {
+	let relative = path.strip_prefix(root)
+		.map_err(|_| Error::Custom(format!("{} is not in {}", path.display(), root.display())))?;
+	let mut destination = target.join(relative);
+	let parent = destination.parent().map(Path::to_path_buf).unwrap_or_else(|| target.to_path_buf());
+	fs::create_dir_all(&parent)?;
+	let name = disk::find_unique_filename_using_random_suffix(&parent, &file_name(&destination))?;
+	destination.set_file_name(name);
+	match fs::rename(path, &destination) {
+		Err(ref err) if is_cross_device(err) => {
+			if let Err(err) = copy_synced(path, &destination) {
+				let _ = remove(&destination);
+				return Err(err.into());
+			}
+			remove(path)?;
+		},
+		result => result?,
+	}
+	Ok(destination)
+}
+
+#[cfg(unix)]
+fn is_cross_device(err: &io::Error) -> bool {
+	err.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(windows)]
+fn is_cross_device(err: &io::Error) -> bool {
+	// ERROR_NOT_SAME_DEVICE
+	err.raw_os_error() == Some(17)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_cross_device(_err: &io::Error) -> bool {
+	false
+}
+
+/// Copies a file or a directory tree and syncs everything it wrote to disk.
+fn copy_synced(from: &Path, to: &Path) -> io::Result<()> {
+	if fs::symlink_metadata(from)?.is_dir() {
+		fs::create_dir(to)?;
+		for entry in fs::read_dir(from)? {
+			let entry = entry?;
+			copy_synced(&entry.path(), &to.join(entry.file_name()))?;
+		}
+	} else {
+		fs::copy(from, to)?;
+	}
+	sync(to)
+}
+
+#[cfg(unix)]
+fn sync(path: &Path) -> io::Result<()> {
+	fs::File::open(path)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync(path: &Path) -> io::Result<()> {
+	// Directories cannot be opened as files here, only file contents are synced.
+	if path.is_dir() {
+		return Ok(());
+	}
+	fs::OpenOptions::new().write(true).open(path)?.sync_all()
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+	if fs::symlink_metadata(path)?.is_dir() {
+		fs::remove_dir_all(path)
+	} else {
+		fs::remove_file(path)
+	}
+}
+
+impl<'a> Checker<'a> {
+	fn push(&mut self, path: &Path, severity: Severity, kind: AnomalyKind, message: String) {
+		self.anomalies.push(Anomaly { path: path.to_path_buf(), severity, kind, message });
+	}
+
+	fn check_dir(&mut self, dir: &Path, location: Location) -> Result<(), Error> {
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+			let path = entry.path();
+			let metadata = entry.metadata()?;
+			let name = file_name(&path);
+			if metadata.is_dir() {
+				continue;
+			}
+			if name.starts_with(".") {
+				if name.contains(".tmp") {
+					self.push(&path, Severity::Warning, AnomalyKind::Leftover, "temporary key file left by an interrupted write".into());
+				}
+				continue;
+			}
+			if name.starts_with(VAULT_TEMP_FILE_NAME) {
+				self.push(&path, Severity::Warning, AnomalyKind::Leftover, "temporary vault file left by an interrupted write".into());
+				continue;
+			}
+			if IGNORED_FILES.contains(&&*name) && name != VAULT_FILE_NAME {
+				continue;
+			}
+			self.check_permissions(&path, &metadata);
+			if name == VAULT_FILE_NAME {
+				continue;
+			}
+			match location {
+				Location::Root => self.check_key_file(&path),
+				Location::Vault(password) => self.check_vault_key_file(&path, password),
+			}
+		}
+		Ok(())
+	}
+
+	fn check_vault(&mut self, dir: &Path, password: Option<&Password>) -> Result<(), Error> {
+		let password = match password {
+			Some(password) => match vault::read_vault_file(dir, Some(&VaultKey::new(password, KdfParams::default()))) {
+				Ok(_) => Some(password),
+				Err(Error::InvalidPassword) => {
+					self.push(&dir.join(VAULT_FILE_NAME), Severity::Warning, AnomalyKind::Vault, "vault password does not open vault.json".into());
+					None
+				},
+				Err(err) => {
+					self.push(&dir.join(VAULT_FILE_NAME), Severity::Error, AnomalyKind::Vault, format!("vault.json is unreadable: {}", err));
+					None
+				},
+			},
+			None => {
+				if let Err(err) = vault::read_vault_file(dir, None) {
+					self.push(&dir.join(VAULT_FILE_NAME), Severity::Error, AnomalyKind::Vault, format!("vault.json is unreadable: {}", err));
+				}
+				None
+			},
+		};
+		if password.is_none() {
+			self.push(dir, Severity::Info, AnomalyKind::VaultSkipped, "vault keys were only checked for structure, give the vault password to check them".into());
+		}
+		self.check_dir(dir, Location::Vault(password))
+	}
+
+	fn check_key_file(&mut self, path: &Path) {
+		let key_file = match fs::File::open(path).map_err(|err| err.to_string()).and_then(|file| json::KeyFile::load(file).map_err(|err| err.to_string())) {
+			Ok(key_file) => key_file,
+			Err(err) => return self.push(path, Severity::Error, AnomalyKind::Unreadable, format!("not a key file: {}", err)),
+		};
+		let declared = key_file.address.as_ref().map(Address::from);
+		if declared.is_none() {
+			self.push(path, Severity::Warning, AnomalyKind::MissingAddress, "key file has no address, it is only loaded with its password".into());
+		}
+		self.check_secret(path, declared, &Crypto::from(key_file.crypto));
+	}
+
+	fn check_vault_key_file(&mut self, path: &Path, vault_password: Option<&Password>) {
+		let vault_key_file = match fs::File::open(path).map_err(|err| err.to_string()).and_then(|file| json::VaultKeyFile::load(file).map_err(|err| err.to_string())) {
+			Ok(vault_key_file) => vault_key_file,
+			Err(err) => return self.push(path, Severity::Error, AnomalyKind::Unreadable, format!("not a vault key file: {}", err)),
+		};
+		if let Some(vault_password) = vault_password {
+			match SafeAccount::from_vault_file(vault_password, vault_key_file, None) {
+				Ok(account) => self.check_secret(path, Some(account.address), &account.crypto),
+				Err(err) => self.push(path, Severity::Error, AnomalyKind::Unreadable, format!("metadata does not decrypt with the vault password: {}", err)),
+			}
+		}
+	}
+
+	/// Verifies the MAC and address with the first password that opens the key.
+	fn check_secret(&mut self, path: &Path, declared: Option<Address>, crypto: &Crypto) {
+		let (mut address, passwords) = (declared, self.passwords);
+		if !passwords.is_empty() {
+			match passwords.iter().filter_map(|password| crypto.secret(password).ok()).next().map(KeyPair::from_secret) {
+				None => self.push(path, Severity::Warning, AnomalyKind::PasswordMismatch, "none of the passwords passes the MAC check".into()),
+				Some(Err(err)) => self.push(path, Severity::Error, AnomalyKind::Unreadable, format!("decrypted secret is not a valid key: {}", err)),
+				Some(Ok(keypair)) => {
+					if let Some(declared) = declared.filter(|declared| *declared != keypair.address()) {
+						self.push(path, Severity::Error, AnomalyKind::AddressMismatch,
+							format!("declares address 0x{:x} but holds the key of 0x{:x}", declared, keypair.address()));
+					}
+					address = Some(keypair.address());
+				},
+			}
+		}
+		if let Some(address) = address {
+			self.addresses.entry(address).or_insert_with(Vec::new).push(path.to_path_buf());
+		}
+	}
+
+	#[cfg(unix)]
+	fn check_permissions(&mut self, path: &Path, metadata: &fs::Metadata) {
+		use std::os::unix::fs::PermissionsExt;
+
+		let mode = metadata.permissions().mode() & 0o777;
+		if mode & 0o077 != 0 {
+			self.push(path, Severity::Warning, AnomalyKind::Permissions, format!("mode {:o} lets other users access the file, expected 600", mode));
+		}
+	}
+
+	#[cfg(not(unix))]
+	fn check_permissions(&mut self, _path: &Path, _metadata: &fs::Metadata) {}
+
+	fn push_duplicates(&mut self) {
+		let duplicates: Vec<_> = self.addresses.iter()
+			.filter(|&(_, paths)| paths.len() > 1)
+			.flat_map(|(address, paths)| paths.iter().map(move |path| {
+				let others: Vec<_> = paths.iter().filter(|other| *other != path).map(|other| other.display().to_string()).collect();
+				(path.clone(), format!("0x{:x} is also stored in {}", address, others.join(", ")))
+			}))
+			.collect();
+		for (path, message) in duplicates {
+			self.push(&path, Severity::Warning, AnomalyKind::DuplicateAddress, message);
+		}
+	}
+}
+
+/// Name of the vault that the directory `name` of `root` is a temporary copy of, made by
+/// `VaultKeyDirectory::set_key`.
+pub fn temp_vault_of(root: &Path, name: &str) -> Option<String> {
+	let mut parts = name.rsplitn(2, "_temp_");
+	match (parts.next(), parts.next()) {
+		(Some(index), Some(vault)) if !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()) && root.join(vault).join(VAULT_FILE_NAME).is_file() =>
+			Some(vault.to_owned()),
+		_ => None,
+	}
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	extern crate tempfile;
+
+	use std::collections::HashMap;
+	use std::fs;
+	use crypto::publickey::{Generator, Random};
+	use accounts_dir::{KeyDirectory, RootDiskDirectory, VaultKey, VaultKeyDirectoryProvider};
+	use account::{KdfParams, SafeAccount};
+	use super::{check, copy_synced, quarantine, remove, Anomaly, AnomalyKind, Severity};
+	use self::tempfile::TempDir;
+
+	const KDF: KdfParams = KdfParams::Pbkdf2 { iterations: 1024 };
+
+	#[test]
+	fn should_find_and_quarantine_anomalies() {
+		// given a directory with a good key, a copy of it, a key with a forged address and garbage
+		let root = TempDir::new().unwrap();
+		let directory = RootDiskDirectory::create(root.path()).unwrap();
+		let password = "password".into();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &password, KDF, "".into(), "{}".into()).unwrap();
+		let account = directory.insert(account).unwrap();
+		let good = root.path().join(account.filename.unwrap());
+		let copy = root.path().join("copy");
+		fs::copy(&good, &copy).unwrap();
+		let forged = root.path().join("forged");
+		let contents = fs::read_to_string(&good).unwrap().replace(&format!("{:x}", account.address), &format!("{:x}", Random.generate().address()));
+		fs::write(&forged, contents).unwrap();
+		let garbage = root.path().join("garbage");
+		fs::write(&garbage, "{}").unwrap();
+		let leftover = root.path().join(".copy.tmp");
+		fs::write(&leftover, "").unwrap();
+
+		// when
+		let anomalies = check(root.path(), &[password], &HashMap::new()).unwrap();
+
+		// then
+		let kinds = |path: &::std::path::Path| anomalies.iter().filter(|a| a.path == path).map(|a| a.kind).collect::<Vec<_>>();
+		assert!(kinds(&good).contains(&AnomalyKind::DuplicateAddress));
+		assert!(kinds(&copy).contains(&AnomalyKind::DuplicateAddress));
+		assert!(kinds(&forged).contains(&AnomalyKind::AddressMismatch));
+		assert_eq!(kinds(&garbage)[0], AnomalyKind::Unreadable);
+		#[cfg(unix)]
+		assert_eq!(kinds(&garbage)[1], AnomalyKind::Permissions);
+		assert_eq!(kinds(&leftover), vec![AnomalyKind::Leftover]);
+		assert!(anomalies.iter().filter(|a| a.severity == Severity::Error).all(|a| a.kind.is_quarantined()));
+
+		// when
+		let target = TempDir::new().unwrap();
+		let moved = quarantine(root.path(), &anomalies, target.path()).unwrap();
+
+		// then
+		assert_eq!(moved.len(), 3);
+		assert!(good.exists() && copy.exists());
+		assert!(!forged.exists() && !garbage.exists() && !leftover.exists());
+		assert!(target.path().join("forged").exists());
+	}
+
+	#[test]
+	fn should_check_vaults() {
+		// given
+		let root = TempDir::new().unwrap();
+		let directory = RootDiskDirectory::create(root.path()).unwrap();
+		let vault_password = "vault".into();
+		let vault = directory.create("vault", VaultKey::new(&vault_password, KDF)).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"password".into(), KDF, "".into(), "{}".into()).unwrap();
+		vault.insert(account).unwrap();
+		fs::create_dir(root.path().join("vault_temp_0")).unwrap();
+		fs::write(root.path().join("vault_temp_0").join("vault.json"), "{}").unwrap();
+
+		// when
+		let skipped = check(root.path(), &[], &HashMap::new()).unwrap();
+		let mut vault_passwords = HashMap::new();
+		vault_passwords.insert("vault".to_owned(), vault_password);
+		let checked = check(root.path(), &["other".into()], &vault_passwords).unwrap();
+
+		// then
+		let kinds = |anomalies: &[super::Anomaly]| anomalies.iter().map(|a| a.kind).collect::<Vec<_>>();
+		assert_eq!(kinds(&skipped), vec![AnomalyKind::VaultSkipped, AnomalyKind::Leftover]);
+		assert_eq!(kinds(&checked), vec![AnomalyKind::PasswordMismatch, AnomalyKind::Leftover]);
+	}
+
+	#[test]
+	fn should_return_moved_files_with_quarantine_error() {
+		// given a broken file in the root and one outside of it
+		let root = TempDir::new().unwrap();
+		let garbage = root.path().join("garbage");
+		fs::write(&garbage, "{}").unwrap();
+		let outside = TempDir::new().unwrap();
+		let anomaly = |path| Anomaly { path, severity: Severity::Error, kind: AnomalyKind::Unreadable, message: String::new() };
+		let anomalies = vec![anomaly(garbage.clone()), anomaly(outside.path().join("garbage"))];
+
+		// when
+		let target = TempDir::new().unwrap();
+		let err = quarantine(root.path(), &anomalies, target.path()).unwrap_err();
+
+		// then
+		assert_eq!(err.moved, vec![(garbage, target.path().join("garbage"))]);
+	}
+
+	#[test]
+	fn should_copy_and_remove_directories() {
+		// given
+		let root = TempDir::new().unwrap();
+		let from = root.path().join("vault_temp_0");
+		fs::create_dir(&from).unwrap();
+		fs::write(from.join("vault.json"), "{}").unwrap();
+		let to = root.path().join("copy");
+
+		// when
+		copy_synced(&from, &to).unwrap();
+		remove(&from).unwrap();
+
+		// then
+		assert!(!from.exists());
+		assert_eq!(fs::read_to_string(to.join("vault.json")).unwrap(), "{}");
+	}
+}
@@ -18,16 +18,17 @@
 
 use ethkey::Password;
 use std::path::{PathBuf};
-use {SafeAccount, Error};
+use {SafeAccount, Error, KdfParams};
 
 mod disk;
 mod memory;
 mod vault;
+pub mod fsck;
 
 /// `VaultKeyDirectory::set_key` error
 #[derive(Debug)]
 pub enum SetKeyError {
-	/// Error is fatal and directory is probably in inconsistent state
+	/// Error is fatal, the change is committed but the vault may be missing until it is recovered
 	Fatal(Error),
 	/// Error is non fatal, directory is reverted to pre-operation state
 	NonFatalOld(Error),
@@ -40,8 +41,8 @@ pub enum SetKeyError {
 pub struct VaultKey {
 	/// Vault password
 	pub password: Password,
-	/// Number of iterations to produce a derived key from password
-	pub iterations: u32,
+	/// Key derivation function to produce a derived key from password
+	pub kdf: KdfParams,
 }
 
 /// Keys directory
@@ -72,6 +73,12 @@ pub trait VaultKeyDirectoryProvider {
 	fn list_vaults(&self) -> Result<Vec<String>, Error>;
 	/// Get vault meta
 	fn vault_meta(&self, name: &str) -> Result<String, Error>;
+	/// Delete vault with given key, only if it holds no keys unless `force` is set
+	fn delete(&self, name: &str, key: VaultKey, force: bool) -> Result<(), Error>;
+	/// Rename vault with given key
+	fn rename(&self, name: &str, new_name: &str, key: VaultKey) -> Result<(), Error>;
+	/// Complete or roll back vault changes interrupted by a crash
+	fn recover(&self) -> Result<(), Error>;
 }
 
 /// Vault directory
@@ -90,16 +97,16 @@ pub trait VaultKeyDirectory: KeyDirectory {
 	fn set_meta(&self, meta: &str) -> Result<(), Error>;
 }
 
-pub use self::disk::{RootDiskDirectory, DiskKeyFileManager, KeyFileManager};
+pub use self::disk::{RootDiskDirectory, DiskKeyFileManager, KeyFileManager, write_file_atomically};
 pub use self::memory::MemoryDirectory;
-pub use self::vault::VaultDiskDirectory;
+pub use self::vault::{VaultDiskDirectory, VAULT_FILE_NAME, VAULT_TEMP_FILE_NAME};
 
 impl VaultKey {
 	/// Create new vault key
-	pub fn new(password: &Password, iterations: u32) -> Self {
+	pub fn new(password: &Password, kdf: KdfParams) -> Self {
 		VaultKey {
 			password: password.clone(),
-			iterations: iterations,
+			kdf: kdf,
 		}
 	}
 }
@@ -15,18 +15,30 @@
 // along with Open Ethereum.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::{fs, io};
+use std::io::Write;
 use std::path::{PathBuf, Path};
 use parking_lot::Mutex;
 use {json, SafeAccount, Error};
 use crypto::Keccak256;
+use rustc_hex::ToHex;
 use super::super::account::Crypto;
 use super::{KeyDirectory, VaultKeyDirectory, VaultKey, SetKeyError};
 use super::disk::{self, DiskDirectory, KeyFileManager};
 
 /// Name of vault metadata file
 pub const VAULT_FILE_NAME: &'static str = "vault.json";
-/// Name of temporary vault metadata file
+/// Name of temporary vault metadata file, as left by earlier versions
 pub const VAULT_TEMP_FILE_NAME: &'static str = "vault_temp.json";
+/// Suffix of the hidden copy of a vault that is re-encrypted with a new key
+const REKEY_SUFFIX: &'static str = "rekey";
+/// Suffix of the hidden vault directory replaced by its re-encrypted copy
+const REKEY_OLD_SUFFIX: &'static str = "rekey.old";
+/// Suffix of the journal file which commits a vault key change
+const REKEY_JOURNAL_SUFFIX: &'static str = "rekey.journal";
+/// Suffix of the hidden vault directory being deleted
+const DELETED_SUFFIX: &'static str = "deleted";
+/// Name of the file in the root directory locked while vault key changes and deletions are made or completed
+const VAULT_LOCK_FILE_NAME: &'static str = ".vaults.lock";
 
 /// Vault directory implementation
 pub type VaultDiskDirectory = DiskDirectory<VaultKeyFileManager>;
@@ -42,7 +54,7 @@ impl VaultDiskDirectory {
 	/// Create new vault directory with given key
 	pub fn create<P>(root: P, name: &str, key: VaultKey) -> Result<Self, Error> where P: AsRef<Path> {
 		// check that vault directory does not exists
-		let vault_dir_path = make_vault_dir_path(root, name, true)?;
+		let vault_dir_path = make_vault_dir_path(&root, name, true)?;
 		if vault_dir_path.exists() {
 			return Err(Error::CreationFailed);
 		}
@@ -50,7 +62,7 @@ impl VaultDiskDirectory {
 		// create vault && vault file
 		let vault_meta = "{}";
 		fs::create_dir_all(&vault_dir_path)?;
-		if let Err(err) = create_vault_file(&vault_dir_path, &key, vault_meta) {
+		if let Err(err) = create_vault_file(&vault_dir_path, &key, vault_meta).and_then(|_| disk::sync_dir(root.as_ref()).map_err(Into::into)) {
 			let _ = fs::remove_dir_all(&vault_dir_path); // can't do anything with this
 			return Err(err);
 		}
@@ -84,38 +96,98 @@ impl VaultDiskDirectory {
 		read_vault_file(&vault_dir_path, None)
 	}
 
-	fn create_temp_vault(&self, key: VaultKey) -> Result<VaultDiskDirectory, Error> {
-		let original_path = self.path().expect("self is instance of DiskDirectory; DiskDirectory always returns path; qed");
-		let mut path: PathBuf = original_path.clone();
-		let name = self.name();
+	/// Delete existing vault directory after checking the key. A vault that still holds key
+	/// files is only deleted with `force`.
+	pub fn delete_at<P>(root: P, name: &str, key: &VaultKey, force: bool) -> Result<(), Error> where P: AsRef<Path> {
+		let _lock = disk::lock_file_exclusive(root.as_ref(), VAULT_LOCK_FILE_NAME)?;
+		let vault_dir_path = existing_vault_dir_path(&root, name, key)?;
+		if !force && has_key_files(&vault_dir_path)? {
+			return Err(Error::VaultNotEmpty);
+		}
 
-		path.push(name); // to jump to the next level
+		// hide the vault first, so that it is not listed anymore if removal fails halfway
+		let hidden_name = disk::find_unique_filename_using_random_suffix(root.as_ref(), &format!(".{}.{}", name, DELETED_SUFFIX))?;
+		let hidden_path = root.as_ref().join(hidden_name);
+		fs::rename(&vault_dir_path, &hidden_path)?;
+		disk::sync_dir(root.as_ref())?;
+		fs::remove_dir_all(hidden_path).map_err(Into::into)
+	}
+
+	/// Rename existing vault directory after checking the key.
+	pub fn rename_at<P>(root: P, name: &str, new_name: &str, key: &VaultKey) -> Result<(), Error> where P: AsRef<Path> {
+		let vault_dir_path = existing_vault_dir_path(&root, name, key)?;
+		let new_vault_dir_path = make_vault_dir_path(&root, new_name, true)?;
+
+		// vault name is not stored in the vault files => moving the directory is enough
+		rename_dir_no_replace(&vault_dir_path, &new_vault_dir_path).map_err(|err| match err.kind() {
+			io::ErrorKind::AlreadyExists => Error::Custom(format!("Vault '{}' already exists", new_name)),
+			_ => err.into(),
+		})?;
+		disk::sync_dir(root.as_ref()).map_err(Into::into)
+	}
 
-		let mut index = 0;
-		loop {
-			let name = format!("{}_temp_{}", name, index);
-			path.set_file_name(&name);
-			if !path.exists() {
-				return VaultDiskDirectory::create(original_path, &name, key);
+	/// Complete the vault key changes under `root` interrupted after they were committed, and
+	/// remove what is left of the uncommitted ones and of interrupted deletions.
+	pub fn recover_at<P>(root: P) -> Result<(), Error> where P: AsRef<Path> {
+		let root = root.as_ref();
+		// a key directory without leftovers is not written to, so that it can be read only
+		if hidden_vault_entries(root)?.is_empty() {
+			return Ok(());
+		}
+
+		// entries of a change made by another process are only complete once it releases the lock
+		let _lock = disk::lock_file_exclusive(root, VAULT_LOCK_FILE_NAME)?;
+		for (name, suffix) in hidden_vault_entries(root)? {
+			if suffix == REKEY_JOURNAL_SUFFIX {
+				warn!("Completing interrupted key change of vault '{}'", name);
+				complete_rekey(&RekeyPaths::new(root, &name)).map_err(|err| match err {
+					SetKeyError::Fatal(err) | SetKeyError::NonFatalOld(err) | SetKeyError::NonFatalNew(err) => err,
+				})?;
 			}
+		}
 
-			index += 1;
+		// entries left without a journal are never part of a consistent vault
+		for (name, suffix) in hidden_vault_entries(root)? {
+			let path = root.join(format!(".{}.{}", name, suffix));
+			if suffix != REKEY_JOURNAL_SUFFIX && path.is_dir() {
+				warn!("Removing {} left by an interrupted change of vault '{}'", path.display(), name);
+				fs::remove_dir_all(&path)?;
+			}
 		}
+		Ok(())
 	}
 
-	fn copy_to_vault(&self, vault: &VaultDiskDirectory) -> Result<(), Error> {
-		for account in self.load()? {
+	/// Write a copy of the vault encrypted with `key` at `path`, and check that it reads back.
+	/// Fails if the vault holds anything besides its keys, which the copy would not carry over.
+	fn write_rekeyed_copy(&self, path: &Path, key: VaultKey) -> Result<(), Error> {
+		let accounts = self.load()?;
+		let vault_path = self.path().expect("self is instance of DiskDirectory; DiskDirectory always returns path; qed");
+		for entry in fs::read_dir(vault_path)? {
+			let file_name = entry?.file_name();
+			let file_name = file_name.to_string_lossy();
+			let is_copied = file_name == VAULT_FILE_NAME
+				|| accounts.iter().any(|account| account.filename.as_ref().map_or(false, |filename| *filename == file_name));
+			if !is_copied {
+				return Err(Error::Custom(format!("{} is not a key of vault '{}', move it out of the vault first", vault_path.join(&*file_name).display(), self.name())));
+			}
+		}
+
+		let meta = self.meta();
+		fs::create_dir(path)?;
+		create_vault_file(path, &key, &meta)?;
+
+		let copy = DiskDirectory::new(path, VaultKeyFileManager::new(self.name(), key, &meta));
+		let count = accounts.len();
+		for account in accounts {
 			let filename = account.filename.clone().expect("self is instance of DiskDirectory; DiskDirectory fills filename in load; qed");
-			vault.insert_with_filename(account, filename, true)?;
+			copy.insert_with_filename(account, filename, false)?;
 		}
 
+		if copy.load()?.len() != count {
+			return Err(Error::Custom("Re-encrypted vault keys could not be read back".into()));
+		}
 		Ok(())
 	}
-
-	fn delete(&self) -> Result<(), Error> {
-		let path = self.path().expect("self is instance of DiskDirectory; DiskDirectory always returns path; qed");
-		fs::remove_dir_all(path).map_err(Into::into)
-	}
 }
 
 impl VaultKeyDirectory for VaultDiskDirectory {
@@ -132,39 +204,31 @@ impl VaultKeyDirectory for VaultDiskDirectory {
 	}
 
 	fn set_key(&self, new_key: VaultKey) -> Result<(), SetKeyError> {
-		let temp_vault = VaultDiskDirectory::create_temp_vault(self, new_key.clone()).map_err(|err| SetKeyError::NonFatalOld(err))?;
-		let mut source_path = temp_vault.path().expect("temp_vault is instance of DiskDirectory; DiskDirectory always returns path; qed").clone();
-		let mut target_path = self.path().expect("self is instance of DiskDirectory; DiskDirectory always returns path; qed").clone();
-
-		// preserve meta
-		temp_vault.set_meta(&self.meta()).map_err(SetKeyError::NonFatalOld)?;
+		let vault_path = self.path().expect("self is instance of DiskDirectory; DiskDirectory always returns path; qed");
+		let root = vault_path.parent().expect("vault directory is created inside of the root directory; qed");
+		let paths = RekeyPaths::new(root, self.name());
+		let _lock = disk::lock_file_exclusive(root, VAULT_LOCK_FILE_NAME).map_err(|err| SetKeyError::NonFatalOld(err.into()))?;
 
-		// jump to next fs level
-		source_path.push("next");
-		target_path.push("next");
+		// the copy is left by a change that was never committed
+		if paths.new.exists() {
+			fs::remove_dir_all(&paths.new).map_err(|err| SetKeyError::NonFatalOld(err.into()))?;
+		}
 
-		let temp_accounts = self.copy_to_vault(&temp_vault)
-			.and_then(|_| temp_vault.load())
+		// re-encrypt the whole vault next to it; until the journal is written, a crash leaves the old vault
+		let journal_filename = file_name(&paths.journal);
+		self.write_rekeyed_copy(&paths.new, new_key)
+			.and_then(|_| vault_file_hash(&paths.new))
+			.and_then(|hash| disk::write_file_atomically(root, &journal_filename, false, |file| {
+				file.write_all(hash.as_bytes()).map_err(Into::into)
+			}))
 			.map_err(|err| {
-				// ignore error, as we already processing error
-				let _ = temp_vault.delete();
+				// ignore errors, as we are already processing one
+				let _ = fs::remove_file(&paths.journal);
+				let _ = fs::remove_dir_all(&paths.new);
 				SetKeyError::NonFatalOld(err)
 			})?;
 
-		// we can't just delete temp vault until all files moved, because
-		// original vault content has already been partially replaced
-		// => when error or crash happens here, we can't do anything
-		for temp_account in temp_accounts {
-			let filename = temp_account.filename.expect("self is instance of DiskDirectory; DiskDirectory fills filename in load; qed");
-			source_path.set_file_name(&filename);
-			target_path.set_file_name(&filename);
-			fs::rename(&source_path, &target_path).map_err(|err| SetKeyError::Fatal(err.into()))?;
-		}
-		source_path.set_file_name(VAULT_FILE_NAME);
-		target_path.set_file_name(VAULT_FILE_NAME);
-		fs::rename(source_path, target_path).map_err(|err| SetKeyError::Fatal(err.into()))?;
-
-		temp_vault.delete().map_err(|err| SetKeyError::NonFatalNew(err))
+		complete_rekey(&paths)
 	}
 
 	fn meta(&self) -> String {
@@ -204,7 +268,7 @@ impl KeyFileManager for VaultKeyFileManager {
 		account.meta = json::remove_vault_name_from_json_meta(&account.meta)
 			.map_err(|err| Error::Custom(format!("{:?}", err)))?;
 
-		let vault_file: json::VaultKeyFile = account.into_vault_file(self.key.iterations, &self.key.password)?;
+		let vault_file: json::VaultKeyFile = account.into_vault_file(self.key.kdf, &self.key.password)?;
 		vault_file.write(writer).map_err(|e| Error::Custom(format!("{:?}", e)))
 	}
 }
@@ -221,6 +285,50 @@ fn make_vault_dir_path<P>(root: P, name: &str, check_name: bool) -> Result<PathB
 	Ok(vault_dir_path)
 }
 
+/// Renames directory `from` to `to`, failing with `AlreadyExists` if `to` exists.
+#[cfg(unix)]
+fn rename_dir_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+	// rename replaces an empty directory => claim the name first, so that a directory made by
+	// anybody else is never replaced, and only the claimed one is
+	fs::create_dir(to)?;
+	fs::rename(from, to).map_err(|err| {
+		let _ = fs::remove_dir(to);
+		err
+	})
+}
+
+/// Renames directory `from` to `to`, failing if `to` exists.
+#[cfg(not(unix))]
+fn rename_dir_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+	// directories are never replaced by rename here
+	fs::rename(from, to)
+}
+
+/// Makes path to existing vault directory, checking that the key matches vault file
+fn existing_vault_dir_path<P>(root: P, name: &str, key: &VaultKey) -> Result<PathBuf, Error> where P: AsRef<Path> {
+	let vault_dir_path = make_vault_dir_path(root, name, true)?;
+	if !vault_dir_path.is_dir() {
+		return Err(Error::VaultNotFound);
+	}
+
+	read_vault_file(&vault_dir_path, Some(key))?;
+	Ok(vault_dir_path)
+}
+
+/// Checks if vault directory holds anything besides the vault file and leftovers of interrupted writes
+fn has_key_files(vault_dir_path: &Path) -> Result<bool, Error> {
+	for entry in fs::read_dir(vault_dir_path)? {
+		let entry = entry?;
+		let file_name = entry.file_name();
+		let name = file_name.to_string_lossy();
+		if !name.starts_with(".") && name != VAULT_FILE_NAME && !name.starts_with(VAULT_TEMP_FILE_NAME) {
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
 /// Every vault must have unique name => we rely on filesystem to check this
 /// => vault name must not contain any fs-special characters to avoid directory traversal
 /// => we only allow alphanumeric + separator characters in vault name.
@@ -235,28 +343,114 @@ fn check_vault_name(name: &str) -> bool {
 /// Vault can be empty, but still must be pluggable => we store vault password in separate file
 fn create_vault_file<P>(vault_dir_path: P, key: &VaultKey, meta: &str) -> Result<(), Error> where P: AsRef<Path> {
 	let password_hash = key.password.as_bytes().keccak256();
-	let crypto = Crypto::with_plain(&password_hash, &key.password, key.iterations)?;
+	let crypto = Crypto::with_plain(&password_hash, &key.password, key.kdf)?;
 
-	let vault_file_path = vault_dir_path.as_ref().join(VAULT_FILE_NAME);
-	let temp_vault_file_name = disk::find_unique_filename_using_random_suffix(vault_dir_path.as_ref(), &VAULT_TEMP_FILE_NAME)?;
-	let temp_vault_file_path = vault_dir_path.as_ref().join(&temp_vault_file_name);
-
-	// this method is used to rewrite existing vault file
-	// => write to temporary file first, then rename temporary file to vault file
-	let mut vault_file = disk::create_new_file_with_permissions_to_owner(&temp_vault_file_path)?;
 	let vault_file_contents = json::VaultFile {
 		crypto: crypto.into(),
 		meta: Some(meta.to_owned()),
 	};
-	vault_file_contents.write(&mut vault_file).map_err(|e| Error::Custom(format!("{:?}", e)))?;
-	drop(vault_file);
-	fs::rename(&temp_vault_file_path, &vault_file_path)?;
 
-	Ok(())
+	// this method is used to rewrite existing vault file
+	disk::write_file_atomically(vault_dir_path.as_ref(), VAULT_FILE_NAME, true, |file| {
+		vault_file_contents.write(file).map_err(|e| Error::Custom(format!("{:?}", e)))
+	})
+}
+
+/// Paths of the entries a vault key change keeps next to the vault directory
+struct RekeyPaths {
+	/// Vault directory
+	vault: PathBuf,
+	/// Copy of the vault encrypted with the new key
+	new: PathBuf,
+	/// Vault directory once replaced by the copy, until it is removed
+	old: PathBuf,
+	/// Journal file holding the hash of the copy's vault file; the key change is committed once it exists
+	journal: PathBuf,
+}
+
+impl RekeyPaths {
+	fn new(root: &Path, name: &str) -> Self {
+		RekeyPaths {
+			vault: root.join(name),
+			new: root.join(format!(".{}.{}", name, REKEY_SUFFIX)),
+			old: root.join(format!(".{}.{}", name, REKEY_OLD_SUFFIX)),
+			journal: root.join(format!(".{}.{}", name, REKEY_JOURNAL_SUFFIX)),
+		}
+	}
+}
+
+/// Replace the vault with its re-encrypted copy once the journal is written. Every step checks
+/// what is already done, so that a change interrupted at any point can be completed again.
+fn complete_rekey(paths: &RekeyPaths) -> Result<(), SetKeyError> {
+	let root = paths.journal.parent().expect("journal is created inside of the root directory; qed");
+	let swap = || -> Result<(), Error> {
+		if !paths.new.exists() {
+			// the copy is gone once it replaced the vault, otherwise the committed change is lost
+			let journal_hash = fs::read_to_string(&paths.journal)?;
+			if paths.vault.exists() && vault_file_hash(&paths.vault)? == journal_hash {
+				return Ok(());
+			}
+			return Err(Error::Custom(format!("Re-encrypted copy of vault '{}' is missing, remove {} to keep the vault with its current key",
+				file_name(&paths.vault), paths.journal.display())));
+		}
+
+		if paths.vault.exists() {
+			// the old directory is left by a change that failed to remove it
+			if paths.old.exists() {
+				fs::remove_dir_all(&paths.old)?;
+			}
+			fs::rename(&paths.vault, &paths.old)?;
+		}
+		fs::rename(&paths.new, &paths.vault)?;
+		disk::sync_dir(root).map_err(Into::into)
+	};
+	// the vault may be missing until the change is completed again
+	swap().map_err(SetKeyError::Fatal)?;
+
+	let cleanup = || -> Result<(), Error> {
+		fs::remove_file(&paths.journal)?;
+		disk::sync_dir(root)?;
+		if paths.old.exists() {
+			fs::remove_dir_all(&paths.old)?;
+		}
+		Ok(())
+	};
+	cleanup().map_err(SetKeyError::NonFatalNew)
+}
+
+/// Vault names and suffixes of the hidden entries left in `root` by vault key changes and deletions
+pub(super) fn hidden_vault_entries(root: &Path) -> Result<Vec<(String, String)>, Error> {
+	let mut entries = Vec::new();
+	for entry in fs::read_dir(root)? {
+		let file_name = entry?.file_name();
+		let file_name = file_name.to_string_lossy();
+		if !file_name.starts_with(".") {
+			continue;
+		}
+
+		// vault names never contain dots
+		let mut parts = file_name[1..].splitn(2, '.');
+		if let (Some(name), Some(suffix)) = (parts.next(), parts.next()) {
+			let is_vault_entry = suffix == REKEY_SUFFIX || suffix == REKEY_OLD_SUFFIX || suffix == REKEY_JOURNAL_SUFFIX || suffix.starts_with(DELETED_SUFFIX);
+			if is_vault_entry && check_vault_name(name) {
+				entries.push((name.to_owned(), suffix.to_owned()));
+			}
+		}
+	}
+	Ok(entries)
+}
+
+/// Hash of the vault file in `vault_dir_path`
+fn vault_file_hash(vault_dir_path: &Path) -> Result<String, Error> {
+	Ok(fs::read(vault_dir_path.join(VAULT_FILE_NAME))?.keccak256().to_hex())
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
 }
 
 /// When vault is opened => we must check that password matches && read metadata
-fn read_vault_file<P>(vault_dir_path: P, key: Option<&VaultKey>) -> Result<String, Error> where P: AsRef<Path> {
+pub(super) fn read_vault_file<P>(vault_dir_path: P, key: Option<&VaultKey>) -> Result<String, Error> where P: AsRef<Path> {
 	let mut vault_file_path: PathBuf = vault_dir_path.as_ref().into();
 	vault_file_path.push(VAULT_FILE_NAME);
 
@@ -283,8 +477,11 @@ mod test {
 	use std::fs;
 	use std::io::Write;
 	use std::path::PathBuf;
+	use crypto::publickey::{Generator, Random};
 	use super::VaultKey;
-	use super::{VAULT_FILE_NAME, check_vault_name, make_vault_dir_path, create_vault_file, read_vault_file, VaultDiskDirectory};
+	use super::super::{KeyDirectory, VaultKeyDirectory, SetKeyError};
+	use {Error, KdfParams, SafeAccount};
+	use super::{VAULT_FILE_NAME, VAULT_LOCK_FILE_NAME, check_vault_name, make_vault_dir_path, create_vault_file, read_vault_file, hidden_vault_entries, vault_file_hash, RekeyPaths, VaultDiskDirectory};
 	use self::tempfile::TempDir;
 
 	#[test]
@@ -325,7 +522,7 @@ mod test {
 	fn create_vault_file_succeeds() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password".into(), 1024);
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let mut vault_dir: PathBuf = temp_path.path().into();
 		vault_dir.push("vault");
 		fs::create_dir_all(&vault_dir).unwrap();
@@ -344,7 +541,7 @@ mod test {
 	fn read_vault_file_succeeds() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password".into(), 1024);
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let vault_file_contents = r#"{"crypto":{"cipher":"aes-128-ctr","cipherparams":{"iv":"758696c8dc6378ab9b25bb42790da2f5"},"ciphertext":"54eb50683717d41caaeb12ea969f2c159daada5907383f26f327606a37dc7168","kdf":"pbkdf2","kdfparams":{"c":1024,"dklen":32,"prf":"hmac-sha256","salt":"3c320fa566a1a7963ac8df68a19548d27c8f40bf92ef87c84594dcd5bbc402b6"},"mac":"9e5c2314c2a0781962db85611417c614bd6756666b6b1e93840f5b6ed895f003"}}"#;
 		let dir: PathBuf = temp_path.path().into();
 		let mut vault_file_path: PathBuf = dir.clone();
@@ -365,7 +562,7 @@ mod test {
 	fn read_vault_file_fails() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password1".into(), 1024);
+		let key = VaultKey::new(&"password1".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let dir: PathBuf = temp_path.path().into();
 		let mut vault_file_path: PathBuf = dir.clone();
 		vault_file_path.push(VAULT_FILE_NAME);
@@ -394,7 +591,7 @@ mod test {
 	fn vault_directory_can_be_created() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password".into(), 1024);
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let dir: PathBuf = temp_path.path().into();
 
 		// when
@@ -414,7 +611,7 @@ mod test {
 	fn vault_directory_cannot_be_created_if_already_exists() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password".into(), 1024);
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let dir: PathBuf = temp_path.path().into();
 		let mut vault_dir = dir.clone();
 		vault_dir.push("vault");
@@ -427,11 +624,165 @@ mod test {
 		assert!(vault.is_err());
 	}
 
+	#[test]
+	fn vault_directory_can_be_renamed() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let dir: PathBuf = temp_path.path().into();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"account".into(), key.kdf, "".into(), "{}".into()).unwrap();
+		vault.insert(account.clone()).unwrap();
+		VaultDiskDirectory::create(&dir, "other", key.clone()).unwrap();
+		fs::create_dir(dir.join("empty")).unwrap();
+
+		// when
+		let wrong_key = VaultDiskDirectory::rename_at(&dir, "vault", "renamed", &VaultKey::new(&"wrong".into(), key.kdf));
+		let existing = VaultDiskDirectory::rename_at(&dir, "vault", "other", &key);
+		let existing_empty = VaultDiskDirectory::rename_at(&dir, "vault", "empty", &key);
+		let renamed = VaultDiskDirectory::rename_at(&dir, "vault", "renamed", &key);
+
+		// then
+		assert_matches!(wrong_key, Err(Error::InvalidPassword));
+		assert!(existing.is_err());
+		assert!(existing_empty.is_err());
+		assert_eq!(fs::read_dir(dir.join("empty")).unwrap().count(), 0);
+		assert!(renamed.is_ok());
+		let vault = VaultDiskDirectory::at(&dir, "renamed", key.clone()).unwrap();
+		assert_eq!(vault.load().unwrap()[0].address, account.address);
+		assert!(VaultDiskDirectory::at(&dir, "vault", key).is_err());
+	}
+
+	#[test]
+	fn vault_directory_is_deleted_only_when_empty_or_forced() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let dir: PathBuf = temp_path.path().into();
+		VaultDiskDirectory::create(&dir, "empty", key.clone()).unwrap();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"account".into(), key.kdf, "".into(), "{}".into()).unwrap();
+		vault.insert(account).unwrap();
+
+		// when
+		let missing = VaultDiskDirectory::delete_at(&dir, "missing", &key, false);
+		let wrong_key = VaultDiskDirectory::delete_at(&dir, "empty", &VaultKey::new(&"wrong".into(), key.kdf), false);
+		let not_empty = VaultDiskDirectory::delete_at(&dir, "vault", &key, false);
+
+		// then
+		assert_matches!(missing, Err(Error::VaultNotFound));
+		assert_matches!(wrong_key, Err(Error::InvalidPassword));
+		assert_matches!(not_empty, Err(Error::VaultNotEmpty));
+
+		// and when
+		VaultDiskDirectory::delete_at(&dir, "empty", &key, false).unwrap();
+		VaultDiskDirectory::delete_at(&dir, "vault", &key, true).unwrap();
+
+		// then
+		let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+		assert_eq!(entries, vec![VAULT_LOCK_FILE_NAME]);
+	}
+
+	#[test]
+	fn interrupted_key_change_is_rolled_back_before_commit() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let new_key = VaultKey::new(&"new_password".into(), key.kdf);
+		let dir: PathBuf = temp_path.path().into();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"account".into(), key.kdf, "".into(), "{}".into()).unwrap();
+		vault.insert(account.clone()).unwrap();
+		let paths = RekeyPaths::new(&dir, "vault");
+		vault.write_rekeyed_copy(&paths.new, new_key.clone()).unwrap();
+
+		// when
+		VaultDiskDirectory::recover_at(&dir).unwrap();
+
+		// then
+		assert!(hidden_vault_entries(&dir).unwrap().is_empty());
+		assert!(VaultDiskDirectory::at(&dir, "vault", new_key).is_err());
+		let vault = VaultDiskDirectory::at(&dir, "vault", key).unwrap();
+		assert_eq!(vault.load().unwrap()[0].address, account.address);
+	}
+
+	#[test]
+	fn interrupted_key_change_is_completed_after_commit() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let new_key = VaultKey::new(&"new_password".into(), key.kdf);
+		let dir: PathBuf = temp_path.path().into();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"account".into(), key.kdf, "".into(), "{}".into()).unwrap();
+		vault.insert(account.clone()).unwrap();
+		let paths = RekeyPaths::new(&dir, "vault");
+		vault.write_rekeyed_copy(&paths.new, new_key.clone()).unwrap();
+		fs::write(&paths.journal, vault_file_hash(&paths.new).unwrap()).unwrap();
+		// crash between moving the old vault away and moving the copy in
+		fs::rename(&paths.vault, &paths.old).unwrap();
+
+		// when
+		VaultDiskDirectory::recover_at(&dir).unwrap();
+
+		// then
+		assert!(hidden_vault_entries(&dir).unwrap().is_empty());
+		assert!(VaultDiskDirectory::at(&dir, "vault", key).is_err());
+		let vault = VaultDiskDirectory::at(&dir, "vault", new_key).unwrap();
+		assert_eq!(vault.load().unwrap()[0].address, account.address);
+	}
+
+	#[test]
+	fn committed_key_change_without_copy_is_not_completed() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let new_key = VaultKey::new(&"new_password".into(), key.kdf);
+		let dir: PathBuf = temp_path.path().into();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let paths = RekeyPaths::new(&dir, "vault");
+		vault.write_rekeyed_copy(&paths.new, new_key.clone()).unwrap();
+		fs::write(&paths.journal, vault_file_hash(&paths.new).unwrap()).unwrap();
+		// the copy is removed by anybody but the key change
+		fs::remove_dir_all(&paths.new).unwrap();
+
+		// when
+		let result = VaultDiskDirectory::recover_at(&dir);
+
+		// then
+		assert!(result.is_err());
+		assert!(paths.journal.exists());
+		assert!(VaultDiskDirectory::at(&dir, "vault", key).is_ok());
+	}
+
+	#[test]
+	fn vault_key_change_keeps_files_that_are_not_keys() {
+		// given
+		let temp_path = TempDir::new().unwrap();
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
+		let dir: PathBuf = temp_path.path().into();
+		let vault = VaultDiskDirectory::create(&dir, "vault", key.clone()).unwrap();
+		let account = SafeAccount::create(&Random.generate(), [0u8; 16], &"account".into(), key.kdf, "".into(), "{}".into()).unwrap();
+		vault.insert(account.clone()).unwrap();
+		let corrupt_path = dir.join("vault").join("corrupt.json");
+		fs::write(&corrupt_path, "{\"not\": \"a key\"}").unwrap();
+
+		// when
+		let result = vault.set_key(VaultKey::new(&"new_password".into(), key.kdf));
+
+		// then
+		assert_matches!(result, Err(SetKeyError::NonFatalOld(_)));
+		assert_eq!(fs::read_to_string(&corrupt_path).unwrap(), "{\"not\": \"a key\"}");
+		assert!(hidden_vault_entries(&dir).unwrap().is_empty());
+		let vault = VaultDiskDirectory::at(&dir, "vault", key).unwrap();
+		assert_eq!(vault.load().unwrap()[0].address, account.address);
+	}
+
 	#[test]
 	fn vault_directory_cannot_be_opened_if_not_exists() {
 		// given
 		let temp_path = TempDir::new().unwrap();
-		let key = VaultKey::new(&"password".into(), 1024);
+		let key = VaultKey::new(&"password".into(), KdfParams::Pbkdf2 { iterations: 1024 });
 		let dir: PathBuf = temp_path.path().into();
 
 		// when
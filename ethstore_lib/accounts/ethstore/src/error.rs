@@ -44,6 +44,8 @@ pub enum Error {
 	InvalidVaultName,
 	/// Vault not found
 	VaultNotFound,
+	/// Vault still holds keys
+	VaultNotEmpty,
 	/// Account creation failed.
 	CreationFailed,
 	/// `EthCrypto` error
@@ -70,6 +72,7 @@ impl fmt::Display for Error {
 			Error::UnsupportedVault => "Vault is not supported for this operation".into(),
 			Error::InvalidVaultName => "Invalid vault name".into(),
 			Error::VaultNotFound => "Vault not found".into(),
+			Error::VaultNotEmpty => "Vault is not empty".into(),
 			Error::CreationFailed => "Account creation failed".into(),
 			Error::EthCrypto(ref err) => err.to_string(),
 			Error::EthPublicKeyCrypto(ref err) => err.to_string(),
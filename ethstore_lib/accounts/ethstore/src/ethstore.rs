@@ -18,13 +18,13 @@ use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use parking_lot::{Mutex, RwLock};
 use std::time::{Instant, Duration};
+use time;
 
-use crypto::KEY_ITERATIONS;
 use random::Random;
 use crypto::publickey::{Signature, Address, Message, Secret, Public, KeyPair, ExtendedKeyPair};
 use ethkey::Password;
 use accounts_dir::{KeyDirectory, VaultKeyDirectory, VaultKey, SetKeyError};
-use account::SafeAccount;
+use account::{KdfParams, SafeAccount};
 use presale::PresaleWallet;
 use json::{self, Uuid, OpaqueKeyFile};
 use {import, Error, SimpleSecretStore, SecretStore, SecretVaultRef, StoreAccountRef, Derivation, OpaqueSecret};
@@ -37,13 +37,18 @@ pub struct EthStore {
 impl EthStore {
 	/// Open a new accounts store with given key directory backend.
 	pub fn open(directory: Box<dyn KeyDirectory>) -> Result<Self, Error> {
-		Self::open_with_iterations(directory, KEY_ITERATIONS as u32)
+		Self::open_with_kdf(directory, KdfParams::default())
 	}
 
-	/// Open a new account store with given key directory backend and custom number of iterations.
+	/// Open a new account store with given key directory backend and custom number of PBKDF2 iterations.
 	pub fn open_with_iterations(directory: Box<dyn KeyDirectory>, iterations: u32) -> Result<Self, Error> {
+		Self::open_with_kdf(directory, KdfParams::Pbkdf2 { iterations })
+	}
+
+	/// Open a new account store with given key directory backend and key derivation function for new keys.
+	pub fn open_with_kdf(directory: Box<dyn KeyDirectory>, kdf: KdfParams) -> Result<Self, Error> {
 		Ok(EthStore {
-			store: EthMultiStore::open_with_iterations(directory, iterations)?,
+			store: EthMultiStore::open_with_kdf(directory, kdf)?,
 		})
 	}
 
@@ -57,6 +62,23 @@ impl EthStore {
 		self.store.set_refresh_time(time)
 	}
 
+	/// Key derivation function used for new and re-encrypted keys.
+	pub fn kdf(&self) -> KdfParams {
+		self.store.kdf()
+	}
+
+	/// Key derivation function the account's key is currently encrypted with.
+	pub fn account_kdf(&self, account: &StoreAccountRef) -> Result<KdfParams, Error> {
+		Ok(KdfParams::from(&self.get(account)?.crypto.kdf))
+	}
+
+	/// Writes the account to `dst` as a standard key file named the way geth names them and
+	/// returns the file name. With `new_password` the copy is re-encrypted with it using the
+	/// store's key derivation function.
+	pub fn export_account_to(&self, account: &StoreAccountRef, password: &Password, new_password: Option<&Password>, dst: &dyn KeyDirectory) -> Result<String, Error> {
+		self.store.export_account_to(account, password, new_password, dst)
+	}
+
 	fn get(&self, account: &StoreAccountRef) -> Result<SafeAccount, Error> {
 		let mut accounts = self.store.get_accounts(account)?.into_iter();
 		accounts.next().ok_or(Error::InvalidAccount)
@@ -141,6 +163,14 @@ impl SimpleSecretStore for EthStore {
 		self.store.change_vault_password(name, new_password)
 	}
 
+	fn delete_vault(&self, name: &str, password: &Password, force: bool) -> Result<(), Error> {
+		self.store.delete_vault(name, password, force)
+	}
+
+	fn rename_vault(&self, name: &str, new_name: &str, password: &Password) -> Result<(), Error> {
+		self.store.rename_vault(name, new_name, password)
+	}
+
 	fn change_account_vault(&self, vault: SecretVaultRef, account: StoreAccountRef) -> Result<StoreAccountRef, Error> {
 		self.store.change_account_vault(vault, account)
 	}
@@ -257,7 +287,7 @@ impl SecretStore for EthStore {
 /// Similar to `EthStore` but may store many accounts (with different passwords) for the same `Address`
 pub struct EthMultiStore {
 	dir: Box<dyn KeyDirectory>,
-	iterations: u32,
+	kdf: KdfParams,
 	// order lock: cache, then vaults
 	cache: RwLock<BTreeMap<StoreAccountRef, Vec<SafeAccount>>>,
 	vaults: Mutex<HashMap<String, Box<dyn VaultKeyDirectory>>>,
@@ -273,15 +303,21 @@ struct Timestamp {
 impl EthMultiStore {
 	/// Open new multi-accounts store with given key directory backend.
 	pub fn open(directory: Box<dyn KeyDirectory>) -> Result<Self, Error> {
-		Self::open_with_iterations(directory, KEY_ITERATIONS as u32)
+		Self::open_with_kdf(directory, KdfParams::default())
 	}
 
-	/// Open new multi-accounts store with given key directory backend and custom number of iterations for new keys.
+	/// Open new multi-accounts store with given key directory backend and custom number of PBKDF2 iterations for new keys.
 	pub fn open_with_iterations(directory: Box<dyn KeyDirectory>, iterations: u32) -> Result<Self, Error> {
+		Self::open_with_kdf(directory, KdfParams::Pbkdf2 { iterations })
+	}
+
+	/// Open new multi-accounts store with given key directory backend and key derivation function for new keys.
+	pub fn open_with_kdf(directory: Box<dyn KeyDirectory>, kdf: KdfParams) -> Result<Self, Error> {
+		kdf.validate().map_err(Error::Custom)?;
 		let store = EthMultiStore {
 			dir: directory,
 			vaults: Mutex::new(HashMap::new()),
-			iterations: iterations,
+			kdf: kdf,
 			cache: Default::default(),
 			timestamp: Mutex::new(Timestamp {
 				dir_hash: None,
@@ -290,6 +326,10 @@ impl EthMultiStore {
 				refresh_time: Duration::from_secs(u64::max_value()),
 			}),
 		};
+		// finish vault changes interrupted by a crash before any key is read
+		if let Some(vault_provider) = store.dir.as_vault_provider() {
+			vault_provider.recover()?;
+		}
 		store.reload_accounts()?;
 		Ok(store)
 	}
@@ -304,6 +344,27 @@ impl EthMultiStore {
 		self.timestamp.lock().refresh_time = time;
 	}
 
+	/// Key derivation function used for new and re-encrypted keys.
+	pub fn kdf(&self) -> KdfParams {
+		self.kdf
+	}
+
+	/// Writes the account to `dst` as a standard key file named the way geth names them and
+	/// returns the file name. With `new_password` the copy is re-encrypted with it using the
+	/// store's key derivation function.
+	pub fn export_account_to(&self, account_ref: &StoreAccountRef, password: &Password, new_password: Option<&Password>, dst: &dyn KeyDirectory) -> Result<String, Error> {
+		let account = self.get_matching(account_ref, password)?.into_iter().nth(0).ok_or(Error::InvalidPassword)?;
+		let mut account = match new_password {
+			Some(new_password) => account.change_password(password, new_password, self.kdf)?,
+			None => account,
+		};
+		let timestamp = time::strftime("%Y-%m-%dT%H-%M-%S", &time::now_utc()).expect("Time-format string is valid.");
+		let filename = format!("UTC--{}Z--{:x}", timestamp, account.address);
+		account.filename = Some(filename.clone());
+		// the directory may give the file another name if this one is taken
+		Ok(dst.insert(account)?.filename.unwrap_or(filename))
+	}
+
 	fn reload_if_changed(&self) -> Result<(), Error> {
 		let mut last_timestamp = self.timestamp.lock();
 		let now = Instant::now();
@@ -458,7 +519,7 @@ impl SimpleSecretStore for EthMultiStore {
 	fn insert_account(&self, vault: SecretVaultRef, secret: Secret, password: &Password) -> Result<StoreAccountRef, Error> {
 		let keypair = KeyPair::from_secret(secret).map_err(|_| Error::CreationFailed)?;
 		let id: [u8; 16] = Random::random();
-		let account = SafeAccount::create(&keypair, id, password, self.iterations, "".to_owned(), "{}".to_owned())?;
+		let account = SafeAccount::create(&keypair, id, password, self.kdf, "".to_owned(), "{}".to_owned())?;
 		self.import(vault, account)
 	}
 
@@ -537,7 +598,7 @@ impl SimpleSecretStore for EthMultiStore {
 
 		for account in accounts {
 			// Change password
-			let new_account = account.change_password(old_password, new_password, self.iterations)?;
+			let new_account = account.change_password(old_password, new_password, self.kdf)?;
 			self.update(account_ref, account, new_account)?;
 		}
 
@@ -577,7 +638,7 @@ impl SimpleSecretStore for EthMultiStore {
 			let mut vaults = self.vaults.lock();
 			if !vaults.contains_key(&name.to_owned()) {
 				let vault_provider = self.dir.as_vault_provider().ok_or(Error::VaultsAreNotSupported)?;
-				let vault = vault_provider.create(name, VaultKey::new(password, self.iterations))?;
+				let vault = vault_provider.create(name, VaultKey::new(password, self.kdf))?;
 				vaults.insert(name.to_owned(), vault);
 				true
 			} else {
@@ -597,7 +658,7 @@ impl SimpleSecretStore for EthMultiStore {
 			let mut vaults = self.vaults.lock();
 			if !vaults.contains_key(&name.to_owned()) {
 				let vault_provider = self.dir.as_vault_provider().ok_or(Error::VaultsAreNotSupported)?;
-				let vault = vault_provider.open(name, VaultKey::new(password, self.iterations))?;
+				let vault = vault_provider.open(name, VaultKey::new(password, self.kdf))?;
 				vaults.insert(name.to_owned(), vault);
 				true
 			} else {
@@ -633,13 +694,17 @@ impl SimpleSecretStore for EthMultiStore {
 		let old_key = self.vaults.lock().get(name).map(|v| v.key()).ok_or(Error::VaultNotFound)?;
 		let vault_provider = self.dir.as_vault_provider().ok_or(Error::VaultsAreNotSupported)?;
 		let vault = vault_provider.open(name, old_key)?;
-		match vault.set_key(VaultKey::new(new_password, self.iterations)) {
+		match vault.set_key(VaultKey::new(new_password, self.kdf)) {
 			Ok(_) => {
 				self.close_vault(name)
 					.and_then(|_| self.open_vault(name, new_password))
 			},
 			Err(SetKeyError::Fatal(err)) => {
 				let _ = self.close_vault(name);
+				// the change is committed => try to complete it now rather than on next open
+				if vault_provider.recover().is_ok() {
+					let _ = self.open_vault(name, new_password);
+				}
 				Err(err)
 			},
 			Err(SetKeyError::NonFatalNew(err)) => {
@@ -651,6 +716,23 @@ impl SimpleSecretStore for EthMultiStore {
 		}
 	}
 
+	fn delete_vault(&self, name: &str, password: &Password, force: bool) -> Result<(), Error> {
+		let vault_provider = self.dir.as_vault_provider().ok_or(Error::VaultsAreNotSupported)?;
+		vault_provider.delete(name, VaultKey::new(password, self.kdf), force)?;
+		self.close_vault(name)
+	}
+
+	fn rename_vault(&self, name: &str, new_name: &str, password: &Password) -> Result<(), Error> {
+		let vault_provider = self.dir.as_vault_provider().ok_or(Error::VaultsAreNotSupported)?;
+		let is_vault_opened = self.vaults.lock().contains_key(name);
+		vault_provider.rename(name, new_name, VaultKey::new(password, self.kdf))?;
+		self.close_vault(name)?;
+		if is_vault_opened {
+			self.open_vault(new_name, password)?;
+		}
+		Ok(())
+	}
+
 	fn change_account_vault(&self, vault: SecretVaultRef, account_ref: StoreAccountRef) -> Result<StoreAccountRef, Error> {
 		if account_ref.vault == vault {
 			return Ok(account_ref);
@@ -690,10 +772,12 @@ mod tests {
 	extern crate tempfile;
 
 	use accounts_dir::{KeyDirectory, MemoryDirectory, RootDiskDirectory};
+	use account::{Kdf, KdfParams};
 	use crypto::publickey::{Random, Generator, KeyPair};
 	use secret_store::{SimpleSecretStore, SecretStore, SecretVaultRef, StoreAccountRef, Derivation};
 	use super::{EthStore, EthMultiStore};
 	use self::tempfile::TempDir;
+	use Error;
 	use ethereum_types::H256;
 
 	fn keypair() -> KeyPair {
@@ -1043,6 +1127,45 @@ mod tests {
 		assert!(opened_vaults.iter().any(|v| &*v == name3));
 	}
 
+	#[test]
+	fn should_rename_and_delete_vaults() {
+		// given
+		let mut dir = RootDiskDirectoryGuard::new();
+		let store = EthStore::open(dir.key_dir.take().unwrap()).unwrap();
+		let password = "password".into();
+		store.create_vault("vault", &password).unwrap();
+		store.create_vault("empty", &password).unwrap();
+		let account = store.insert_account(SecretVaultRef::Vault("vault".into()), keypair().secret().clone(), &"account".into()).unwrap();
+
+		// when
+		store.rename_vault("vault", "renamed", &password).unwrap();
+
+		// then
+		let mut vaults = store.list_vaults().unwrap();
+		vaults.sort();
+		assert_eq!(vaults, vec!["empty".to_owned(), "renamed".to_owned()]);
+		let opened_vaults = store.list_opened_vaults().unwrap();
+		assert!(opened_vaults.iter().any(|v| &*v == "renamed"));
+		assert!(!opened_vaults.iter().any(|v| &*v == "vault"));
+		assert_eq!(store.accounts().unwrap(), vec![StoreAccountRef::vault("renamed", account.address)]);
+
+		// when
+		let not_empty = store.delete_vault("renamed", &password, false);
+		store.delete_vault("empty", &password, false).unwrap();
+
+		// then
+		assert_matches!(not_empty, Err(Error::VaultNotEmpty));
+		assert_eq!(store.list_vaults().unwrap(), vec!["renamed".to_owned()]);
+
+		// and when
+		store.delete_vault("renamed", &password, true).unwrap();
+
+		// then
+		assert!(store.list_vaults().unwrap().is_empty());
+		assert!(store.list_opened_vaults().unwrap().is_empty());
+		assert!(store.accounts().unwrap().is_empty());
+	}
+
 	#[test]
 	fn should_manage_vaults_meta() {
 		// given
@@ -1097,6 +1220,27 @@ mod tests {
 		assert!(store.sign(&derived, &"test".into(), &message).is_ok(), "Second password should work for second store.");
 	}
 
+	#[test]
+	fn should_encrypt_new_keys_with_store_kdf() {
+		// given a store writing keys with scrypt
+		let kdf = KdfParams::Scrypt { n: 1024, r: 8, p: 1, dklen: 32 };
+		let store = EthStore::open_with_kdf(Box::new(MemoryDirectory::default()), kdf).unwrap();
+
+		// when we insert an account and change its password
+		let account = store.insert_account(SecretVaultRef::Root, keypair().secret().clone(), &"test".into()).unwrap();
+		assert_matches!(store.get(&account).unwrap().crypto.kdf, Kdf::Scrypt(_));
+		assert_eq!(store.account_kdf(&account).unwrap(), kdf);
+		store.change_password(&account, &"test".into(), &"other".into()).unwrap();
+
+		// then the key stays encrypted with scrypt
+		assert_matches!(store.get(&account).unwrap().crypto.kdf, Kdf::Scrypt(_));
+		assert!(store.sign(&account, &"other".into(), &[1u8; 32].into()).is_ok());
+
+		// and invalid parameters are rejected
+		let kdf = KdfParams::Scrypt { n: 1000, r: 8, p: 1, dklen: 32 };
+		assert!(EthStore::open_with_kdf(Box::new(MemoryDirectory::default()), kdf).is_err());
+	}
+
 	#[test]
 	fn should_save_meta_when_setting_before_password() {
 		// given
@@ -1114,6 +1258,27 @@ mod tests {
 		assert_eq!(store.get_vault_meta(name).unwrap(), "OldMeta".to_owned());
 	}
 
+	#[test]
+	fn should_export_account_to_key_file() {
+		// given
+		let store = store();
+		let keypair = keypair();
+		let account = store.insert_account(SecretVaultRef::Root, keypair.secret().clone(), &"test".into()).unwrap();
+		let dir = TempDir::new().unwrap();
+		let dst = RootDiskDirectory::create(dir.path()).unwrap();
+
+		// when
+		let filename = store.export_account_to(&account, &"test".into(), Some(&"export".into()), &dst).unwrap();
+
+		// then
+		assert!(filename.starts_with("UTC--") && filename.ends_with(&format!("Z--{:x}", keypair.address())), "{}", filename);
+		let loaded = RootDiskDirectory::at(dir.path()).load().unwrap();
+		assert_eq!(loaded.len(), 1);
+		assert_eq!(loaded[0].address, keypair.address());
+		assert!(loaded[0].crypto.secret(&"export".into()).is_ok());
+		assert!(store.test_password(&account, &"test".into()).unwrap());
+	}
+
 	#[test]
 	fn should_export_account() {
 		// given
@@ -56,7 +56,7 @@ mod presale;
 mod random;
 mod secret_store;
 
-pub use self::account::{SafeAccount, Crypto};
+pub use self::account::{SafeAccount, Crypto, KdfParams};
 pub use self::error::Error;
 pub use self::ethstore::{EthStore, EthMultiStore};
 pub use self::import::{import_account, import_accounts, read_geth_accounts};
@@ -72,6 +72,13 @@ pub use self::parity_wordlist::random_phrase;
 /// An opaque wrapper for secret.
 pub struct OpaqueSecret(crypto::publickey::Secret);
 
+impl OpaqueSecret {
+	/// Raw bytes of the secret, for backup schemes that have to process it outside the store.
+	pub fn as_bytes(&self) -> &[u8] {
+		self.0.as_bytes()
+	}
+}
+
 // Additional converters for Address
 use crypto::publickey::Address;
 
@@ -95,6 +95,10 @@ pub trait SimpleSecretStore: Send + Sync {
 	fn list_opened_vaults(&self) -> Result<Vec<String>, Error>;
 	/// Change vault password
 	fn change_vault_password(&self, name: &str, new_password: &Password) -> Result<(), Error>;
+	/// Delete vault with given password, only if it holds no keys unless `force` is set
+	fn delete_vault(&self, name: &str, password: &Password, force: bool) -> Result<(), Error>;
+	/// Rename vault with given password, keeping it opened if it was
+	fn rename_vault(&self, name: &str, new_name: &str, password: &Password) -> Result<(), Error>;
 	/// Cnage account' vault
 	fn change_account_vault(&self, vault: SecretVaultRef, account: StoreAccountRef) -> Result<StoreAccountRef, Error>;
 	/// Get vault metadata string.
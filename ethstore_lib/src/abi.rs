@@ -0,0 +1,173 @@
+//! Solidity JSON ABI encoding and decoding for contract commands.
+
+use std::fs;
+
+use ethabi::token::{LenientTokenizer, Tokenizer};
+use ethabi::{ParamType, RawLog, Token};
+use ethereum_types::U256;
+
+pub use ethabi::{Contract, Function};
+
+use crate::ethstore_rely::Error;
+use crate::evm::Log;
+
+/// Decoded value together with its ABI name and type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub name: String,
+    pub kind: String,
+    pub token: Token,
+}
+
+/// Log decoded with one of the contract events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub name: String,
+    pub params: Vec<Value>,
+}
+
+fn abi_error(err: ethabi::Error) -> Error {
+    Error::InvalidInput(format!("ABI: {}", err))
+}
+
+/// Loads a Solidity JSON ABI file.
+pub fn load(path: &str) -> Result<Contract, Error> {
+    let file = fs::File::open(path)?;
+    Contract::load(file).map_err(abi_error)
+}
+
+fn tokenize(kinds: &[ParamType], args: &[String]) -> Result<Vec<Token>, Error> {
+    if kinds.len() != args.len() {
+        return Err(Error::InvalidInput(format!("expected {} arguments, got {}", kinds.len(), args.len())));
+    }
+    kinds.iter()
+        .zip(args)
+        .map(|(kind, arg)| {
+            let value = match *kind {
+                ParamType::String => arg.as_str(),
+                _ => arg.trim_start_matches("0x"),
+            };
+            LenientTokenizer::tokenize(kind, value)
+                .map_err(|e| Error::InvalidInput(format!("argument '{}' is not a valid {}: {}", arg, kind, e)))
+        })
+        .collect()
+}
+
+/// Finds the function by name, picking the overload that takes `arg_count` arguments.
+pub fn function<'a>(contract: &'a Contract, name: &str, arg_count: usize) -> Result<&'a Function, Error> {
+    let overloads = contract.functions_by_name(name).map_err(abi_error)?;
+    overloads.iter()
+        .find(|f| f.inputs.len() == arg_count)
+        .ok_or_else(|| Error::InvalidInput(format!("function '{}' does not take {} arguments", name, arg_count)))
+}
+
+/// Encodes call data from textual arguments.
+pub fn encode_call(function: &Function, args: &[String]) -> Result<Vec<u8>, Error> {
+    let kinds: Vec<_> = function.inputs.iter().map(|p| p.kind.clone()).collect();
+    let tokens = tokenize(&kinds, args)?;
+    function.encode_input(&tokens).map_err(abi_error)
+}
+
+/// Appends encoded constructor arguments to the bytecode.
+pub fn encode_constructor(contract: &Contract, code: Vec<u8>, args: &[String]) -> Result<Vec<u8>, Error> {
+    match contract.constructor {
+        Some(ref constructor) => {
+            let kinds: Vec<_> = constructor.inputs.iter().map(|p| p.kind.clone()).collect();
+            let tokens = tokenize(&kinds, args)?;
+            constructor.encode_input(code, &tokens).map_err(abi_error)
+        },
+        None if args.is_empty() => Ok(code),
+        None => Err(Error::InvalidInput("ABI has no constructor, arguments are not accepted".into())),
+    }
+}
+
+/// Decodes the return data of the function.
+pub fn decode_output(function: &Function, data: &[u8]) -> Result<Vec<Value>, Error> {
+    let tokens = function.decode_output(data).map_err(abi_error)?;
+    Ok(function.outputs.iter()
+        .zip(tokens)
+        .map(|(param, token)| Value { name: param.name.clone(), kind: param.kind.to_string(), token })
+        .collect())
+}
+
+/// Decodes the log with the contract event matching its first topic, if any.
+pub fn decode_log(contract: &Contract, log: &Log) -> Option<Event> {
+    let signature = log.topics.first()?;
+    let event = contract.events().find(|e| !e.anonymous && e.signature() == *signature)?;
+    let parsed = event.parse_log(RawLog { topics: log.topics.clone(), data: log.data.clone() }).ok()?;
+    Some(Event {
+        name: event.name.clone(),
+        params: parsed.params.into_iter()
+            .map(|param| {
+                let kind = event.inputs.iter()
+                    .find(|input| input.name == param.name)
+                    .map(|input| input.kind.to_string())
+                    .unwrap_or_default();
+                Value { name: param.name, kind, token: param.value }
+            })
+            .collect(),
+    })
+}
+
+/// Renders the token the way Solidity source would write it.
+pub fn format_token(token: &Token) -> String {
+    match *token {
+        Token::Address(ref address) => format!("0x{:x}", address),
+        Token::FixedBytes(ref bytes) | Token::Bytes(ref bytes) => format!("0x{}", rustc_hex::ToHex::to_hex::<String>(&bytes[..])),
+        Token::Int(ref value) if value.bit(255) => format!("-{}", (!*value).overflowing_add(U256::one()).0),
+        Token::Int(ref value) | Token::Uint(ref value) => value.to_string(),
+        Token::Bool(value) => value.to_string(),
+        Token::String(ref value) => value.clone(),
+        Token::FixedArray(ref tokens) | Token::Array(ref tokens) =>
+            format!("[{}]", tokens.iter().map(format_token).collect::<Vec<_>>().join(", ")),
+        Token::Tuple(ref tokens) =>
+            format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABI: &str = r#"[
+        {"type":"constructor","inputs":[{"name":"owner","type":"address"}],"stateMutability":"nonpayable"},
+        {"type":"function","name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"value","type":"uint256"}],
+         "outputs":[{"name":"ok","type":"bool"}],"stateMutability":"nonpayable"},
+        {"type":"event","name":"Transfer","anonymous":false,"inputs":[
+            {"name":"from","type":"address","indexed":true},
+            {"name":"to","type":"address","indexed":true},
+            {"name":"value","type":"int256","indexed":false}]}
+    ]"#;
+
+    fn contract() -> Contract {
+        Contract::load(ABI.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn should_encode_call_and_decode_output() {
+        let contract = contract();
+        let function = function(&contract, "transfer", 2).unwrap();
+        let data = encode_call(function, &["0x0000000000000000000000000000000000000002".into(), "1".into()]).unwrap();
+        assert_eq!(&data[..4], &[0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(data.len(), 4 + 64);
+        assert!(encode_call(function, &["1".into()]).is_err());
+
+        let mut output = vec![0u8; 32];
+        output[31] = 1;
+        let values = decode_output(function, &output).unwrap();
+        assert_eq!(values, vec![Value { name: "ok".into(), kind: "bool".into(), token: Token::Bool(true) }]);
+    }
+
+    #[test]
+    fn should_append_constructor_arguments() {
+        let code = encode_constructor(&contract(), vec![0x60, 0x00], &["0000000000000000000000000000000000000001".into()]).unwrap();
+        assert_eq!(code.len(), 2 + 32);
+        assert_eq!(code[33], 1);
+    }
+
+    #[test]
+    fn should_format_negative_int() {
+        assert_eq!(format_token(&Token::Int(!U256::zero())), "-1");
+        assert_eq!(format_token(&Token::Array(vec![Token::Uint(5.into()), Token::Bool(false)])), "[5, false]");
+    }
+}
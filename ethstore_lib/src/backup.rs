@@ -0,0 +1,518 @@
+//! Encrypted backup archives of a whole key directory.
+//!
+//! An archive is a JSON document `{"version":1,"crypto":{…}}` whose `crypto` section has
+//! the layout of a key file's. Decrypted with the backup passphrase it holds the manifest
+//! of every file of the key directory and its vaults (path, vault, address when known and
+//! SHA-256) together with their contents, so `vault.json` files, the address book and
+//! other metadata files are kept as they are.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ethkey::Password;
+use ethstore::accounts_dir::{
+    write_file_atomically, KeyDirectory, RootDiskDirectory, VaultKey, VaultKeyDirectoryProvider, VAULT_FILE_NAME, VAULT_TEMP_FILE_NAME,
+};
+use ethstore::accounts_dir::fsck::temp_vault_of;
+use ethstore::{Crypto, KdfParams, SafeAccount};
+use parity_crypto::publickey::Address;
+use rustc_hex::{FromHex, ToHex};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ethstore_rely::Error;
+
+/// Archive format version written by `create`; newer archives are refused.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// File stored in an archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the key directory, `/` separated.
+    pub path: String,
+    /// Vault the file belongs to.
+    pub vault: Option<String>,
+    /// Address of the key in the file, when it could be read.
+    pub address: Option<Address>,
+    /// Hex SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    crypto: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    version: u32,
+    /// Unix time the archive was created at.
+    created: u64,
+    files: Vec<ArchiveFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveFile {
+    #[serde(flatten)]
+    entry: ManifestEntry,
+    /// Hex file contents.
+    contents: String,
+}
+
+/// Writes an archive of the key directory at `root` to the new file `out`, encrypted with
+/// `passphrase` using `kdf`. Addresses of vault keys are only listed for the vaults in
+/// `vault_passwords`, the files of every vault are archived either way.
+pub fn create(root: &Path, out: &Path, passphrase: &Password, kdf: KdfParams, vault_passwords: &HashMap<String, Password>) -> Result<Vec<ManifestEntry>, Error> {
+    let addresses = addresses(root, vault_passwords)?;
+    let mut files = Vec::new();
+    collect(root, None, &addresses, &mut files)?;
+    for (name, dir) in vault_dirs(root)? {
+        collect(&dir, Some(&name), &addresses, &mut files)?;
+    }
+
+    let manifest: Vec<ManifestEntry> = files.iter().map(|file| file.entry.clone()).collect();
+
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let payload = serde_json::to_vec(&Payload { version: ARCHIVE_VERSION, created, files })
+        .map_err(|e| Error::InvalidInput(format!("backup could not be encoded: {}", e)))?;
+    let crypto: String = Crypto::with_plain(&payload, passphrase, kdf).map_err(ethstore::Error::from)?.into();
+    let archive = Archive {
+        version: ARCHIVE_VERSION,
+        crypto: serde_json::from_str(&crypto).expect("Crypto serializes to a JSON object; qed"),
+    };
+
+    let mut file = create_private_file(out)?;
+    serde_json::to_writer(&mut file, &archive)
+        .map_err(|e| Error::InvalidInput(format!("backup could not be encoded: {}", e)))?;
+    file.flush()?;
+    file.sync_all()?;
+    Ok(manifest)
+}
+
+/// Decrypts the archive at `path` and checks every file against its checksum without
+/// restoring anything.
+pub fn verify(path: &Path, passphrase: &Password) -> Result<Vec<ManifestEntry>, Error> {
+    Ok(read(path, passphrase)?.into_iter().map(|(entry, _)| entry).collect())
+}
+
+/// Restores the archive at `path` into the key directory `root`. Files that already exist
+/// and keys whose address is already stored in another file of `root` or of the same vault
+/// are refused, unless `overwrite` is set: then they are replaced and the other files holding
+/// those addresses removed. Keys of an existing vault can only be compared with its password
+/// in `vault_passwords`, which is required once the archive adds keys to a vault holding other
+/// keys. Existing vaults whose vault file differs from the archived one are always refused,
+/// so keys of the vault and its vault file never end up with different passwords.
+///
+/// Every check is made before anything is written. The files are then written one at a time
+/// and the duplicates are removed once all of them are written, so an error while writing
+/// leaves the files written so far, which its message lists.
+pub fn restore(path: &Path, root: &Path, passphrase: &Password, overwrite: bool, vault_passwords: &HashMap<String, Password>) -> Result<Vec<ManifestEntry>, Error> {
+    let files = read(path, passphrase)?;
+
+    let restored: HashSet<PathBuf> = files.iter().map(|(entry, _)| root.join(&entry.path)).collect();
+    let mut conflicts: Vec<PathBuf> = restored.iter().filter(|path| path.exists()).cloned().collect();
+    for (entry, contents) in &files {
+        let path = root.join(&entry.path);
+        if let Some(ref vault) = entry.vault {
+            if path.file_name().map_or(false, |name| name == VAULT_FILE_NAME) && path.exists() && fs::read(&path)? != *contents {
+                return Err(Error::InvalidInput(format!("vault '{}' already exists with another vault file, restore into another key directory", vault)));
+            }
+        }
+    }
+
+    let mut replaced = Vec::new();
+    if root.is_dir() {
+        let directory = RootDiskDirectory::at(root);
+        let archived = archived_addresses(&files, None, None)?;
+        duplicates(root, directory.load()?, &archived, &restored, &mut replaced);
+
+        let mut vaults: Vec<&str> = files.iter()
+            .filter(|(entry, _)| !is_vault_file(entry))
+            .filter_map(|(entry, _)| entry.vault.as_ref().map(String::as_str))
+            .collect();
+        vaults.sort();
+        vaults.dedup();
+        for vault in vaults {
+            let dir = root.join(vault);
+            if !has_other_files(&dir, &restored)? {
+                continue;
+            }
+            let password = vault_passwords.get(vault).ok_or_else(|| Error::InvalidInput(format!(
+                "vault '{}' already holds keys, its password is required to check them against the archived ones: use --vault and --vault-pwd", vault)))?;
+            let archived = archived_addresses(&files, Some(vault), Some(password))?;
+            let accounts = directory.open(vault, VaultKey::new(password, KdfParams::default()))?.load()?;
+            duplicates(&dir, accounts, &archived, &restored, &mut replaced);
+        }
+    }
+    conflicts.extend(replaced.iter().cloned());
+    if !conflicts.is_empty() && !overwrite {
+        conflicts.sort();
+        let conflicts: Vec<_> = conflicts.iter().map(|path| path.display().to_string()).collect();
+        return Err(Error::InvalidInput(format!("restore would overwrite existing accounts, use --overwrite to replace them: {}", conflicts.join(", "))));
+    }
+
+    let mut written = Vec::new();
+    let write = |written: &mut Vec<String>| -> Result<(), Error> {
+        for (entry, contents) in &files {
+            let path = root.join(&entry.path);
+            let dir = path.parent().expect("restored paths are relative to the key directory; qed");
+            let name = path.file_name().expect("restored paths end with a file name; qed").to_string_lossy();
+            fs::create_dir_all(dir)?;
+            write_file_atomically(dir, &name, overwrite, |file| file.write_all(contents).map_err(Into::into))?;
+            written.push(path.display().to_string());
+        }
+        for path in &replaced {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    };
+    match write(&mut written) {
+        Err(err) if !written.is_empty() => Err(Error::Io(io::Error::new(io::ErrorKind::Other, format!(
+            "restore failed after writing {}, duplicates of the restored keys may remain: {}", written.join(", "), err)))),
+        result => result.map(|_| files.into_iter().map(|(entry, _)| entry).collect()),
+    }
+}
+
+/// Addresses of the archived keys of `vault`, or of the root directory. Vault keys missing
+/// from the manifest are read with the vault `password`, when given.
+fn archived_addresses(files: &[(ManifestEntry, Vec<u8>)], vault: Option<&str>, password: Option<&Password>) -> Result<HashSet<Address>, Error> {
+    let mut addresses = HashSet::new();
+    for (entry, contents) in files {
+        if entry.vault.as_ref().map(String::as_str) != vault || is_vault_file(entry) {
+            continue;
+        }
+        let address = match (entry.address, password) {
+            (Some(address), _) => Some(address),
+            (None, Some(password)) => vault_key_address(contents, password)?,
+            (None, None) => None,
+        };
+        addresses.extend(address);
+    }
+    Ok(addresses)
+}
+
+/// Address of a vault key file, whose meta is encrypted with the vault `password`. Files
+/// which are not vault keys have none.
+fn vault_key_address(contents: &[u8], password: &Password) -> Result<Option<Address>, Error> {
+    let crypto = serde_json::from_slice::<serde_json::Value>(contents).ok()
+        .and_then(|file| file.get("metacrypto").map(|metacrypto| metacrypto.to_string()))
+        .and_then(|metacrypto| metacrypto.parse::<Crypto>().ok());
+    let crypto = match crypto {
+        Some(crypto) => crypto,
+        None => return Ok(None),
+    };
+    let meta = serde_json::from_slice::<serde_json::Value>(&crypto.decrypt(password)?).ok();
+    Ok(meta.as_ref()
+        .and_then(|meta| meta.get("address"))
+        .and_then(|address| address.as_str())
+        .and_then(|address| address.trim_start_matches("0x").parse().ok()))
+}
+
+/// Adds the key files of `accounts`, stored in `dir`, that hold an archived address but are
+/// not restored over.
+fn duplicates(dir: &Path, accounts: Vec<SafeAccount>, archived: &HashSet<Address>, restored: &HashSet<PathBuf>, replaced: &mut Vec<PathBuf>) {
+    for account in accounts {
+        let path = match account.filename {
+            Some(ref filename) => dir.join(filename),
+            None => continue,
+        };
+        if archived.contains(&account.address) && !restored.contains(&path) {
+            replaced.push(path);
+        }
+    }
+}
+
+/// Checks if the vault directory `dir` holds files that are not restored over, besides its
+/// vault file, hidden files and leftovers of interrupted writes.
+fn has_other_files(dir: &Path, restored: &HashSet<PathBuf>) -> Result<bool, Error> {
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && !name.starts_with('.') && name != VAULT_FILE_NAME && !name.starts_with(VAULT_TEMP_FILE_NAME) && !restored.contains(&entry.path()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn is_vault_file(entry: &ManifestEntry) -> bool {
+    entry.vault.is_some() && Path::new(&entry.path).file_name().map_or(false, |name| name == VAULT_FILE_NAME)
+}
+
+/// Decrypts the archive and returns its files, checked against the manifest.
+fn read(path: &Path, passphrase: &Password) -> Result<Vec<(ManifestEntry, Vec<u8>)>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| Error::InvalidInput(format!("'{}' is not a backup archive: {}", path.display(), e));
+    let archive: Archive = serde_json::from_reader(fs::File::open(path)?).map_err(|e| invalid(&e))?;
+    if archive.version > ARCHIVE_VERSION {
+        return Err(Error::InvalidInput(format!("backup archive version {} is not supported, at most {}", archive.version, ARCHIVE_VERSION)));
+    }
+    let crypto: Crypto = archive.crypto.to_string().parse().map_err(|e| invalid(&e))?;
+    let payload: Payload = serde_json::from_slice(&crypto.decrypt(passphrase)?).map_err(|e| invalid(&e))?;
+
+    let mut files = Vec::with_capacity(payload.files.len());
+    for file in payload.files {
+        check_path(&file.entry.path)?;
+        let contents: Vec<u8> = file.contents.from_hex()
+            .map_err(|_| Error::InvalidInput(format!("backup archive is corrupted: contents of {} are not hex", file.entry.path)))?;
+        if sha256(&contents) != file.entry.sha256 {
+            return Err(Error::InvalidInput(format!("backup archive is corrupted: checksum of {} does not match", file.entry.path)));
+        }
+        files.push((file.entry, contents));
+    }
+    Ok(files)
+}
+
+/// Addresses of the keys of the root directory and of the vaults with a known password,
+/// by path relative to `root`.
+fn addresses(root: &Path, vault_passwords: &HashMap<String, Password>) -> Result<HashMap<String, Address>, Error> {
+    let directory = RootDiskDirectory::at(root);
+    let mut addresses = HashMap::new();
+    for account in directory.load()? {
+        if let Some(filename) = account.filename {
+            addresses.insert(filename, account.address);
+        }
+    }
+    for (name, password) in vault_passwords {
+        let vault = directory.open(name, VaultKey::new(password, KdfParams::default()))?;
+        for account in vault.load()? {
+            if let Some(filename) = account.filename {
+                addresses.insert(format!("{}/{}", name, filename), account.address);
+            }
+        }
+    }
+    Ok(addresses)
+}
+
+/// Vault subdirectories of `root`, without the hidden and temporary copies left by vault changes.
+fn vault_dirs(root: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut vaults = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        if !path.join(VAULT_FILE_NAME).is_file() || name.starts_with('.') || temp_vault_of(root, &name).is_some() {
+            continue;
+        }
+        vaults.push((name, path));
+    }
+    vaults.sort();
+    Ok(vaults)
+}
+
+/// Adds the regular files of `dir`, skipping hidden files and leftovers of interrupted writes.
+fn collect(dir: &Path, vault: Option<&str>, addresses: &HashMap<String, Address>, files: &mut Vec<ArchiveFile>) -> Result<(), Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_file() || name.starts_with('.') || name.starts_with(VAULT_TEMP_FILE_NAME) {
+            continue;
+        }
+        paths.push((name, entry.path()));
+    }
+    paths.sort();
+
+    for (name, path) in paths {
+        let contents = fs::read(&path)?;
+        let path = match vault {
+            Some(vault) => format!("{}/{}", vault, name),
+            None => name,
+        };
+        files.push(ArchiveFile {
+            entry: ManifestEntry {
+                address: addresses.get(&path).cloned(),
+                vault: vault.map(str::to_owned),
+                sha256: sha256(&contents),
+                path,
+            },
+            contents: contents.to_hex(),
+        });
+    }
+    Ok(())
+}
+
+/// Archived paths are a file name, or a vault and a file name.
+fn check_path(path: &str) -> Result<(), Error> {
+    let components: Vec<_> = Path::new(path).components().collect();
+    if components.is_empty() || components.len() > 2 || components.iter().any(|c| match c { Component::Normal(_) => false, _ => true }) {
+        return Err(Error::InvalidInput(format!("backup archive is corrupted: invalid path {}", path)));
+    }
+    Ok(())
+}
+
+fn sha256(contents: &[u8]) -> String {
+    Sha256::digest(contents).as_slice().to_hex()
+}
+
+/// Creates a new file readable by its owner only.
+fn create_private_file(path: &Path) -> Result<fs::File, Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Error creating '{}': {}", path.display(), e)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use ethstore::EthStore;
+    use ethstore::accounts_dir::RootDiskDirectory;
+    use ethstore::{SecretStore, SecretVaultRef, SimpleSecretStore, StoreAccountRef};
+    use parity_crypto::publickey::{Generator, Random};
+    use super::*;
+
+    const KDF: KdfParams = KdfParams::Pbkdf2 { iterations: 1024 };
+
+    fn keystore(root: &Path) -> (Address, Address) {
+        let store = EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(root).unwrap()), KDF).unwrap();
+        store.create_vault("team", &"vault".into()).unwrap();
+        let root_account = store.insert_account(SecretVaultRef::Root, Random.generate().secret().clone(), &"first".into()).unwrap();
+        let vault_account = store.insert_account(SecretVaultRef::Vault("team".into()), Random.generate().secret().clone(), &"second".into()).unwrap();
+        fs::write(root.join("address_book.json"), "{}").unwrap();
+        (root_account.address, vault_account.address)
+    }
+
+    #[test]
+    fn should_backup_and_restore_keystore() {
+        let (source, target, out) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (root_address, vault_address) = keystore(source.path());
+        let archive = out.path().join("backup.json");
+        let mut vault_passwords = HashMap::new();
+        vault_passwords.insert("team".to_owned(), "vault".into());
+
+        let manifest = create(source.path(), &archive, &"backup".into(), KDF, &vault_passwords).unwrap();
+        assert_eq!(manifest.len(), 4);
+        assert!(manifest.iter().any(|e| e.path == "address_book.json"));
+        assert!(manifest.iter().any(|e| e.path == "team/vault.json" && e.vault == Some("team".into())));
+        assert!(manifest.iter().any(|e| e.address == Some(root_address) && e.vault.is_none()));
+        assert!(manifest.iter().any(|e| e.address == Some(vault_address)));
+        assert_eq!(verify(&archive, &"backup".into()).unwrap(), manifest);
+        assert!(create(source.path(), &archive, &"backup".into(), KDF, &HashMap::new()).is_err());
+
+        let wrong = verify(&archive, &"wrong".into()).unwrap_err();
+        assert_eq!(wrong.exit_code(), crate::ethstore_rely::exit_code::BAD_PASSWORD);
+
+        restore(&archive, target.path(), &"backup".into(), false, &HashMap::new()).unwrap();
+        let store = EthStore::open(Box::new(RootDiskDirectory::at(target.path()))).unwrap();
+        store.open_vault("team", &"vault".into()).unwrap();
+        assert!(store.test_password(&StoreAccountRef::root(root_address), &"first".into()).unwrap());
+        assert!(store.test_password(&StoreAccountRef::vault("team", vault_address), &"second".into()).unwrap());
+    }
+
+    #[test]
+    fn should_refuse_to_overwrite_accounts() {
+        let (source, target, out) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (root_address, _) = keystore(source.path());
+        let archive = out.path().join("backup.json");
+        create(source.path(), &archive, &"backup".into(), KDF, &HashMap::new()).unwrap();
+
+        // the same key stored under another file name
+        let copy = target.path().join("copy");
+        let original = fs::read_dir(source.path()).unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.file_name().unwrap().to_str().unwrap().starts_with("UTC--"))
+            .unwrap();
+        fs::copy(&original, &copy).unwrap();
+
+        let err = restore(&archive, target.path(), &"backup".into(), false, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("--overwrite"), "{}", err);
+        assert_eq!(fs::read_dir(target.path()).unwrap().count(), 1);
+
+        restore(&archive, target.path(), &"backup".into(), true, &HashMap::new()).unwrap();
+        assert!(!copy.exists());
+        let accounts = RootDiskDirectory::at(target.path()).load().unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].address, root_address);
+    }
+
+    #[test]
+    fn should_refuse_duplicate_vault_accounts() {
+        let (source, target, out) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (_, vault_address) = keystore(source.path());
+        let archive = out.path().join("backup.json");
+        create(source.path(), &archive, &"backup".into(), KDF, &HashMap::new()).unwrap();
+        restore(&archive, target.path(), &"backup".into(), false, &HashMap::new()).unwrap();
+
+        // the same vault key stored under another file name
+        let vault_dir = target.path().join("team");
+        let original = fs::read_dir(&vault_dir).unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.file_name().unwrap() != VAULT_FILE_NAME)
+            .unwrap();
+        let renamed = vault_dir.join("renamed");
+        fs::rename(&original, &renamed).unwrap();
+        let mut vault_passwords = HashMap::new();
+        vault_passwords.insert("team".to_owned(), "vault".into());
+
+        let err = restore(&archive, target.path(), &"backup".into(), true, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("password is required"), "{}", err);
+        let err = restore(&archive, target.path(), &"backup".into(), false, &vault_passwords).unwrap_err();
+        assert!(err.to_string().contains(&renamed.display().to_string()), "{}", err);
+
+        restore(&archive, target.path(), &"backup".into(), true, &vault_passwords).unwrap();
+        assert!(!renamed.exists());
+        let store = EthStore::open(Box::new(RootDiskDirectory::at(target.path()))).unwrap();
+        store.open_vault("team", &"vault".into()).unwrap();
+        let vault_accounts: Vec<_> = store.accounts().unwrap().into_iter()
+            .filter(|account| account.vault == SecretVaultRef::Vault("team".into()))
+            .map(|account| account.address)
+            .collect();
+        assert_eq!(vault_accounts, vec![vault_address]);
+    }
+
+    #[test]
+    fn should_refuse_to_overwrite_other_vault() {
+        let (source, target, out) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        keystore(source.path());
+        let archive = out.path().join("backup.json");
+        create(source.path(), &archive, &"backup".into(), KDF, &HashMap::new()).unwrap();
+
+        let store = EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(target.path()).unwrap()), KDF).unwrap();
+        store.create_vault("team", &"other".into()).unwrap();
+        let vault_file = target.path().join("team").join(VAULT_FILE_NAME);
+        let before = fs::read(&vault_file).unwrap();
+
+        let err = restore(&archive, target.path(), &"backup".into(), true, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("vault 'team'"), "{}", err);
+        assert_eq!(fs::read(&vault_file).unwrap(), before);
+        assert_eq!(fs::read_dir(target.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn should_list_files_written_before_failing() {
+        let (source, target, out) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        keystore(source.path());
+        let archive = out.path().join("backup.json");
+        create(source.path(), &archive, &"backup".into(), KDF, &HashMap::new()).unwrap();
+        // a file in place of the vault directory fails the restore after the root files are written
+        fs::write(target.path().join("team"), "").unwrap();
+
+        let err = restore(&archive, target.path(), &"backup".into(), false, &HashMap::new()).unwrap_err();
+        let address_book = target.path().join("address_book.json");
+        assert_eq!(err.exit_code(), crate::ethstore_rely::exit_code::IO);
+        assert!(err.to_string().contains(&address_book.display().to_string()), "{}", err);
+        assert!(address_book.exists());
+    }
+
+    #[test]
+    fn should_reject_paths_outside_key_directory() {
+        assert!(check_path("UTC--key").is_ok());
+        assert!(check_path("team/vault.json").is_ok());
+        for path in &["", "/etc/passwd", "../key", "team/../../key", "a/b/c"] {
+            assert!(check_path(path).is_err(), "{}", path);
+        }
+    }
+}
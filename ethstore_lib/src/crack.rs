@@ -23,16 +23,19 @@ use ethstore::{PresaleWallet, Error};
 use ethkey::Password;
 use num_cpus;
 
-pub fn run(passwords: VecDeque<Password>, wallet_path: &str) -> Result<(), Error> {
+/// Tries every password on the presale wallet, returns the one that decrypts it.
+pub fn run(passwords: VecDeque<Password>, wallet_path: &str) -> Result<Option<Password>, Error> {
     let passwords = Arc::new(Mutex::new(passwords));
+    let found = Arc::new(Mutex::new(None));
 
     let mut handles = Vec::new();
 
     for _ in 0..num_cpus::get() {
         let passwords = passwords.clone();
+        let found = found.clone();
         let wallet = PresaleWallet::open(&wallet_path)?;
         handles.push(thread::spawn(move || {
-            look_for_password(passwords, wallet);
+            look_for_password(passwords, found, wallet);
         }));
     }
 
@@ -40,11 +43,11 @@ pub fn run(passwords: VecDeque<Password>, wallet_path: &str) -> Result<(), Error
         handle.join().map_err(|err| Error::Custom(format!("Error finishing thread: {:?}", err)))?;
     }
 
-    Ok(())
+    let found = found.lock().take();
+    Ok(found)
 }
 
-fn look_for_password(passwords: Arc<Mutex<VecDeque<Password>>>, wallet: PresaleWallet) {
-    let mut counter = 0;
+fn look_for_password(passwords: Arc<Mutex<VecDeque<Password>>>, found: Arc<Mutex<Option<Password>>>, wallet: PresaleWallet) {
     while !passwords.lock().is_empty() {
         let package = {
             let mut passwords = passwords.lock();
@@ -52,15 +55,10 @@ fn look_for_password(passwords: Arc<Mutex<VecDeque<Password>>>, wallet: PresaleW
             passwords.split_off(cmp::min(len, 32))
         };
         for pass in package {
-            counter += 1;
-            match wallet.decrypt(&pass) {
-                Ok(_) => {
-                    println!("Found password: {}", pass.as_str());
-                    passwords.lock().clear();
-                    return;
-                },
-                _ if counter % 100 == 0 => print!("."),
-                _ => {},
+            if wallet.decrypt(&pass).is_ok() {
+                passwords.lock().clear();
+                *found.lock() = Some(pass);
+                return;
             }
         }
     }
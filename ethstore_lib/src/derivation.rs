@@ -0,0 +1,67 @@
+//! Text form of the keystore's own derivation specs, used with `insert_derived`,
+//! `generate_derived` and `sign_derived`.
+//!
+//! These follow Parity's extended key scheme and do not give BIP-32 addresses; see
+//! `hd` for wallet-compatible derivation.
+
+use ethereum_types::H256;
+use ethstore::{Derivation, IndexDerivation};
+
+use crate::ethstore_rely::Error;
+use crate::hd::{DerivationPath, HARDENED};
+
+/// Parses `soft:<hash>`, `hard:<hash>` or an index path such as `m/0/1'/2`, where `'`
+/// marks a hard derivation step.
+pub fn parse(spec: &str) -> Result<Derivation, Error> {
+    let hash = |value: &str| {
+        value.trim_start_matches("0x").parse::<H256>()
+            .map_err(|_| Error::InvalidInput(format!("derivation hash '{}' must be 32 bytes of hex", value)))
+    };
+    if spec.starts_with("soft:") {
+        return Ok(Derivation::SoftHash(hash(&spec[5..])?));
+    }
+    if spec.starts_with("hard:") {
+        return Ok(Derivation::HardHash(hash(&spec[5..])?));
+    }
+    let path: DerivationPath = spec.parse()
+        .map_err(|_| Error::InvalidInput(format!("'{}' is not a derivation: use soft:<hash>, hard:<hash> or a path like m/0/1'", spec)))?;
+    if path.indexes().is_empty() {
+        return Err(Error::InvalidInput("derivation path must have at least one index".into()));
+    }
+    Ok(Derivation::Hierarchical(path.indexes()
+        .iter()
+        .map(|index| IndexDerivation { soft: index & HARDENED == 0, index: index & !HARDENED })
+        .collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_derivation_specs() {
+        let hash = "0x0000000000000000000000000000000000000000000000000000000000000001";
+        match parse(&format!("soft:{}", hash)).unwrap() {
+            Derivation::SoftHash(h) => assert_eq!(h, H256::from_low_u64_be(1)),
+            _ => panic!("expected soft hash derivation"),
+        }
+        match parse(&format!("hard:{}", &hash[2..])).unwrap() {
+            Derivation::HardHash(h) => assert_eq!(h, H256::from_low_u64_be(1)),
+            _ => panic!("expected hard hash derivation"),
+        }
+        match parse("m/0/7'").unwrap() {
+            Derivation::Hierarchical(path) => {
+                let path: Vec<_> = path.iter().map(|i| (i.soft, i.index)).collect();
+                assert_eq!(path, vec![(true, 0), (false, 7)]);
+            },
+            _ => panic!("expected hierarchical derivation"),
+        }
+    }
+
+    #[test]
+    fn should_reject_invalid_specs() {
+        for spec in &["soft:1234", "hard:", "m", "0/1", "medium:00"] {
+            assert!(parse(spec).is_err(), "{}", spec);
+        }
+    }
+}
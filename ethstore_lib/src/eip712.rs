@@ -0,0 +1,390 @@
+//! EIP-712 typed structured data hashing.
+//!
+//! Documents use the `eth_signTypedData_v4` JSON layout. Validation errors name the
+//! offending field by its path, e.g. `message.to.wallet` or `types.Mail[1].type`.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::iter;
+
+use ethereum_types::{H256, U256};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+use rustc_hex::FromHex;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::ethstore_rely::Error;
+
+/// Name of the domain struct type every document must define.
+pub const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Member of a struct type.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Typed data document: struct types, the primary type, the domain and the message.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<Field>>,
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+fn invalid(path: &str, msg: impl fmt::Display) -> Error {
+    Error::InvalidInput(format!("typed data {}: {}", path, msg))
+}
+
+fn keccak(data: &[u8]) -> H256 {
+    KeccakHasher::hash(data)
+}
+
+fn word(value: U256) -> H256 {
+    let mut word = H256::zero();
+    value.to_big_endian(word.as_bytes_mut());
+    word
+}
+
+/// Splits `T[n]` or `T[]` into `T` and the length text.
+fn split_array(kind: &str) -> Option<(&str, &str)> {
+    if !kind.ends_with(']') {
+        return None;
+    }
+    let open = kind.rfind('[')?;
+    Some((&kind[..open], &kind[open + 1..kind.len() - 1]))
+}
+
+/// Struct or atomic type name with all array dimensions removed.
+fn base_type(mut kind: &str) -> &str {
+    while let Some((inner, _)) = split_array(kind) {
+        kind = inner;
+    }
+    kind
+}
+
+/// Bit or byte size of `uintN`, `intN` and `bytesN`, if `kind` is one of them and valid.
+fn sized(kind: &str, prefix: &str) -> Option<usize> {
+    if !kind.starts_with(prefix) {
+        return None;
+    }
+    let size: usize = kind[prefix.len()..].parse().ok()?;
+    match prefix {
+        "bytes" if (1..=32).contains(&size) => Some(size),
+        "uint" | "int" if (8..=256).contains(&size) && size % 8 == 0 => Some(size),
+        _ => None,
+    }
+}
+
+fn is_atomic(kind: &str) -> bool {
+    match kind {
+        "address" | "bool" | "string" | "bytes" => true,
+        _ => sized(kind, "bytes").or_else(|| sized(kind, "uint")).or_else(|| sized(kind, "int")).is_some(),
+    }
+}
+
+fn parse_hex(value: &Value, path: &str) -> Result<Vec<u8>, Error> {
+    let text = value.as_str().ok_or_else(|| invalid(path, "expected a hex string"))?;
+    text.trim_start_matches("0x").from_hex().map_err(|_| invalid(path, format!("'{}' is not valid hex", text)))
+}
+
+/// Reads an integer given as a JSON number, a decimal string or a `0x` hex string.
+/// Returns whether it is negative and its magnitude.
+fn parse_integer(value: &Value, path: &str) -> Result<(bool, U256), Error> {
+    let not_integer = || invalid(path, format!("{} is not an integer", value));
+    match *value {
+        Value::Number(ref number) => match (number.as_u64(), number.as_i64()) {
+            (Some(n), _) => Ok((false, n.into())),
+            (None, Some(n)) => Ok((true, U256::from(n.wrapping_neg() as u64))),
+            _ => Err(not_integer()),
+        },
+        Value::String(ref text) => {
+            let negative = text.starts_with('-');
+            let digits = if negative { &text[1..] } else { text.as_str() };
+            let magnitude = if digits.starts_with("0x") {
+                let hex = &digits[2..];
+                if hex.is_empty() || hex.len() > 64 {
+                    return Err(not_integer());
+                }
+                hex.parse::<U256>().map_err(|_| not_integer())?
+            } else {
+                U256::from_dec_str(digits).map_err(|_| not_integer())?
+            };
+            Ok((negative && !magnitude.is_zero(), magnitude))
+        },
+        _ => Err(not_integer()),
+    }
+}
+
+fn encode_uint(value: &Value, bits: usize, path: &str) -> Result<H256, Error> {
+    let (negative, magnitude) = parse_integer(value, path)?;
+    if negative || magnitude.bits() > bits {
+        return Err(invalid(path, format!("{} does not fit uint{}", value, bits)));
+    }
+    Ok(word(magnitude))
+}
+
+/// Encodes a signed integer as its 256-bit two's complement.
+fn encode_int(value: &Value, bits: usize, path: &str) -> Result<H256, Error> {
+    let (negative, magnitude) = parse_integer(value, path)?;
+    let limit = U256::one() << (bits - 1);
+    if (!negative && magnitude >= limit) || (negative && magnitude > limit) {
+        return Err(invalid(path, format!("{} does not fit int{}", value, bits)));
+    }
+    Ok(word(if negative { (!magnitude).overflowing_add(U256::one()).0 } else { magnitude }))
+}
+
+impl TypedData {
+    /// Parses a JSON document and checks its type definitions.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let data: TypedData = serde_json::from_str(json).map_err(|e| Error::InvalidInput(format!("typed data: {}", e)))?;
+        data.validate_types()?;
+        Ok(data)
+    }
+
+    fn validate_types(&self) -> Result<(), Error> {
+        if !self.types.contains_key(DOMAIN_TYPE) {
+            return Err(invalid("types", format!("{} is not defined", DOMAIN_TYPE)));
+        }
+        if !self.types.contains_key(&self.primary_type) {
+            return Err(invalid("primaryType", format!("'{}' is not defined in types", self.primary_type)));
+        }
+        for (name, fields) in &self.types {
+            if is_atomic(name) || split_array(name).is_some() {
+                return Err(invalid(&format!("types.{}", name), "is not a valid struct name"));
+            }
+            let mut names = HashSet::new();
+            for (i, field) in fields.iter().enumerate() {
+                if !names.insert(&field.name) {
+                    return Err(invalid(&format!("types.{}[{}].name", name, i), format!("'{}' is defined twice", field.name)));
+                }
+                let mut kind = field.kind.as_str();
+                while let Some((inner, len)) = split_array(kind) {
+                    if !len.is_empty() && len.parse::<usize>().is_err() {
+                        return Err(invalid(&format!("types.{}[{}].type", name, i), format!("'{}' has an invalid array length", field.kind)));
+                    }
+                    kind = inner;
+                }
+                if !is_atomic(kind) && !self.types.contains_key(kind) {
+                    return Err(invalid(&format!("types.{}[{}].type", name, i), format!("unknown type '{}'", field.kind)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn dependencies<'a>(&'a self, kind: &'a str, found: &mut BTreeSet<&'a str>) {
+        let kind = base_type(kind);
+        if found.contains(kind) {
+            return;
+        }
+        if let Some(fields) = self.types.get(kind) {
+            found.insert(kind);
+            for field in fields {
+                self.dependencies(&field.kind, found);
+            }
+        }
+    }
+
+    /// Type encoding: the struct followed by the structs it references, sorted by name.
+    pub fn encode_type(&self, kind: &str) -> String {
+        let mut found = BTreeSet::new();
+        self.dependencies(kind, &mut found);
+        found.remove(kind);
+        iter::once(kind)
+            .chain(found)
+            .map(|name| {
+                let fields: Vec<_> = self.types[name].iter().map(|f| format!("{} {}", f.kind, f.name)).collect();
+                format!("{}({})", name, fields.join(","))
+            })
+            .collect()
+    }
+
+    pub fn type_hash(&self, kind: &str) -> H256 {
+        keccak(self.encode_type(kind).as_bytes())
+    }
+
+    /// `hashStruct` of the value, `path` names it in error messages.
+    pub fn hash_struct(&self, kind: &str, value: &Value, path: &str) -> Result<H256, Error> {
+        let fields = self.types.get(kind).ok_or_else(|| invalid(path, format!("unknown type '{}'", kind)))?;
+        let object = value.as_object().ok_or_else(|| invalid(path, format!("expected a {} object", kind)))?;
+        if let Some(key) = object.keys().find(|key| !fields.iter().any(|f| &f.name == *key)) {
+            return Err(invalid(&format!("{}.{}", path, key), format!("is not a field of {}", kind)));
+        }
+        let mut data = self.type_hash(kind).as_bytes().to_vec();
+        for field in fields {
+            let field_path = format!("{}.{}", path, field.name);
+            let value = object.get(&field.name).ok_or_else(|| invalid(&field_path, "is missing"))?;
+            data.extend_from_slice(self.encode_value(&field.kind, value, &field_path)?.as_bytes());
+        }
+        Ok(keccak(&data))
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value, path: &str) -> Result<H256, Error> {
+        if let Some((inner, len)) = split_array(kind) {
+            let items = value.as_array().ok_or_else(|| invalid(path, "expected an array"))?;
+            if let Ok(len) = len.parse::<usize>() {
+                if items.len() != len {
+                    return Err(invalid(path, format!("expected {} items, got {}", len, items.len())));
+                }
+            }
+            let mut data = Vec::with_capacity(items.len() * 32);
+            for (i, item) in items.iter().enumerate() {
+                data.extend_from_slice(self.encode_value(inner, item, &format!("{}[{}]", path, i))?.as_bytes());
+            }
+            return Ok(keccak(&data));
+        }
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value, path);
+        }
+        match kind {
+            "string" => value.as_str()
+                .map(|text| keccak(text.as_bytes()))
+                .ok_or_else(|| invalid(path, "expected a string")),
+            "bytes" => Ok(keccak(&parse_hex(value, path)?)),
+            "bool" => value.as_bool()
+                .map(|b| word(U256::from(u8::from(b))))
+                .ok_or_else(|| invalid(path, "expected true or false")),
+            "address" => {
+                let bytes = parse_hex(value, path)?;
+                if bytes.len() != 20 {
+                    return Err(invalid(path, "expected a 20-byte address"));
+                }
+                let mut word = H256::zero();
+                word.as_bytes_mut()[12..].copy_from_slice(&bytes);
+                Ok(word)
+            },
+            _ => {
+                if let Some(size) = sized(kind, "bytes") {
+                    let bytes = parse_hex(value, path)?;
+                    if bytes.len() != size {
+                        return Err(invalid(path, format!("expected {} bytes, got {}", size, bytes.len())));
+                    }
+                    let mut word = H256::zero();
+                    word.as_bytes_mut()[..size].copy_from_slice(&bytes);
+                    Ok(word)
+                } else if let Some(bits) = sized(kind, "uint") {
+                    encode_uint(value, bits, path)
+                } else if let Some(bits) = sized(kind, "int") {
+                    encode_int(value, bits, path)
+                } else {
+                    Err(invalid(path, format!("unknown type '{}'", kind)))
+                }
+            },
+        }
+    }
+
+    /// `hashStruct` of the domain.
+    pub fn domain_separator(&self) -> Result<H256, Error> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain, "domain")
+    }
+
+    /// `hashStruct` of the message, `None` when the primary type is the domain itself.
+    pub fn struct_hash(&self) -> Result<Option<H256>, Error> {
+        if self.primary_type == DOMAIN_TYPE {
+            return Ok(None);
+        }
+        self.hash_struct(&self.primary_type, &self.message, "message").map(Some)
+    }
+
+    /// Digest to sign: keccak256 of `0x19 0x01`, the domain separator and the struct hash.
+    pub fn digest(&self) -> Result<H256, Error> {
+        let mut data = vec![0x19, 0x01];
+        data.extend_from_slice(self.domain_separator()?.as_bytes());
+        if let Some(hash) = self.struct_hash()? {
+            data.extend_from_slice(hash.as_bytes());
+        }
+        Ok(keccak(&data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_crypto::publickey::{sign, Secret};
+
+    // Example from the EIP-712 specification.
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    #[test]
+    fn should_hash_specification_example() {
+        let data = TypedData::from_json(MAIL).unwrap();
+        assert_eq!(data.encode_type("Mail"), "Mail(Person from,Person to,string contents)Person(string name,address wallet)");
+        assert_eq!(format!("{:x}", data.type_hash("Mail")), "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2");
+        assert_eq!(format!("{:x}", data.struct_hash().unwrap().unwrap()), "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e");
+        assert_eq!(format!("{:x}", data.domain_separator().unwrap()), "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f");
+        let digest = data.digest().unwrap();
+        assert_eq!(format!("{:x}", digest), "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2");
+
+        let secret = Secret::from(keccak(b"cow"));
+        let signature = sign(&secret, &digest).unwrap();
+        assert_eq!(signature.v(), 1);
+        assert_eq!(format!("{:x}", H256::from_slice(signature.r())), "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d");
+        assert_eq!(format!("{:x}", H256::from_slice(signature.s())), "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562");
+    }
+
+    #[test]
+    fn should_point_at_offending_field() {
+        let error = |json: String| TypedData::from_json(&json).and_then(|data| data.digest()).unwrap_err().to_string();
+
+        assert!(error(MAIL.replace("0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB", "0x1234")).contains("message.to.wallet"));
+        assert!(error(MAIL.replace(r#""contents": "Hello, Bob!""#, r#""contents": 7"#)).contains("message.contents"));
+        assert!(error(MAIL.replace(r#""chainId": 1"#, r#""chainId": -1"#)).contains("domain.chainId"));
+        assert!(error(MAIL.replace(r#""type": "Person"}"#, r#""type": "Persona"}"#)).contains("types.Mail[0].type"));
+        assert!(error(MAIL.replace(r#""name": "Bob", "#, "")).contains("message.to.name: is missing"));
+        assert!(error(MAIL.replace(r#""primaryType": "Mail""#, r#""primaryType": "Letter""#)).contains("primaryType"));
+    }
+
+    #[test]
+    fn should_encode_integers_and_arrays() {
+        let data = TypedData::from_json(r#"{
+            "types": {"EIP712Domain": [], "Values": [{"name": "a", "type": "int8"}, {"name": "b", "type": "uint8[2]"}]},
+            "primaryType": "Values", "domain": {}, "message": {"a": "-1", "b": [1, "0xff"]}
+        }"#).unwrap();
+        assert_eq!(data.encode_value("int8", &"-1".into(), "a").unwrap(), word(!U256::zero()));
+        assert!(data.encode_value("int8", &"128".into(), "a").is_err());
+        assert!(data.encode_value("int8", &"-128".into(), "a").is_ok());
+        assert!(data.encode_value("uint8", &"256".into(), "a").is_err());
+
+        let mut items = word(1.into()).as_bytes().to_vec();
+        items.extend_from_slice(word(255.into()).as_bytes());
+        assert_eq!(data.encode_value("uint8[2]", &serde_json::json!([1, "0xff"]), "b").unwrap(), keccak(&items));
+        assert!(data.encode_value("uint8[2]", &serde_json::json!([1]), "b").is_err());
+        assert!(data.digest().is_ok());
+    }
+}
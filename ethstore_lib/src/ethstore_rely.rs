@@ -1,5 +1,4 @@
 extern crate dir;
-extern crate docopt;
 extern crate ethstore;
 extern crate ethkey;
 extern crate num_cpus;
@@ -10,123 +9,68 @@ extern crate serde;
 extern crate env_logger;
 extern crate serde_derive;
 
-use std::collections::VecDeque;
-use std::io::Read;
-use std::{env, process, fs, fmt};
-use serde::{Deserialize, Serialize};
+use std::io;
+use std::fmt;
+use std::path::PathBuf;
 
-use docopt::Docopt;
 use ethstore::accounts_dir::{KeyDirectory, RootDiskDirectory};
 use ethkey::Password;
 use parity_crypto::publickey::Address;
-use ethstore::{EthStore, SimpleSecretStore, SecretStore, import_accounts, PresaleWallet, SecretVaultRef, StoreAccountRef};
-
-
-pub const USAGE: &'static str = r#"
-OpenEthereum key management tool.
-  Copyright 2015-2020 Parity Technologies (UK) Ltd.
-
-Usage:
-    ethstore insert <secret> <password> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore change-pwd <address> <old-pwd> <new-pwd> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore list [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore import [<password>] [--src DIR] [--dir DIR]
-    ethstore import-wallet <path> <password> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore find-wallet-pass <path> <password>
-    ethstore remove <address> <password> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore sign <address> <password> <message> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore public <address> <password> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore list-vaults [--dir DIR]
-    ethstore create-vault <vault> <password> [--dir DIR]
-    ethstore change-vault-pwd <vault> <old-pwd> <new-pwd> [--dir DIR]
-    ethstore move-to-vault <address> <vault> <password> [--dir DIR] [--vault VAULT] [--vault-pwd VAULTPWD]
-    ethstore move-from-vault <address> <vault> <password> [--dir DIR]
-    ethstore [-h | --help]
-
-Options:
-    -h, --help               Display this message and exit.
-    --dir DIR                Specify the secret store directory. It may be either
-                             parity, parity-(chain), geth, geth-test
-                             or a path [default: parity].
-    --vault VAULT            Specify vault to use in this operation.
-    --vault-pwd VAULTPWD     Specify vault password to use in this operation. Please note
-                             that this option is required when vault option is set.
-                             Otherwise it is ignored.
-    --src DIR                Specify import source. It may be either
-                             parity, parity-(chain), geth, geth-test
-                             or a path [default: geth].
-
-Commands:
-    insert             Save account with password.
-    change-pwd         Change password.
-    list               List accounts.
-    import             Import accounts from src.
-    import-wallet      Import presale wallet.
-    find-wallet-pass   Tries to open a wallet with list of passwords given.
-    remove             Remove account.
-    sign               Sign message.
-    public             Displays public key for an address.
-    list-vaults        List vaults.
-    create-vault       Create new vault.
-    change-vault-pwd   Change vault password.
-    move-to-vault      Move account to vault from another vault/root directory.
-    move-from-vault    Move account to root directory from given vault.
-"#;
-
-#[derive(Debug, Deserialize)]
-pub struct Args {
-    pub cmd_insert: bool,
-    pub cmd_change_pwd: bool,
-    pub cmd_list: bool,
-    pub cmd_import: bool,
-    pub cmd_import_wallet: bool,
-    pub cmd_find_wallet_pass: bool,
-    pub cmd_remove: bool,
-    pub cmd_sign: bool,
-    pub cmd_public: bool,
-    pub cmd_list_vaults: bool,
-    pub cmd_create_vault: bool,
-    pub cmd_change_vault_pwd: bool,
-    pub cmd_move_to_vault: bool,
-    pub cmd_move_from_vault: bool,
-    pub arg_secret: String,
-    pub arg_password: String,
-    pub arg_old_pwd: String,
-    pub arg_new_pwd: String,
-    pub arg_address: String,
-    pub arg_message: String,
-    pub arg_path: String,
-    pub arg_vault: String,
-    pub flag_src: String,
-    pub flag_dir: String,
-    pub flag_vault: String,
-    pub flag_vault_pwd: String,
-}
 
-#[derive(Debug, Clone)]
-pub enum EthstoreNUM {
-    cmd_insert,
-    cmd_change_pwd,
-    cmd_list,
-    cmd_import,
-    cmd_import_wallet,
-    cmd_find_wallet_pass,
-    cmd_remove,
-    cmd_sign,
-    cmd_public,
-    cmd_list_vaults,
-    cmd_create_vault,
-    cmd_change_vault_pwd,
-    cmd_move_to_vault,
-    cmd_move_from_vault,
-    cmd_null
+use crate::{evm, state, transaction};
+
+/// Process exit codes, one per error category.
+///
+/// Scripts may rely on these values; do not renumber them.
+pub mod exit_code {
+    /// Any failure not covered by a more specific code.
+    pub const FAILURE: i32 = 1;
+    /// Password does not match the account or vault.
+    pub const BAD_PASSWORD: i32 = 2;
+    /// Account does not exist in the selected keystore or vault.
+    pub const ACCOUNT_NOT_FOUND: i32 = 3;
+    /// Vault does not exist.
+    pub const VAULT_NOT_FOUND: i32 = 4;
+    /// Malformed address, secret, message, number or other argument, or an operation the
+    /// arguments don't allow, such as deleting a vault that holds accounts without `--force`.
+    pub const INVALID_INPUT: i32 = 5;
+    /// File or directory could not be read or written.
+    pub const IO: i32 = 6;
+    /// Account balance does not cover a transfer.
+    pub const INSUFFICIENT_BALANCE: i32 = 7;
 }
 
-
 #[derive(Debug)]
 pub enum Error {
     Ethstore(ethstore::Error),
-    Docopt(docopt::Error),
+    Io(io::Error),
+    State(state::Error),
+    InvalidInput(String),
+}
+
+impl Error {
+    /// Exit code the process should terminate with, see `exit_code`.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Ethstore(ethstore::Error::InvalidPassword) => exit_code::BAD_PASSWORD,
+            Error::Ethstore(ethstore::Error::InvalidAccount) => exit_code::ACCOUNT_NOT_FOUND,
+            Error::Ethstore(ethstore::Error::VaultNotFound) => exit_code::VAULT_NOT_FOUND,
+            Error::Ethstore(ethstore::Error::InvalidSecret) |
+            Error::Ethstore(ethstore::Error::InvalidMessage) |
+            Error::Ethstore(ethstore::Error::InvalidVaultName) |
+            Error::Ethstore(ethstore::Error::VaultNotEmpty) |
+            Error::Ethstore(ethstore::Error::InvalidKeyFile(_)) => exit_code::INVALID_INPUT,
+            Error::Ethstore(ethstore::Error::Io(_)) => exit_code::IO,
+            Error::Ethstore(_) => exit_code::FAILURE,
+            Error::InvalidInput(_) => exit_code::INVALID_INPUT,
+            Error::Io(_) => exit_code::IO,
+            Error::State(state::Error::AccountNotFound(_)) => exit_code::ACCOUNT_NOT_FOUND,
+            Error::State(state::Error::AccountExists(_)) => exit_code::INVALID_INPUT,
+            Error::State(state::Error::InsufficientBalance { .. }) => exit_code::INSUFFICIENT_BALANCE,
+            Error::State(state::Error::Io(_)) => exit_code::IO,
+            Error::State(_) => exit_code::FAILURE,
+        }
+    }
 }
 
 impl From<ethstore::Error> for Error {
@@ -135,9 +79,31 @@ impl From<ethstore::Error> for Error {
     }
 }
 
-impl From<docopt::Error> for Error {
-    fn from(err: docopt::Error) -> Self {
-        Error::Docopt(err)
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<state::Error> for Error {
+    fn from(err: state::Error) -> Self {
+        Error::State(err)
+    }
+}
+
+impl From<evm::Error> for Error {
+    fn from(err: evm::Error) -> Self {
+        match err {
+            evm::Error::State(err) => Error::State(err),
+            err @ evm::Error::IntrinsicGas { .. } |
+            err @ evm::Error::UnsupportedPrecompile(_) => Error::InvalidInput(err.to_string()),
+        }
+    }
+}
+
+impl From<transaction::Error> for Error {
+    fn from(err: transaction::Error) -> Self {
+        Error::InvalidInput(err.to_string())
     }
 }
 
@@ -145,77 +111,32 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Ethstore(ref err) => fmt::Display::fmt(err, f),
-            Error::Docopt(ref err) => fmt::Display::fmt(err, f),
+            Error::Io(ref err) => fmt::Display::fmt(err, f),
+            Error::State(ref err) => fmt::Display::fmt(err, f),
+            Error::InvalidInput(ref msg) => write!(f, "Invalid input: {}", msg),
         }
     }
 }
-// 获取ethstor下的子命令
-pub fn getsubcmd(args: &Args) -> EthstoreNUM {
-    return if args.cmd_insert {
-        EthstoreNUM::cmd_insert
-    } else if args.cmd_change_pwd {
-        EthstoreNUM::cmd_change_pwd
-    } else if args.cmd_list {
-        EthstoreNUM::cmd_list
-    } else if args.cmd_change_vault_pwd {
-        EthstoreNUM::cmd_change_vault_pwd
-    } else if args.cmd_create_vault {
-        EthstoreNUM::cmd_create_vault
-    } else if args.cmd_find_wallet_pass {
-        EthstoreNUM::cmd_find_wallet_pass
-    } else if args.cmd_import {
-        EthstoreNUM::cmd_import
-    } else if args.cmd_list_vaults {
-        EthstoreNUM::cmd_list_vaults
-    } else if args.cmd_move_from_vault {
-        EthstoreNUM::cmd_move_from_vault
-    } else if args.cmd_public {
-        EthstoreNUM::cmd_public
-    } else if args.cmd_remove {
-        EthstoreNUM::cmd_remove
-    } else if args.cmd_sign {
-        EthstoreNUM::cmd_sign
-    } else if args.cmd_move_to_vault {
-        EthstoreNUM::cmd_move_to_vault
-    } else if args.cmd_import_wallet {
-        EthstoreNUM::cmd_import_wallet
-    } else {
-        EthstoreNUM::cmd_null
-    }
-}
 
-pub fn key_dir(location: &str, password: Option<Password>) -> Result<Box<dyn KeyDirectory>, Error> {
-    let dir: RootDiskDirectory = match location {
-        "geth" => RootDiskDirectory::create(dir::geth(false))?,
-        "geth-test" => RootDiskDirectory::create(dir::geth(true))?,
+/// Path of the key directory `location`: parity, parity-(chain), geth, geth-test or a path.
+pub fn key_dir_location(location: &str) -> PathBuf {
+    match location {
+        "geth" => dir::geth(false),
+        "geth-test" => dir::geth(true),
         path if path.starts_with("parity") => {
             let chain = path.split('-').nth(1).unwrap_or("ethereum");
             let mut path = dir::default_data_pathbuf();
             path.push("keys");
             path.push(chain);
-            RootDiskDirectory::create(path)?
+            path
         },
-        path => RootDiskDirectory::create(path)?,
-    };
-
-    Ok(Box::new(dir.with_password(password)))
-}
-
-pub fn open_args_vault(store: &EthStore, args: &Args) -> Result<SecretVaultRef, Error> {
-    if args.flag_vault.is_empty() {
-        return Ok(SecretVaultRef::Root);
+        path => PathBuf::from(path),
     }
-
-    let vault_pwd = load_password(&args.flag_vault_pwd)?;
-    store.open_vault(&args.flag_vault, &vault_pwd)?;
-    Ok(SecretVaultRef::Vault(args.flag_vault.clone()))
 }
 
-pub fn open_args_vault_account(store: &EthStore, address: Address, args: &Args) -> Result<StoreAccountRef, Error> {
-    match open_args_vault(store, args)? {
-        SecretVaultRef::Root => Ok(StoreAccountRef::root(address)),
-        SecretVaultRef::Vault(name) => Ok(StoreAccountRef::vault(&name, address)),
-    }
+pub fn key_dir(location: &str, password: Option<Password>) -> Result<Box<dyn KeyDirectory>, Error> {
+    let dir = RootDiskDirectory::create(key_dir_location(location))?;
+    Ok(Box::new(dir.with_password(password)))
 }
 
 pub fn format_accounts(accounts: &[Address]) -> String {
@@ -230,11 +151,18 @@ pub fn format_vaults(vaults: &[String]) -> String {
     vaults.join("\n")
 }
 
-pub fn load_password(path: &str) -> Result<Password, Error> {
-    let mut file = fs::File::open(path).map_err(|e| ethstore::Error::Custom(format!("Error opening password file '{}': {}", path, e)))?;
-    let mut password = String::new();
-    file.read_to_string(&mut password).map_err(|e| ethstore::Error::Custom(format!("Error reading password file '{}': {}", path, e)))?;
-    // drop EOF
-    let _ = password.pop();
-    Ok(password.into())
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_key_dir_aliases() {
+        let mut parity = dir::default_data_pathbuf();
+        parity.push("keys");
+        assert_eq!(key_dir_location("parity"), parity.join("ethereum"));
+        assert_eq!(key_dir_location("parity-goerli"), parity.join("goerli"));
+        assert_eq!(key_dir_location("geth"), dir::geth(false));
+        assert_eq!(key_dir_location("geth-test"), dir::geth(true));
+        assert_eq!(key_dir_location("./keys"), PathBuf::from("./keys"));
+    }
+}
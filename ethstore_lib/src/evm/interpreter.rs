@@ -0,0 +1,678 @@
+//! Bytecode interpreter running a single call or create frame.
+
+use ethereum_types::{Address, H256, U256, U512};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+
+use crate::state;
+use super::{create2_address, contract_address, CallParams, Error, Executive, Log, MAX_DEPTH};
+
+const STACK_LIMIT: usize = 1024;
+const CALL_STIPEND: u64 = 2300;
+
+/// Code and arguments of a frame.
+pub(super) struct Context {
+    pub address: Address,
+    pub caller: Address,
+    pub value: U256,
+    pub input: Vec<u8>,
+    pub code: Vec<u8>,
+    pub gas: u64,
+    pub is_static: bool,
+    pub depth: usize,
+}
+
+/// How a frame stopped.
+#[derive(Debug)]
+pub(super) enum Halt {
+    /// `STOP`, `RETURN` or end of code.
+    Return(Vec<u8>),
+    /// `REVERT`.
+    Revert(Vec<u8>),
+    /// Exceptional halt, consumes all gas.
+    Error(String),
+}
+
+pub(super) struct FrameResult {
+    pub halt: Halt,
+    pub gas_left: u64,
+}
+
+enum Exit {
+    Halt(Halt),
+    /// Error that aborts the whole transaction.
+    Abort(Error),
+}
+
+impl From<state::Error> for Exit {
+    fn from(err: state::Error) -> Self {
+        Exit::Abort(err.into())
+    }
+}
+
+impl From<Error> for Exit {
+    fn from(err: Error) -> Self {
+        Exit::Abort(err)
+    }
+}
+
+fn error<T>(msg: &str) -> Result<T, Exit> {
+    Err(Exit::Halt(Halt::Error(msg.to_owned())))
+}
+
+/// Runs the frame to completion.
+pub(super) fn run(exec: &mut Executive, ctx: Context) -> Result<FrameResult, Error> {
+    let mut machine = Machine::new(&ctx);
+    loop {
+        match machine.step(exec, &ctx) {
+            Ok(()) => {},
+            Err(Exit::Halt(halt)) => {
+                let gas_left = match halt {
+                    Halt::Error(_) => 0,
+                    _ => machine.gas,
+                };
+                return Ok(FrameResult { halt, gas_left });
+            },
+            Err(Exit::Abort(err)) => return Err(err),
+        }
+    }
+}
+
+fn jump_destinations(code: &[u8]) -> Vec<bool> {
+    let mut valid = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        match code[pc] {
+            0x5b => valid[pc] = true,
+            op @ 0x60..=0x7f => pc += (op - 0x5f) as usize,
+            _ => {},
+        }
+        pc += 1;
+    }
+    valid
+}
+
+fn to_h256(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H256(bytes)
+}
+
+fn to_address(value: U256) -> Address {
+    Address::from_slice(&to_h256(value)[12..])
+}
+
+fn to_u256(address: &Address) -> U256 {
+    U256::from_big_endian(address.as_bytes())
+}
+
+fn is_negative(value: &U256) -> bool {
+    value.bit(255)
+}
+
+fn negate(value: U256) -> U256 {
+    (!value).overflowing_add(U256::one()).0
+}
+
+fn abs(value: U256) -> U256 {
+    if is_negative(&value) { negate(value) } else { value }
+}
+
+fn low_u256(value: U512) -> U256 {
+    U256([value.0[0], value.0[1], value.0[2], value.0[3]])
+}
+
+fn bool_to_u256(value: bool) -> U256 {
+    if value { U256::one() } else { U256::zero() }
+}
+
+/// Converts a memory offset or size, anything above 4GB cannot be paid for anyway.
+fn to_usize(value: U256) -> Result<usize, Exit> {
+    if value > U256::from(u32::max_value()) {
+        return error("out of gas");
+    }
+    Ok(value.as_usize())
+}
+
+pub(super) fn words(len: usize) -> u64 {
+    (len as u64 + 31) / 32
+}
+
+fn memory_cost(words: u64) -> u64 {
+    3 * words + words * words / 512
+}
+
+/// Gas forwarded to a nested frame: all but one 64th of what is left.
+fn all_but_one_64th(gas: u64) -> u64 {
+    gas - gas / 64
+}
+
+/// Copies `len` bytes of `source` from `offset`, padding with zeros past its end.
+fn padded_slice(source: &[u8], offset: U256, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    if offset < U256::from(source.len()) {
+        let offset = offset.as_usize();
+        let available = std::cmp::min(len, source.len() - offset);
+        out[..available].copy_from_slice(&source[offset..offset + available]);
+    }
+    out
+}
+
+struct Machine {
+    stack: Vec<U256>,
+    memory: Vec<u8>,
+    gas: u64,
+    pc: usize,
+    return_data: Vec<u8>,
+    jump_destinations: Vec<bool>,
+}
+
+impl Machine {
+    fn new(ctx: &Context) -> Self {
+        Machine {
+            stack: Vec::with_capacity(STACK_LIMIT),
+            memory: Vec::new(),
+            gas: ctx.gas,
+            pc: 0,
+            return_data: Vec::new(),
+            jump_destinations: jump_destinations(&ctx.code),
+        }
+    }
+
+    fn charge(&mut self, cost: u64) -> Result<(), Exit> {
+        if cost > self.gas {
+            return error("out of gas");
+        }
+        self.gas -= cost;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<U256, Exit> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => error("stack underflow"),
+        }
+    }
+
+    fn push(&mut self, value: U256) -> Result<(), Exit> {
+        if self.stack.len() >= STACK_LIMIT {
+            return error("stack overflow");
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Charges for and performs memory expansion, returns the range as `usize`.
+    fn expand(&mut self, offset: U256, len: U256) -> Result<(usize, usize), Exit> {
+        if len.is_zero() {
+            return Ok((0, 0));
+        }
+        let offset = to_usize(offset)?;
+        let len = to_usize(len)?;
+        let end = offset + len;
+        let current = words(self.memory.len());
+        let required = words(end);
+        if required > current {
+            self.charge(memory_cost(required) - memory_cost(current))?;
+            self.memory.resize(required as usize * 32, 0);
+        }
+        Ok((offset, len))
+    }
+
+    fn read_memory(&mut self, offset: U256, len: U256) -> Result<Vec<u8>, Exit> {
+        let (offset, len) = self.expand(offset, len)?;
+        Ok(self.memory[offset..offset + len].to_vec())
+    }
+
+    fn write_memory(&mut self, offset: usize, data: &[u8]) {
+        self.memory[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Implements the `*COPY` instructions reading from `source`.
+    fn copy_to_memory(&mut self, source: &[u8]) -> Result<(), Exit> {
+        let (dest, offset, len) = (self.pop()?, self.pop()?, self.pop()?);
+        let (dest, len) = self.expand(dest, len)?;
+        self.charge(3 * words(len))?;
+        let data = padded_slice(source, offset, len);
+        self.write_memory(dest, &data);
+        Ok(())
+    }
+
+    fn step(&mut self, exec: &mut Executive, ctx: &Context) -> Result<(), Exit> {
+        let op = match ctx.code.get(self.pc) {
+            Some(op) => *op,
+            None => return Err(Exit::Halt(Halt::Return(Vec::new()))),
+        };
+        self.pc += 1;
+
+        match op {
+            0x00 => return Err(Exit::Halt(Halt::Return(Vec::new()))),
+
+            // Arithmetic
+            0x01 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a.overflowing_add(b).0)?; },
+            0x02 => { self.charge(5)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a.overflowing_mul(b).0)?; },
+            0x03 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a.overflowing_sub(b).0)?; },
+            0x04 => {
+                self.charge(5)?;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(if b.is_zero() { U256::zero() } else { a / b })?;
+            },
+            0x05 => {
+                self.charge(5)?;
+                let (a, b) = (self.pop()?, self.pop()?);
+                let result = if b.is_zero() {
+                    U256::zero()
+                } else {
+                    let quotient = abs(a) / abs(b);
+                    if is_negative(&a) != is_negative(&b) { negate(quotient) } else { quotient }
+                };
+                self.push(result)?;
+            },
+            0x06 => {
+                self.charge(5)?;
+                let (a, b) = (self.pop()?, self.pop()?);
+                self.push(if b.is_zero() { U256::zero() } else { a % b })?;
+            },
+            0x07 => {
+                self.charge(5)?;
+                let (a, b) = (self.pop()?, self.pop()?);
+                let result = if b.is_zero() {
+                    U256::zero()
+                } else {
+                    let remainder = abs(a) % abs(b);
+                    if is_negative(&a) { negate(remainder) } else { remainder }
+                };
+                self.push(result)?;
+            },
+            0x08 | 0x09 => {
+                self.charge(8)?;
+                let (a, b, n) = (self.pop()?, self.pop()?, self.pop()?);
+                let result = if n.is_zero() {
+                    U256::zero()
+                } else if op == 0x08 {
+                    low_u256((U512::from(a) + U512::from(b)) % U512::from(n))
+                } else {
+                    low_u256((U512::from(a) * U512::from(b)) % U512::from(n))
+                };
+                self.push(result)?;
+            },
+            0x0a => {
+                let (base, exponent) = (self.pop()?, self.pop()?);
+                let exponent_bytes = (exponent.bits() as u64 + 7) / 8;
+                self.charge(10 + 50 * exponent_bytes)?;
+                self.push(base.overflowing_pow(exponent).0)?;
+            },
+            0x0b => {
+                self.charge(5)?;
+                let (size, value) = (self.pop()?, self.pop()?);
+                let result = if size < U256::from(31) {
+                    let bit = size.as_usize() * 8 + 7;
+                    let mask = (U256::one() << bit) - U256::one();
+                    if value.bit(bit) { value | !mask } else { value & mask }
+                } else {
+                    value
+                };
+                self.push(result)?;
+            },
+
+            // Comparison and bitwise logic
+            0x10 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(bool_to_u256(a < b))?; },
+            0x11 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(bool_to_u256(a > b))?; },
+            0x12 | 0x13 => {
+                self.charge(3)?;
+                let (a, b) = (self.pop()?, self.pop()?);
+                let less = match (is_negative(&a), is_negative(&b)) {
+                    (true, false) => true,
+                    (false, true) => false,
+                    _ => a < b,
+                };
+                let result = if op == 0x12 { less } else { !less && a != b };
+                self.push(bool_to_u256(result))?;
+            },
+            0x14 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(bool_to_u256(a == b))?; },
+            0x15 => { self.charge(3)?; let a = self.pop()?; self.push(bool_to_u256(a.is_zero()))?; },
+            0x16 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a & b)?; },
+            0x17 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a | b)?; },
+            0x18 => { self.charge(3)?; let (a, b) = (self.pop()?, self.pop()?); self.push(a ^ b)?; },
+            0x19 => { self.charge(3)?; let a = self.pop()?; self.push(!a)?; },
+            0x1a => {
+                self.charge(3)?;
+                let (index, value) = (self.pop()?, self.pop()?);
+                let result = if index < U256::from(32) { U256::from(value.byte(31 - index.as_usize())) } else { U256::zero() };
+                self.push(result)?;
+            },
+            0x1b | 0x1c | 0x1d => {
+                self.charge(3)?;
+                let (shift, value) = (self.pop()?, self.pop()?);
+                let negative = is_negative(&value);
+                let result = if shift >= U256::from(256) {
+                    if op == 0x1d && negative { !U256::zero() } else { U256::zero() }
+                } else {
+                    let shift = shift.as_usize();
+                    match op {
+                        0x1b => value << shift,
+                        0x1c => value >> shift,
+                        _ if negative => !((!value) >> shift),
+                        _ => value >> shift,
+                    }
+                };
+                self.push(result)?;
+            },
+
+            0x20 => {
+                let (offset, len) = (self.pop()?, self.pop()?);
+                let data = self.read_memory(offset, len)?;
+                self.charge(30 + 6 * words(data.len()))?;
+                self.push(U256::from_big_endian(KeccakHasher::hash(&data).as_bytes()))?;
+            },
+
+            // Environment
+            0x30 => { self.charge(2)?; self.push(to_u256(&ctx.address))?; },
+            0x31 => {
+                self.charge(700)?;
+                let address = to_address(self.pop()?);
+                let balance = exec.overlay.balance(&address)?;
+                self.push(balance)?;
+            },
+            0x32 => { self.charge(2)?; self.push(to_u256(&exec.env.origin))?; },
+            0x33 => { self.charge(2)?; self.push(to_u256(&ctx.caller))?; },
+            0x34 => { self.charge(2)?; self.push(ctx.value)?; },
+            0x35 => {
+                self.charge(3)?;
+                let offset = self.pop()?;
+                self.push(U256::from_big_endian(&padded_slice(&ctx.input, offset, 32)))?;
+            },
+            0x36 => { self.charge(2)?; self.push(ctx.input.len().into())?; },
+            0x37 => { self.charge(3)?; self.copy_to_memory(&ctx.input)?; },
+            0x38 => { self.charge(2)?; self.push(ctx.code.len().into())?; },
+            0x39 => { self.charge(3)?; self.copy_to_memory(&ctx.code)?; },
+            0x3a => { self.charge(2)?; self.push(exec.env.gas_price)?; },
+            0x3b => {
+                self.charge(700)?;
+                let address = to_address(self.pop()?);
+                let len = exec.overlay.code(&address)?.len();
+                self.push(len.into())?;
+            },
+            0x3c => {
+                self.charge(700)?;
+                let address = to_address(self.pop()?);
+                let code = exec.overlay.code(&address)?;
+                self.copy_to_memory(&code)?;
+            },
+            0x3d => { self.charge(2)?; self.push(self.return_data.len().into())?; },
+            0x3e => {
+                self.charge(3)?;
+                let (dest, offset, len) = (self.pop()?, self.pop()?, self.pop()?);
+                let end = offset.overflowing_add(len);
+                if end.1 || end.0 > U256::from(self.return_data.len()) {
+                    return error("return data out of bounds");
+                }
+                let (dest, len) = self.expand(dest, len)?;
+                self.charge(3 * words(len))?;
+                let data = padded_slice(&self.return_data, offset, len);
+                self.write_memory(dest, &data);
+            },
+            0x3f => {
+                self.charge(700)?;
+                let address = to_address(self.pop()?);
+                let hash = exec.overlay.code_hash(&address)?;
+                self.push(U256::from_big_endian(hash.as_bytes()))?;
+            },
+
+            // Block information
+            0x40 => { self.charge(20)?; self.pop()?; self.push(U256::zero())?; },
+            0x41 => { self.charge(2)?; self.push(U256::zero())?; },
+            0x42 => { self.charge(2)?; self.push(exec.env.timestamp.into())?; },
+            0x43 => { self.charge(2)?; self.push(U256::zero())?; },
+            0x44 => { self.charge(2)?; self.push(U256::zero())?; },
+            0x45 => { self.charge(2)?; self.push(exec.env.gas_limit.into())?; },
+            0x46 => { self.charge(2)?; self.push(exec.env.chain_id)?; },
+            0x47 => {
+                self.charge(5)?;
+                let balance = exec.overlay.balance(&ctx.address)?;
+                self.push(balance)?;
+            },
+
+            // Stack, memory, storage and flow
+            0x50 => { self.charge(2)?; self.pop()?; },
+            0x51 => {
+                self.charge(3)?;
+                let offset = self.pop()?;
+                let data = self.read_memory(offset, 32.into())?;
+                self.push(U256::from_big_endian(&data))?;
+            },
+            0x52 => {
+                self.charge(3)?;
+                let (offset, value) = (self.pop()?, self.pop()?);
+                let (offset, _) = self.expand(offset, 32.into())?;
+                let mut word = [0u8; 32];
+                value.to_big_endian(&mut word);
+                self.write_memory(offset, &word);
+            },
+            0x53 => {
+                self.charge(3)?;
+                let (offset, value) = (self.pop()?, self.pop()?);
+                let (offset, _) = self.expand(offset, 1.into())?;
+                self.write_memory(offset, &[value.low_u32() as u8]);
+            },
+            0x54 => {
+                self.charge(800)?;
+                let slot = to_h256(self.pop()?);
+                let value = exec.overlay.storage(&ctx.address, &slot)?;
+                self.push(value)?;
+            },
+            0x55 => {
+                if ctx.is_static {
+                    return error("state modification in static call");
+                }
+                if self.gas <= CALL_STIPEND {
+                    return error("out of gas");
+                }
+                let (slot, value) = (to_h256(self.pop()?), self.pop()?);
+                let current = exec.overlay.storage(&ctx.address, &slot)?;
+                // EIP-2200: only the first change of a slot in a transaction pays in full.
+                let cost = if current == value {
+                    800
+                } else if exec.overlay.original_storage(&ctx.address, &slot)? == current {
+                    if current.is_zero() { 20000 } else { 5000 }
+                } else {
+                    800
+                };
+                self.charge(cost)?;
+                exec.overlay.set_storage(&ctx.address, slot, value)?;
+            },
+            0x56 => {
+                self.charge(8)?;
+                let dest = self.pop()?;
+                self.jump(dest)?;
+            },
+            0x57 => {
+                self.charge(10)?;
+                let (dest, condition) = (self.pop()?, self.pop()?);
+                if !condition.is_zero() {
+                    self.jump(dest)?;
+                }
+            },
+            0x58 => { self.charge(2)?; self.push((self.pc - 1).into())?; },
+            0x59 => { self.charge(2)?; self.push(self.memory.len().into())?; },
+            0x5a => { self.charge(2)?; self.push(self.gas.into())?; },
+            0x5b => { self.charge(1)?; },
+
+            0x60..=0x7f => {
+                self.charge(3)?;
+                let len = (op - 0x5f) as usize;
+                let value = U256::from_big_endian(&padded_slice(&ctx.code, self.pc.into(), len));
+                self.pc += len;
+                self.push(value)?;
+            },
+            0x80..=0x8f => {
+                self.charge(3)?;
+                let depth = (op - 0x7f) as usize;
+                if self.stack.len() < depth {
+                    return error("stack underflow");
+                }
+                let value = self.stack[self.stack.len() - depth];
+                self.push(value)?;
+            },
+            0x90..=0x9f => {
+                self.charge(3)?;
+                let depth = (op - 0x8f) as usize;
+                let len = self.stack.len();
+                if len <= depth {
+                    return error("stack underflow");
+                }
+                self.stack.swap(len - 1, len - 1 - depth);
+            },
+            0xa0..=0xa4 => {
+                if ctx.is_static {
+                    return error("state modification in static call");
+                }
+                let (offset, len) = (self.pop()?, self.pop()?);
+                let count = (op - 0xa0) as usize;
+                let mut topics = Vec::with_capacity(count);
+                for _ in 0..count {
+                    topics.push(to_h256(self.pop()?));
+                }
+                let data = self.read_memory(offset, len)?;
+                self.charge(375 + 375 * count as u64 + 8 * data.len() as u64)?;
+                exec.logs.push(Log { address: ctx.address, topics, data });
+            },
+
+            // System
+            0xf0 | 0xf5 => self.create(exec, ctx, op == 0xf5)?,
+            0xf1 | 0xf2 | 0xf4 | 0xfa => self.call(exec, ctx, op)?,
+            0xf3 | 0xfd => {
+                let (offset, len) = (self.pop()?, self.pop()?);
+                let data = self.read_memory(offset, len)?;
+                return Err(Exit::Halt(if op == 0xf3 { Halt::Return(data) } else { Halt::Revert(data) }));
+            },
+            0xff => {
+                if ctx.is_static {
+                    return error("state modification in static call");
+                }
+                let beneficiary = to_address(self.pop()?);
+                let balance = exec.overlay.balance(&ctx.address)?;
+                // Creating the beneficiary only costs extra when value is sent to it.
+                let cost = if !balance.is_zero() && !exec.overlay.exists(&beneficiary)? { 30000 } else { 5000 };
+                self.charge(cost)?;
+                exec.overlay.transfer(&ctx.address, &beneficiary, balance)?;
+                exec.overlay.destroy(&ctx.address);
+                return Err(Exit::Halt(Halt::Return(Vec::new())));
+            },
+            0xfe => return error("invalid instruction"),
+            _ => return error(&format!("undefined instruction 0x{:02x}", op)),
+        }
+        Ok(())
+    }
+
+    fn jump(&mut self, dest: U256) -> Result<(), Exit> {
+        if dest >= U256::from(self.jump_destinations.len()) || !self.jump_destinations[dest.as_usize()] {
+            return error("bad jump destination");
+        }
+        self.pc = dest.as_usize();
+        Ok(())
+    }
+
+    fn create(&mut self, exec: &mut Executive, ctx: &Context, create2: bool) -> Result<(), Exit> {
+        if ctx.is_static {
+            return error("state modification in static call");
+        }
+        let (value, offset, len) = (self.pop()?, self.pop()?, self.pop()?);
+        let salt = if create2 { Some(to_h256(self.pop()?)) } else { None };
+        let init_code = self.read_memory(offset, len)?;
+        self.charge(32000)?;
+        if create2 {
+            self.charge(6 * words(init_code.len()))?;
+        }
+        self.return_data.clear();
+
+        if ctx.depth + 1 > MAX_DEPTH || exec.overlay.balance(&ctx.address)? < value {
+            return self.push(U256::zero());
+        }
+        let nonce = exec.overlay.nonce(&ctx.address)?;
+        let address = match salt {
+            Some(salt) => create2_address(&ctx.address, &salt, &init_code),
+            None => contract_address(&ctx.address, &nonce),
+        };
+        exec.overlay.inc_nonce(&ctx.address)?;
+
+        let gas = all_but_one_64th(self.gas);
+        self.gas -= gas;
+        let result = exec.create(ctx.address, address, value, init_code, gas, ctx.depth + 1)?;
+        self.gas += result.gas_left;
+        match result.halt {
+            Halt::Return(_) => self.push(to_u256(&address)),
+            Halt::Revert(output) => {
+                self.return_data = output;
+                self.push(U256::zero())
+            },
+            Halt::Error(_) => self.push(U256::zero()),
+        }
+    }
+
+    fn call(&mut self, exec: &mut Executive, ctx: &Context, op: u8) -> Result<(), Exit> {
+        let requested = self.pop()?;
+        let to = to_address(self.pop()?);
+        let value = match op {
+            0xf1 | 0xf2 => self.pop()?,
+            _ => U256::zero(),
+        };
+        let (in_offset, in_len, out_offset, out_len) = (self.pop()?, self.pop()?, self.pop()?, self.pop()?);
+        if op == 0xf1 && ctx.is_static && !value.is_zero() {
+            return error("state modification in static call");
+        }
+
+        let input = self.read_memory(in_offset, in_len)?;
+        let (out_offset, out_len) = self.expand(out_offset, out_len)?;
+        let mut cost = 700;
+        if !value.is_zero() {
+            cost += 9000;
+            if op == 0xf1 && !exec.overlay.exists(&to)? {
+                cost += 25000;
+            }
+        }
+        self.charge(cost)?;
+
+        let available = all_but_one_64th(self.gas);
+        let gas = if requested > U256::from(available) { available } else { requested.as_u64() };
+        self.gas -= gas;
+        let stipend = if value.is_zero() { 0 } else { CALL_STIPEND };
+        self.return_data.clear();
+
+        if ctx.depth + 1 > MAX_DEPTH || exec.overlay.balance(&ctx.address)? < value {
+            self.gas += gas;
+            return self.push(U256::zero());
+        }
+
+        let params = match op {
+            0xf1 => CallParams {
+                caller: ctx.address, address: to, code_address: to, value, transfer: true,
+                input, gas: gas + stipend, is_static: ctx.is_static, depth: ctx.depth + 1,
+            },
+            0xf2 => CallParams {
+                caller: ctx.address, address: ctx.address, code_address: to, value, transfer: true,
+                input, gas: gas + stipend, is_static: ctx.is_static, depth: ctx.depth + 1,
+            },
+            0xf4 => CallParams {
+                caller: ctx.caller, address: ctx.address, code_address: to, value: ctx.value, transfer: false,
+                input, gas, is_static: ctx.is_static, depth: ctx.depth + 1,
+            },
+            _ => CallParams {
+                caller: ctx.address, address: to, code_address: to, value: U256::zero(), transfer: false,
+                input, gas, is_static: true, depth: ctx.depth + 1,
+            },
+        };
+        let result = exec.call(params)?;
+        self.gas += result.gas_left;
+
+        let (success, output) = match result.halt {
+            Halt::Return(output) => (true, output),
+            Halt::Revert(output) => (false, output),
+            Halt::Error(_) => (false, Vec::new()),
+        };
+        let copied = std::cmp::min(out_len, output.len());
+        self.write_memory(out_offset, &output[..copied]);
+        self.return_data = output;
+        self.push(bool_to_u256(success))
+    }
+}
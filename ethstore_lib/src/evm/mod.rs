@@ -0,0 +1,477 @@
+//! Embedded EVM used by `bloom-cmd contract`.
+//!
+//! Transactions run against a `StateDB` through an in-memory overlay and their effects
+//! are applied as a single state update once they finish. The interpreter follows the
+//! Istanbul instruction set and gas schedule with three simplifications: there is no
+//! block chain behind it (`BLOCKHASH` is always zero, the block number is zero), gas
+//! refunds for clearing storage are not paid out and only the precompiled contracts
+//! `0x01` to `0x04` are available, calling `0x05` to `0x09` aborts the transaction.
+//! Gas fees are burned.
+
+mod interpreter;
+mod overlay;
+mod precompiles;
+
+use std::{fmt, time};
+
+use ethereum_types::{Address, H256, U256};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+use rlp::RlpStream;
+
+use crate::state::{self, StateDB};
+use self::interpreter::{Context, FrameResult, Halt};
+use self::overlay::Overlay;
+
+/// Maximum nesting of calls and creates.
+const MAX_DEPTH: usize = 1024;
+/// Maximum size of deployed code (EIP-170).
+const MAX_CODE_SIZE: usize = 24576;
+
+/// Message call or contract creation.
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub from: Address,
+    /// Called address, `None` deploys `data` as init code.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: u64,
+    pub gas_price: U256,
+    pub data: Vec<u8>,
+    /// Value returned by `CHAINID`.
+    pub chain_id: u64,
+}
+
+/// Log entry emitted with `LOG0`..`LOG4`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
+}
+
+/// How the execution ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Success,
+    /// Execution ran `REVERT`, unused gas was returned.
+    Revert,
+    /// Execution failed with an exceptional halt, all gas was consumed.
+    Failure(String),
+}
+
+/// Result of a transaction.
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub status: Status,
+    /// Returned data, or the deployed code for a successful creation.
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    pub logs: Vec<Log>,
+    /// Address of the created contract.
+    pub contract_address: Option<Address>,
+    /// Message of a Solidity `Error(string)` revert.
+    pub revert_reason: Option<String>,
+}
+
+/// Errors that prevent a transaction from being executed at all.
+#[derive(Debug)]
+pub enum Error {
+    State(state::Error),
+    /// Gas limit below the intrinsic cost of the transaction.
+    IntrinsicGas { required: u64, provided: u64 },
+    /// Call to a precompiled contract the interpreter does not implement.
+    UnsupportedPrecompile(u8),
+}
+
+impl From<state::Error> for Error {
+    fn from(err: state::Error) -> Self {
+        Error::State(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::State(ref err) => fmt::Display::fmt(err, f),
+            Error::IntrinsicGas { required, provided } =>
+                write!(f, "Gas limit {} is below the intrinsic transaction cost {}", provided, required),
+            Error::UnsupportedPrecompile(index) =>
+                write!(f, "Precompiled contract 0x{:02x} is not supported", index),
+        }
+    }
+}
+
+/// Block values visible to contracts.
+#[derive(Debug, Clone)]
+struct Env {
+    origin: Address,
+    gas_price: U256,
+    gas_limit: u64,
+    timestamp: u64,
+    chain_id: U256,
+}
+
+/// Address of a contract created by `sender` with given nonce.
+pub fn contract_address(sender: &Address, nonce: &U256) -> Address {
+    let mut stream = RlpStream::new_list(2);
+    stream.append(sender);
+    stream.append(nonce);
+    Address::from_slice(&KeccakHasher::hash(&stream.out())[12..])
+}
+
+/// Address of a contract created with `CREATE2`.
+fn create2_address(sender: &Address, salt: &H256, init_code: &[u8]) -> Address {
+    let mut buffer = Vec::with_capacity(85);
+    buffer.push(0xff);
+    buffer.extend_from_slice(sender.as_bytes());
+    buffer.extend_from_slice(salt.as_bytes());
+    buffer.extend_from_slice(KeccakHasher::hash(init_code).as_bytes());
+    Address::from_slice(&KeccakHasher::hash(&buffer)[12..])
+}
+
+/// Decodes the message of a Solidity `Error(string)` revert.
+pub fn revert_reason(output: &[u8]) -> Option<String> {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    if output.len() < 68 || output[..4] != ERROR_SELECTOR {
+        return None;
+    }
+    let data = &output[4..];
+    let offset = U256::from_big_endian(&data[..32]);
+    if offset > U256::from(data.len()) {
+        return None;
+    }
+    let offset = offset.as_usize();
+    let len = U256::from_big_endian(data.get(offset..offset + 32)?);
+    if len > U256::from(data.len()) {
+        return None;
+    }
+    let start = offset + 32;
+    let bytes = data.get(start..start + len.as_usize())?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+fn intrinsic_gas(tx: &Transaction) -> u64 {
+    let data: u64 = tx.data.iter().map(|b| if *b == 0 { 4 } else { 16 }).sum();
+    let create = if tx.to.is_none() { 32000 } else { 0 };
+    21000 + create + data
+}
+
+/// Executes the transaction and applies its effects to the state.
+///
+/// The sender must exist and afford `value + gas * gas_price`. Failed or reverted
+/// executions still increase the sender nonce and charge the used gas.
+pub fn transact(state: &mut StateDB, tx: &Transaction) -> Result<Receipt, Error> {
+    let sender = state.existing_account(&tx.from)?;
+    let required = intrinsic_gas(tx);
+    if tx.gas < required {
+        return Err(Error::IntrinsicGas { required, provided: tx.gas });
+    }
+    let upfront = U256::from(tx.gas).checked_mul(tx.gas_price).ok_or(state::Error::Overflow)?;
+    let total = upfront.checked_add(tx.value).ok_or(state::Error::Overflow)?;
+    if sender.balance < total {
+        return Err(state::Error::InsufficientBalance { address: tx.from, balance: sender.balance, required: total }.into());
+    }
+
+    let env = Env {
+        origin: tx.from,
+        gas_price: tx.gas_price,
+        gas_limit: tx.gas,
+        timestamp: time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        chain_id: tx.chain_id.into(),
+    };
+    let mut executive = Executive {
+        overlay: Overlay::new(state),
+        env,
+        logs: Vec::new(),
+    };
+    executive.overlay.sub_balance(&tx.from, upfront)?;
+    executive.overlay.inc_nonce(&tx.from)?;
+
+    let gas = tx.gas - required;
+    let (result, contract_address) = match tx.to {
+        None => {
+            let address = contract_address(&tx.from, &sender.nonce);
+            (executive.create(tx.from, address, tx.value, tx.data.clone(), gas, 0)?, Some(address))
+        },
+        Some(to) => (executive.call(CallParams {
+            caller: tx.from,
+            address: to,
+            code_address: to,
+            value: tx.value,
+            transfer: true,
+            input: tx.data.clone(),
+            gas,
+            is_static: false,
+            depth: 0,
+        })?, None),
+    };
+
+    executive.overlay.add_balance(&tx.from, U256::from(result.gas_left) * tx.gas_price)?;
+    let gas_used = tx.gas - result.gas_left;
+    let (status, output) = match result.halt {
+        Halt::Return(output) => (Status::Success, output),
+        Halt::Revert(output) => (Status::Revert, output),
+        Halt::Error(msg) => (Status::Failure(msg), Vec::new()),
+    };
+    let logs = executive.logs;
+    let updates = executive.overlay.into_updates();
+    state.apply(&updates)?;
+
+    Ok(Receipt {
+        revert_reason: if status == Status::Revert { revert_reason(&output) } else { None },
+        contract_address: if status == Status::Success { contract_address } else { None },
+        status,
+        output,
+        gas_used,
+        logs,
+    })
+}
+
+/// Parameters of a message call frame.
+struct CallParams {
+    caller: Address,
+    /// Account whose storage and balance the code runs against.
+    address: Address,
+    /// Account the code is loaded from.
+    code_address: Address,
+    value: U256,
+    /// Whether `value` moves from caller to address, false for `DELEGATECALL`.
+    transfer: bool,
+    input: Vec<u8>,
+    gas: u64,
+    is_static: bool,
+    depth: usize,
+}
+
+/// Runs frames and keeps what they change.
+struct Executive<'a> {
+    overlay: Overlay<'a>,
+    env: Env,
+    logs: Vec<Log>,
+}
+
+impl<'a> Executive<'a> {
+    fn snapshot(&self) -> (Overlay<'a>, usize) {
+        (self.overlay.clone(), self.logs.len())
+    }
+
+    fn revert(&mut self, (overlay, logs): (Overlay<'a>, usize)) {
+        self.overlay = overlay;
+        self.logs.truncate(logs);
+    }
+
+    fn call(&mut self, params: CallParams) -> Result<FrameResult, Error> {
+        let snapshot = self.snapshot();
+        if params.transfer && !self.overlay.transfer(&params.caller, &params.address, params.value)? {
+            self.revert(snapshot);
+            return Ok(FrameResult { halt: Halt::Error("insufficient balance for transfer".into()), gas_left: params.gas });
+        }
+        let result = match precompiles::index(&params.code_address) {
+            Some(index) => precompiles::run(index, &params.input, params.gas)?,
+            None => {
+                let code = self.overlay.code(&params.code_address)?;
+                if code.is_empty() {
+                    return Ok(FrameResult { halt: Halt::Return(Vec::new()), gas_left: params.gas });
+                }
+                interpreter::run(self, Context {
+                    address: params.address,
+                    caller: params.caller,
+                    value: params.value,
+                    input: params.input,
+                    code,
+                    gas: params.gas,
+                    is_static: params.is_static,
+                    depth: params.depth,
+                })?
+            },
+        };
+        if let Halt::Return(_) = result.halt {} else {
+            self.revert(snapshot);
+        }
+        Ok(result)
+    }
+
+    fn create(&mut self, caller: Address, address: Address, value: U256, init_code: Vec<u8>, gas: u64, depth: usize) -> Result<FrameResult, Error> {
+        let snapshot = self.snapshot();
+        if !self.overlay.nonce(&address)?.is_zero() || !self.overlay.code(&address)?.is_empty() {
+            return Ok(FrameResult { halt: Halt::Error("contract address collision".into()), gas_left: 0 });
+        }
+        self.overlay.reset(&address)?;
+        if !self.overlay.transfer(&caller, &address, value)? {
+            self.revert(snapshot);
+            return Ok(FrameResult { halt: Halt::Error("insufficient balance for transfer".into()), gas_left: gas });
+        }
+
+        let result = interpreter::run(self, Context {
+            address,
+            caller,
+            value,
+            input: Vec::new(),
+            code: init_code,
+            gas,
+            is_static: false,
+            depth,
+        })?;
+        let result = match result.halt {
+            Halt::Return(code) => {
+                let deposit = 200 * code.len() as u64;
+                if code.len() > MAX_CODE_SIZE {
+                    FrameResult { halt: Halt::Error("contract code size limit exceeded".into()), gas_left: 0 }
+                } else if deposit > result.gas_left {
+                    FrameResult { halt: Halt::Error("out of gas paying code deposit".into()), gas_left: 0 }
+                } else {
+                    self.overlay.set_code(&address, code.clone())?;
+                    FrameResult { halt: Halt::Return(code), gas_left: result.gas_left - deposit }
+                }
+            },
+            halt => FrameResult { halt, gas_left: result.gas_left },
+        };
+        if let Halt::Return(_) = result.halt {} else {
+            self.revert(snapshot);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deploy(state: &mut StateDB, from: Address, code: &str) -> Receipt {
+        transact(state, &Transaction {
+            from,
+            to: None,
+            value: U256::zero(),
+            gas: 1_000_000,
+            gas_price: U256::one(),
+            data: rustc_hex::FromHex::from_hex(code).unwrap(),
+            chain_id: 1,
+        }).unwrap()
+    }
+
+    #[test]
+    fn should_compute_create_address() {
+        let sender: Address = "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap();
+        assert_eq!(contract_address(&sender, &U256::zero()), "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d".parse().unwrap());
+        assert_eq!(contract_address(&sender, &U256::one()), "343c43a37d37dff08ae8c4a11544c718abb4fcf8".parse().unwrap());
+    }
+
+    #[test]
+    fn should_deploy_and_call_contract() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+
+        // Runtime: SSTORE(0, CALLDATALOAD(0)); LOG1(0, 0, topic = 0x01); return SLOAD(0).
+        let runtime = "600035600055600160006000a160005460005260206000f3";
+        // Init: copy runtime to memory and return it.
+        let init = format!("6018600c60003960186000f3{}", runtime);
+        let receipt = deploy(&mut state, from, &init);
+        assert_eq!(receipt.status, Status::Success);
+        let contract = receipt.contract_address.unwrap();
+        assert_eq!(contract, contract_address(&from, &U256::zero()));
+        assert_eq!(state.code(&contract).unwrap(), rustc_hex::FromHex::from_hex::<Vec<u8>>(runtime).unwrap());
+        assert_eq!(state.existing_account(&from).unwrap().nonce, U256::one());
+
+        let mut input = vec![0u8; 32];
+        input[31] = 42;
+        let receipt = transact(&mut state, &Transaction {
+            from,
+            to: Some(contract),
+            value: U256::zero(),
+            gas: 100_000,
+            gas_price: U256::one(),
+            data: input.clone(),
+            chain_id: 1,
+        }).unwrap();
+        assert_eq!(receipt.status, Status::Success);
+        assert_eq!(receipt.output, input);
+        assert_eq!(receipt.logs, vec![Log { address: contract, topics: vec![H256::from_low_u64_be(1)], data: Vec::new() }]);
+        assert_eq!(state.storage(&contract).unwrap(), vec![(H256::zero(), 42.into())]);
+    }
+
+    #[test]
+    fn should_revert_with_reason() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+
+        // Error("no") encoded in memory, then REVERT(0, 100).
+        let init = "7f08c379a000000000000000000000000000000000000000000000000000000000600052\
+                    7f00000020000000000000000000000000000000000000000000000000000000006020527f000000026e6f0000000000000000000000000000000000000000000000000000604052\
+                    60646000fd";
+        let receipt = deploy(&mut state, from, init);
+        assert_eq!(receipt.status, Status::Revert);
+        assert_eq!(receipt.revert_reason, Some("no".into()));
+        assert_eq!(receipt.contract_address, None);
+        assert!(receipt.gas_used < 1_000_000);
+        assert_eq!(state.account(&contract_address(&from, &U256::zero())).unwrap(), None);
+    }
+
+    #[test]
+    fn should_meter_repeated_sstore_by_net_gas() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+
+        // SSTORE(0, 1); SSTORE(0, 2): 20000 for the first write, 800 for the second.
+        let receipt = deploy(&mut state, from, "60016000556002600055");
+        assert_eq!(receipt.status, Status::Success);
+        assert_eq!(receipt.gas_used, 21000 + 32000 + 8 * 16 + 2 * 4 + 4 * 3 + 20000 + 800);
+    }
+
+    #[test]
+    fn should_not_charge_account_creation_for_empty_selfdestruct() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+
+        // SELFDESTRUCT(2) without balance, the beneficiary does not exist.
+        let receipt = deploy(&mut state, from, "6002ff");
+        assert_eq!(receipt.status, Status::Success);
+        assert_eq!(receipt.gas_used, 21000 + 32000 + 3 * 16 + 3 + 5000);
+    }
+
+    #[test]
+    fn should_call_precompiled_contracts() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+        let call = |to: u64| Transaction {
+            from,
+            to: Some(Address::from_low_u64_be(to)),
+            value: U256::zero(),
+            gas: 100_000,
+            gas_price: U256::one(),
+            data: b"abc".to_vec(),
+            chain_id: 1,
+        };
+
+        let receipt = transact(&mut state, &call(4)).unwrap();
+        assert_eq!(receipt.status, Status::Success);
+        assert_eq!(receipt.output, b"abc".to_vec());
+        assert_eq!(receipt.gas_used, 21000 + 3 * 16 + 15 + 3);
+        assert!(match transact(&mut state, &call(5)) { Err(Error::UnsupportedPrecompile(5)) => true, _ => false });
+    }
+
+    #[test]
+    fn should_return_transaction_chain_id() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        state.create_account(from, 10_000_000.into(), 0.into()).unwrap();
+
+        // Returns MSTORE(0, CHAINID) as the contract code.
+        let receipt = transact(&mut state, &Transaction {
+            from,
+            to: None,
+            value: U256::zero(),
+            gas: 1_000_000,
+            gas_price: U256::one(),
+            data: rustc_hex::FromHex::from_hex("4660005260206000f3").unwrap(),
+            chain_id: 5,
+        }).unwrap();
+        assert_eq!(receipt.status, Status::Success);
+        assert_eq!(U256::from_big_endian(&receipt.output), 5.into());
+    }
+}
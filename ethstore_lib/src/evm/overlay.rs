@@ -0,0 +1,206 @@
+//! Account changes made by a transaction, kept in memory until it finishes.
+
+use std::collections::{HashMap, HashSet};
+
+use ethereum_types::{Address, H256, U256};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+
+use crate::state::{AccountUpdate, Error, StateDB, KECCAK_NULL_RLP};
+
+#[derive(Debug, Clone)]
+struct CachedAccount {
+    nonce: U256,
+    balance: U256,
+    code: Vec<u8>,
+    code_changed: bool,
+    /// Root the storage is read from when a slot is not cached.
+    storage_root: H256,
+    reset_storage: bool,
+    storage: HashMap<H256, U256>,
+    dirty: bool,
+}
+
+impl CachedAccount {
+    fn empty() -> Self {
+        CachedAccount {
+            nonce: U256::zero(),
+            balance: U256::zero(),
+            code: Vec::new(),
+            code_changed: false,
+            storage_root: KECCAK_NULL_RLP,
+            reset_storage: false,
+            storage: HashMap::new(),
+            dirty: true,
+        }
+    }
+}
+
+/// Copy-on-write view of the state. Cloning it takes a snapshot.
+#[derive(Clone)]
+pub struct Overlay<'a> {
+    state: &'a StateDB,
+    accounts: HashMap<Address, Option<CachedAccount>>,
+    destroyed: HashSet<Address>,
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(state: &'a StateDB) -> Self {
+        Overlay {
+            state,
+            accounts: HashMap::new(),
+            destroyed: HashSet::new(),
+        }
+    }
+
+    fn load(&mut self, address: &Address) -> Result<&mut Option<CachedAccount>, Error> {
+        if !self.accounts.contains_key(address) {
+            let cached = match self.state.account(address)? {
+                Some(account) => Some(CachedAccount {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    code: self.state.code(address)?,
+                    code_changed: false,
+                    storage_root: account.storage_root,
+                    reset_storage: false,
+                    storage: HashMap::new(),
+                    dirty: false,
+                }),
+                None => None,
+            };
+            self.accounts.insert(*address, cached);
+        }
+        Ok(self.accounts.get_mut(address).expect("inserted above; qed"))
+    }
+
+    fn account_mut(&mut self, address: &Address) -> Result<&mut CachedAccount, Error> {
+        let account = self.load(address)?.get_or_insert_with(CachedAccount::empty);
+        account.dirty = true;
+        Ok(account)
+    }
+
+    pub fn exists(&mut self, address: &Address) -> Result<bool, Error> {
+        Ok(self.load(address)?.is_some())
+    }
+
+    pub fn balance(&mut self, address: &Address) -> Result<U256, Error> {
+        Ok(self.load(address)?.as_ref().map_or_else(U256::zero, |a| a.balance))
+    }
+
+    pub fn nonce(&mut self, address: &Address) -> Result<U256, Error> {
+        Ok(self.load(address)?.as_ref().map_or_else(U256::zero, |a| a.nonce))
+    }
+
+    pub fn code(&mut self, address: &Address) -> Result<Vec<u8>, Error> {
+        Ok(self.load(address)?.as_ref().map_or_else(Vec::new, |a| a.code.clone()))
+    }
+
+    /// Code hash as seen by `EXTCODEHASH`: zero for accounts that do not exist.
+    pub fn code_hash(&mut self, address: &Address) -> Result<H256, Error> {
+        Ok(self.load(address)?.as_ref().map_or_else(H256::zero, |a| KeccakHasher::hash(&a.code)))
+    }
+
+    pub fn add_balance(&mut self, address: &Address, value: U256) -> Result<(), Error> {
+        let account = self.account_mut(address)?;
+        account.balance = account.balance.checked_add(value).ok_or(Error::Overflow)?;
+        Ok(())
+    }
+
+    /// Moves value between accounts, returns `false` if the sender cannot afford it.
+    pub fn transfer(&mut self, from: &Address, to: &Address, value: U256) -> Result<bool, Error> {
+        if self.balance(from)? < value {
+            return Ok(false);
+        }
+        self.account_mut(from)?.balance -= value;
+        self.add_balance(to, value)?;
+        Ok(true)
+    }
+
+    pub fn sub_balance(&mut self, address: &Address, value: U256) -> Result<(), Error> {
+        let account = self.account_mut(address)?;
+        account.balance = account.balance.checked_sub(value).ok_or(Error::Overflow)?;
+        Ok(())
+    }
+
+    pub fn inc_nonce(&mut self, address: &Address) -> Result<(), Error> {
+        let account = self.account_mut(address)?;
+        account.nonce = account.nonce.checked_add(U256::one()).ok_or(Error::Overflow)?;
+        Ok(())
+    }
+
+    /// Prepares a fresh contract account, keeping any balance sent to the address before.
+    pub fn reset(&mut self, address: &Address) -> Result<(), Error> {
+        let account = self.account_mut(address)?;
+        let balance = account.balance;
+        *account = CachedAccount::empty();
+        account.balance = balance;
+        account.nonce = U256::one();
+        account.reset_storage = true;
+        account.code_changed = true;
+        Ok(())
+    }
+
+    pub fn set_code(&mut self, address: &Address, code: Vec<u8>) -> Result<(), Error> {
+        let account = self.account_mut(address)?;
+        account.code = code;
+        account.code_changed = true;
+        Ok(())
+    }
+
+    pub fn storage(&mut self, address: &Address, slot: &H256) -> Result<U256, Error> {
+        let state = self.state;
+        let account = match self.load(address)? {
+            Some(account) => account,
+            None => return Ok(U256::zero()),
+        };
+        if let Some(value) = account.storage.get(slot) {
+            return Ok(*value);
+        }
+        let value = if account.reset_storage { U256::zero() } else { state.storage_at(account.storage_root, slot)? };
+        account.storage.insert(*slot, value);
+        Ok(value)
+    }
+
+    /// Value of the slot before the transaction started, as used by `SSTORE` gas metering.
+    pub fn original_storage(&self, address: &Address, slot: &H256) -> Result<U256, Error> {
+        match self.state.account(address)? {
+            Some(account) => self.state.storage_at(account.storage_root, slot),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    pub fn set_storage(&mut self, address: &Address, slot: H256, value: U256) -> Result<(), Error> {
+        self.account_mut(address)?.storage.insert(slot, value);
+        Ok(())
+    }
+
+    /// Schedules the account for removal at the end of the transaction.
+    pub fn destroy(&mut self, address: &Address) {
+        self.destroyed.insert(*address);
+    }
+
+    /// Account updates to apply to the state.
+    pub fn into_updates(self) -> Vec<(Address, Option<AccountUpdate>)> {
+        let destroyed = self.destroyed;
+        let mut updates: Vec<_> = self.accounts.into_iter()
+            .filter_map(|(address, account)| {
+                if destroyed.contains(&address) {
+                    return Some((address, None));
+                }
+                let account = account?;
+                if !account.dirty {
+                    return None;
+                }
+                Some((address, Some(AccountUpdate {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    code: if account.code_changed { Some(account.code) } else { None },
+                    reset_storage: account.reset_storage,
+                    storage: account.storage.into_iter().collect(),
+                })))
+            })
+            .collect();
+        updates.sort_by(|a, b| a.0.cmp(&b.0));
+        updates
+    }
+}
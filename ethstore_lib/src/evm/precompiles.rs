@@ -0,0 +1,115 @@
+//! Precompiled contracts at addresses `0x01` to `0x09`.
+
+use ethereum_types::{Address, H256};
+use parity_crypto::digest;
+use parity_crypto::publickey::{public_to_address, recover, Signature};
+use sha2::{Digest, Sha256};
+
+use super::interpreter::{words, FrameResult, Halt};
+use super::Error;
+
+/// Number of the precompiled contract at `address`, if there is one.
+pub(super) fn index(address: &Address) -> Option<u8> {
+    let bytes = address.as_bytes();
+    match bytes[19] {
+        1..=9 if bytes[..19].iter().all(|b| *b == 0) => Some(bytes[19]),
+        _ => None,
+    }
+}
+
+/// Runs precompiled contract `index`.
+///
+/// Only `ecrecover`, `sha256`, `ripemd160` and `identity` are implemented, calling any
+/// other precompiled contract aborts the transaction.
+pub(super) fn run(index: u8, input: &[u8], gas: u64) -> Result<FrameResult, Error> {
+    let cost = match index {
+        1 => 3000,
+        2 => 60 + 12 * words(input.len()),
+        3 => 600 + 120 * words(input.len()),
+        4 => 15 + 3 * words(input.len()),
+        _ => return Err(Error::UnsupportedPrecompile(index)),
+    };
+    if cost > gas {
+        return Ok(FrameResult { halt: Halt::Error("out of gas".into()), gas_left: 0 });
+    }
+    let output = match index {
+        1 => ecrecover(input),
+        2 => Sha256::digest(input).to_vec(),
+        3 => left_pad(&digest::ripemd160(input)),
+        _ => input.to_vec(),
+    };
+    Ok(FrameResult { halt: Halt::Return(output), gas_left: gas - cost })
+}
+
+fn left_pad(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; 32 - bytes.len()];
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Recovers the signer of `hash, v, r, s`, returns nothing for an invalid signature.
+fn ecrecover(input: &[u8]) -> Vec<u8> {
+    let mut data = [0u8; 128];
+    let len = std::cmp::min(input.len(), data.len());
+    data[..len].copy_from_slice(&input[..len]);
+
+    let v = &data[32..64];
+    if v[..31].iter().any(|b| *b != 0) || (v[31] != 27 && v[31] != 28) {
+        return Vec::new();
+    }
+    let signature = Signature::from_rsv(&H256::from_slice(&data[64..96]), &H256::from_slice(&data[96..]), v[31] - 27);
+    if !signature.is_valid() {
+        return Vec::new();
+    }
+    match recover(&signature, &H256::from_slice(&data[..32])) {
+        Ok(public) => left_pad(public_to_address(&public).as_bytes()),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hex::{FromHex, ToHex};
+
+    fn output(index: u8, input: &[u8]) -> Vec<u8> {
+        match run(index, input, 1_000_000).unwrap().halt {
+            Halt::Return(output) => output,
+            halt => panic!("unexpected halt {:?}", halt),
+        }
+    }
+
+    #[test]
+    fn should_find_precompiled_contracts() {
+        assert_eq!(index(&Address::from_low_u64_be(1)), Some(1));
+        assert_eq!(index(&Address::from_low_u64_be(9)), Some(9));
+        assert_eq!(index(&Address::from_low_u64_be(10)), None);
+        assert_eq!(index(&Address::from_low_u64_be(0x0100_0000_0001)), None);
+    }
+
+    #[test]
+    fn should_hash_input() {
+        assert_eq!(output(2, b"").to_hex::<String>(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(output(3, b"").to_hex::<String>(), "0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31");
+        assert_eq!(output(4, b"abc"), b"abc".to_vec());
+    }
+
+    #[test]
+    fn should_recover_signer() {
+        let input: Vec<u8> = "38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e\
+                              000000000000000000000000000000000000000000000000000000000000001b\
+                              38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e\
+                              789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02".from_hex().unwrap();
+        assert_eq!(output(1, &input).to_hex::<String>(), "000000000000000000000000ceaccac640adf55b2028469bd36ba501f28b699d");
+        assert_eq!(output(1, &input[..64]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn should_charge_gas_and_reject_unsupported_contracts() {
+        let result = run(4, &[0u8; 33], 21).unwrap();
+        assert_eq!(result.gas_left, 0);
+        assert!(match result.halt { Halt::Return(_) => true, _ => false });
+        assert!(match run(4, &[0u8; 33], 20).unwrap().halt { Halt::Error(_) => true, _ => false });
+        assert!(match run(5, &[], 1_000_000) { Err(Error::UnsupportedPrecompile(5)) => true, _ => false });
+    }
+}
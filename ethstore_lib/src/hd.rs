@@ -0,0 +1,237 @@
+//! BIP-39 mnemonics and BIP-32/BIP-44 hierarchical deterministic keys.
+//!
+//! Keys derived here match what hardware and software wallets produce for the same
+//! mnemonic, passphrase and path. Only the English word list is supported.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use ethereum_types::{H256, U256};
+use hmac::{Hmac, Mac};
+use parity_crypto::publickey::{Address, KeyPair, Secret};
+use sha2::Sha512;
+
+use crate::ethstore_rely::Error;
+
+/// BIP-44 path of the first Ethereum account.
+pub const DEFAULT_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// Index bit marking hardened derivation.
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Order of the secp256k1 group.
+const CURVE_ORDER: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+/// Generates an English mnemonic of 12, 15, 18, 21 or 24 words from the system RNG.
+pub fn generate_mnemonic(words: usize) -> Result<Mnemonic, Error> {
+    let kind = MnemonicType::for_word_count(words)
+        .map_err(|_| Error::InvalidInput(format!("mnemonic must have 12, 15, 18, 21 or 24 words, not {}", words)))?;
+    Ok(Mnemonic::new(kind, Language::English))
+}
+
+/// Validates the phrase, including its checksum. Case and extra whitespace are ignored.
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, Error> {
+    Mnemonic::from_phrase(&phrase.to_lowercase(), Language::English)
+        .map_err(|e| Error::InvalidInput(format!("mnemonic: {}", e)))
+}
+
+/// BIP-39 seed of the mnemonic with an optional passphrase (empty for none).
+pub fn seed(mnemonic: &Mnemonic, passphrase: &str) -> Vec<u8> {
+    Seed::new(mnemonic, passphrase).as_bytes().to_vec()
+}
+
+/// BIP-32 path such as `m/44'/60'/0'/0/0`. Hardened indexes are written with `'` or `h`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Same path with the last index increased by `offset`, used to walk BIP-44 addresses.
+    pub fn offset(&self, offset: u32) -> Result<Self, Error> {
+        let mut indexes = self.0.clone();
+        let last = indexes.last_mut().ok_or_else(|| Error::InvalidInput("path 'm' has no index to increase".into()))?;
+        let hardened = *last & HARDENED;
+        let index = (*last & !HARDENED).checked_add(offset).filter(|i| i & HARDENED == 0)
+            .ok_or_else(|| Error::InvalidInput(format!("path {} runs out of indexes", self)))?;
+        *last = index | hardened;
+        Ok(DerivationPath(indexes))
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidInput(format!("'{}' is not a derivation path like {}", value, DEFAULT_PATH));
+        let mut parts = value.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+        parts
+            .map(|part| {
+                let (digits, hardened) = match part.chars().last() {
+                    Some('\'') | Some('h') | Some('H') => (&part[..part.len() - 1], HARDENED),
+                    _ => (part, 0),
+                };
+                match digits.parse::<u32>() {
+                    Ok(index) if index & HARDENED == 0 && !digits.starts_with('+') => Ok(index | hardened),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            match index & HARDENED {
+                0 => write!(f, "/{}", index)?,
+                _ => write!(f, "/{}'", index & !HARDENED)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// BIP-32 extended private key.
+#[derive(Debug, Clone)]
+pub struct ExtendedSecret {
+    secret: Secret,
+    chain_code: H256,
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> (H256, H256) {
+    let mut mac = Hmac::<Sha512>::new_varkey(key).expect("HMAC accepts keys of any length; qed");
+    mac.input(data);
+    let result = mac.result().code();
+    (H256::from_slice(&result[..32]), H256::from_slice(&result[32..]))
+}
+
+fn invalid_key() -> Error {
+    Error::InvalidInput("derived key is invalid, use another index".into())
+}
+
+impl ExtendedSecret {
+    /// Master key of the seed.
+    pub fn master(seed: &[u8]) -> Result<Self, Error> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", seed);
+        KeyPair::from_secret(Secret::from(key)).map_err(|_| invalid_key())?;
+        Ok(ExtendedSecret { secret: Secret::from(key), chain_code })
+    }
+
+    pub fn secret(&self) -> &Secret {
+        &self.secret
+    }
+
+    pub fn address(&self) -> Address {
+        KeyPair::from_secret(self.secret.clone()).expect("secret is validated on derivation; qed").address()
+    }
+
+    /// Derives child `index`, hardened if the index has `HARDENED` set.
+    pub fn child(&self, index: u32) -> Result<Self, Error> {
+        let mut data = Vec::with_capacity(37);
+        if index & HARDENED != 0 {
+            data.push(0);
+            data.extend_from_slice(self.secret.as_bytes());
+        } else {
+            let keypair = KeyPair::from_secret(self.secret.clone()).map_err(|_| invalid_key())?;
+            let public = keypair.public();
+            data.push(if public[63] & 1 == 0 { 0x02 } else { 0x03 });
+            data.extend_from_slice(&public[..32]);
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let (tweak, chain_code) = hmac_sha512(self.chain_code.as_bytes(), &data);
+
+        let order = U256::from_str(CURVE_ORDER).expect("valid hex constant; qed");
+        let tweak = U256::from_big_endian(tweak.as_bytes());
+        if tweak >= order {
+            return Err(invalid_key());
+        }
+        let (mut key, overflow) = tweak.overflowing_add(U256::from_big_endian(self.secret.as_bytes()));
+        if overflow || key >= order {
+            key = key.overflowing_sub(order).0;
+        }
+        let mut secret = H256::zero();
+        key.to_big_endian(secret.as_bytes_mut());
+        KeyPair::from_secret(Secret::from(secret)).map_err(|_| invalid_key())?;
+        Ok(ExtendedSecret { secret: Secret::from(secret), chain_code })
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.indexes().iter().try_fold(self.clone(), |key, index| key.child(*index))
+    }
+}
+
+/// Derives the key at `path` from a mnemonic and passphrase.
+pub fn derive(mnemonic: &Mnemonic, passphrase: &str, path: &DerivationPath) -> Result<ExtendedSecret, Error> {
+    ExtendedSecret::master(&seed(mnemonic, passphrase))?.derive(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(secret: &Secret) -> String {
+        format!("{:x}", H256::from_slice(secret.as_bytes()))
+    }
+
+    #[test]
+    fn should_derive_bip32_test_vector() {
+        // Test vector 1 from BIP-32.
+        let seed: Vec<u8> = (0u8..16).collect();
+        let master = ExtendedSecret::master(&seed).unwrap();
+        assert_eq!(hex(master.secret()), "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35");
+
+        let expected = [
+            ("m/0'", "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"),
+            ("m/0'/1", "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"),
+            ("m/0'/1/2'", "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca"),
+            ("m/0'/1/2'/2", "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4"),
+            ("m/0'/1/2'/2/1000000000", "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8"),
+        ];
+        for (path, key) in &expected {
+            assert_eq!(hex(master.derive(&path.parse().unwrap()).unwrap().secret()), *key, "{}", path);
+        }
+    }
+
+    #[test]
+    fn should_derive_wallet_addresses_from_mnemonic() {
+        let mnemonic = parse_mnemonic("test test test test test test test test test test test  JUNK").unwrap();
+        let key = derive(&mnemonic, "", &DEFAULT_PATH.parse().unwrap()).unwrap();
+        assert_eq!(hex(key.secret()), "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80");
+        assert_eq!(format!("{:x}", key.address()), "f39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+
+        let other = derive(&mnemonic, "passphrase", &DEFAULT_PATH.parse().unwrap()).unwrap();
+        assert_ne!(other.address(), key.address());
+    }
+
+    #[test]
+    fn should_validate_mnemonics() {
+        assert!(parse_mnemonic("test test test test test test test test test test test test").is_err());
+        assert!(parse_mnemonic("test test test").is_err());
+        let generated = generate_mnemonic(24).unwrap();
+        assert_eq!(generated.phrase().split(' ').count(), 24);
+        assert!(parse_mnemonic(generated.phrase()).is_ok());
+        assert!(generate_mnemonic(13).is_err());
+    }
+
+    #[test]
+    fn should_parse_and_offset_paths() {
+        let path: DerivationPath = "m/44h/60'/0'/0/7".parse().unwrap();
+        assert_eq!(path.indexes(), &[44 | HARDENED, 60 | HARDENED, HARDENED, 0, 7]);
+        assert_eq!(path.to_string(), "m/44'/60'/0'/0/7");
+        assert_eq!(path.offset(3).unwrap().to_string(), "m/44'/60'/0'/0/10");
+        assert_eq!("m".parse::<DerivationPath>().unwrap().indexes(), &[] as &[u32]);
+
+        for invalid in &["", "44'/60'", "m/", "m/x", "m/-1", "m/2147483648", "m/1''"] {
+            assert!(invalid.parse::<DerivationPath>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -5,5 +5,17 @@ mod tests {
         assert_eq!(2 + 2, 4);
     }
 }
+pub mod abi;
+pub mod backup;
 pub mod ethstore_rely;
 pub mod crack;
+pub mod derivation;
+pub mod eip712;
+pub mod evm;
+pub mod hd;
+pub mod password;
+pub mod service;
+pub mod shamir;
+pub mod signature;
+pub mod state;
+pub mod transaction;
@@ -0,0 +1,194 @@
+//! Password sources for keystore commands.
+//!
+//! Passwords are never given in argv, so they don't end up in shell history: a password
+//! argument is the path of a password file, and front-ends build the other sources
+//! (terminal prompt, standard input, environment variable, open file descriptor) from
+//! dedicated flags.
+//!
+//! Input read from a stream or file has a single trailing newline (`\n` or `\r\n`)
+//! removed, everything else is kept as is.
+
+extern crate rpassword;
+
+use std::{env, fmt, fs, io};
+use std::convert::Infallible;
+use std::io::Read;
+use std::mem::ManuallyDrop;
+use std::str::FromStr;
+
+pub use ethkey::Password;
+
+use crate::ethstore_rely::Error;
+
+/// Where to read a password from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordSource {
+    /// Interactive no-echo terminal prompt.
+    Prompt,
+    /// Standard input.
+    Stdin,
+    /// Environment variable.
+    Env(String),
+    /// Open file descriptor.
+    Fd(i32),
+    /// Password file.
+    File(String),
+}
+
+/// A password argument is always the path of a password file, whatever it looks like.
+impl FromStr for PasswordSource {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(PasswordSource::File(s.to_owned()))
+    }
+}
+
+impl fmt::Display for PasswordSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PasswordSource::Prompt => write!(f, "terminal prompt"),
+            PasswordSource::Stdin => write!(f, "standard input"),
+            PasswordSource::Env(ref var) => write!(f, "environment variable {}", var),
+            PasswordSource::Fd(fd) => write!(f, "file descriptor {}", fd),
+            PasswordSource::File(ref path) => write!(f, "password file '{}'", path),
+        }
+    }
+}
+
+impl PasswordSource {
+    /// Reads an existing password, `what` is shown when prompting.
+    pub fn read(&self, what: &str) -> Result<Password, Error> {
+        match *self {
+            PasswordSource::Prompt => prompt(what),
+            _ => self.read_noninteractive(),
+        }
+    }
+
+    /// Reads a password that is about to be set, prompting twice for confirmation.
+    pub fn read_new(&self, what: &str) -> Result<Password, Error> {
+        match *self {
+            PasswordSource::Prompt => {
+                let password = prompt(what)?;
+                let confirmation = prompt(&format!("Repeat {}", what))?;
+                if password != confirmation {
+                    return Err(Error::InvalidInput("passwords do not match".into()));
+                }
+                Ok(password)
+            },
+            _ => self.read_noninteractive(),
+        }
+    }
+
+    /// Reads the raw contents of the source without trimming, used for password lists.
+    pub fn read_raw(&self, what: &str) -> Result<String, Error> {
+        match *self {
+            PasswordSource::Prompt => Ok(prompt(what)?.as_str().to_owned()),
+            PasswordSource::Stdin => read_stream(io::stdin(), "stdin"),
+            PasswordSource::Env(ref var) => env::var(var)
+                .map_err(|_| Error::InvalidInput(format!("environment variable {} is not set or not valid UTF-8", var))),
+            PasswordSource::Fd(fd) => read_stream(&*open_fd(fd)?, &format!("file descriptor {}", fd)),
+            PasswordSource::File(ref path) => {
+                let file = fs::File::open(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Error opening password file '{}': {}", path, e)))?;
+                read_stream(file, &format!("password file '{}'", path))
+            },
+        }
+    }
+
+    /// Reads a list of passwords, one per line.
+    pub fn read_lines(&self, what: &str) -> Result<Vec<Password>, Error> {
+        Ok(self.read_raw(what)?.lines().map(|line| line.into()).collect())
+    }
+
+    fn read_noninteractive(&self) -> Result<Password, Error> {
+        let raw = self.read_raw("")?;
+        match *self {
+            PasswordSource::Env(_) => Ok(raw.into()),
+            _ => Ok(trim_newline(raw).into()),
+        }
+    }
+}
+
+/// Removes a single trailing `\n` or `\r\n`.
+pub fn trim_newline(mut s: String) -> String {
+    if s.ends_with('\n') {
+        s.pop();
+        if s.ends_with('\r') {
+            s.pop();
+        }
+    }
+    s
+}
+
+fn prompt(what: &str) -> Result<Password, Error> {
+    let password = rpassword::read_password_from_tty(Some(&format!("{}: ", what)))
+        .map_err(|e| io::Error::new(e.kind(), format!("Error reading password from terminal: {}", e)))?;
+    Ok(password.into())
+}
+
+fn read_stream<R: Read>(mut reader: R, what: &str) -> Result<String, Error> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)
+        .map_err(|e| io::Error::new(e.kind(), format!("Error reading {}: {}", what, e)))?;
+    Ok(contents)
+}
+
+/// Borrows the descriptor as a file that is never dropped, so it stays open for its owner.
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<ManuallyDrop<fs::File>, Error> {
+    use std::os::unix::io::FromRawFd;
+
+    if fd < 0 {
+        return Err(Error::InvalidInput(format!("invalid file descriptor {}", fd)));
+    }
+    Ok(ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) }))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<ManuallyDrop<fs::File>, Error> {
+    Err(Error::InvalidInput("passwords from file descriptors are only supported on unix".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_any_argument_as_file() {
+        for arg in &["pwd.txt", "prompt", "-", "env:PWD_VAR", "fd:3"] {
+            assert_eq!(arg.parse::<PasswordSource>().unwrap(), PasswordSource::File(arg.to_string()));
+        }
+    }
+
+    #[test]
+    fn should_trim_only_one_newline() {
+        assert_eq!(trim_newline("password".into()), "password");
+        assert_eq!(trim_newline("password\n".into()), "password");
+        assert_eq!(trim_newline("password\r\n".into()), "password");
+        assert_eq!(trim_newline("password\n\n".into()), "password\n");
+    }
+
+    #[test]
+    fn should_read_file_without_trailing_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("password");
+        fs::write(&path, "password").unwrap();
+        let source = PasswordSource::File(path.to_str().unwrap().to_owned());
+        assert_eq!(source.read("Password").unwrap(), "password".into());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_leave_file_descriptor_open() {
+        use std::os::unix::io::AsRawFd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("password");
+        fs::write(&path, "password\n").unwrap();
+        let file = fs::File::open(&path).unwrap();
+        let source = PasswordSource::Fd(file.as_raw_fd());
+        assert_eq!(source.read("Password").unwrap(), "password".into());
+        assert!(file.metadata().is_ok());
+    }
+}
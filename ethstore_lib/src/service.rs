@@ -0,0 +1,494 @@
+//! Typed keystore operations.
+//!
+//! Every `bloom-cmd ethstore` subcommand maps onto one function here. Front-ends parse
+//! their own arguments into typed values and call these directly; library users can do
+//! the same without going through any command line layer.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ethkey::Password;
+use ethstore::accounts_dir::{KeyDirectory, RootDiskDirectory};
+use ethstore::accounts_dir::fsck::{self, Anomaly};
+use ethstore::{Derivation, EthStore, KdfParams, SimpleSecretStore, SecretStore, import_accounts, PresaleWallet, SecretVaultRef, StoreAccountRef};
+use parity_crypto::publickey::{Address, Generator, KeyPair, Message, Public, Random, Secret, Signature};
+
+use crate::backup::{self, ManifestEntry};
+use crate::crack;
+use crate::eip712::TypedData;
+use crate::hd::{DerivationPath, ExtendedSecret};
+use crate::shamir::{self, Share};
+use crate::transaction::{SignedTransaction, TypedTransaction};
+use crate::ethstore_rely::Error;
+
+/// Opens the given vault (if any) and returns a reference to it.
+pub fn open_vault(store: &EthStore, vault: Option<&str>, vault_password: Option<&Password>) -> Result<SecretVaultRef, Error> {
+    match vault {
+        None => Ok(SecretVaultRef::Root),
+        Some(name) => {
+            let password = vault_password.ok_or_else(|| Error::InvalidInput(format!("password for vault '{}' is required", name)))?;
+            check_vault_exists(store, name)?;
+            store.open_vault(name, password)?;
+            Ok(SecretVaultRef::Vault(name.to_owned()))
+        },
+    }
+}
+
+/// Fails with `VaultNotFound` unless the vault exists; opening a missing vault only
+/// reports that its directory could not be created.
+fn check_vault_exists(store: &EthStore, name: &str) -> Result<(), Error> {
+    if !store.list_vaults()?.iter().any(|v| v == name) {
+        return Err(ethstore::Error::VaultNotFound.into());
+    }
+    Ok(())
+}
+
+/// Saves the secret with given password in the root directory or vault.
+pub fn insert(store: &EthStore, vault: SecretVaultRef, secret: Secret, password: &Password) -> Result<Address, Error> {
+    let account_ref = store.insert_account(vault, secret, password)?;
+    Ok(account_ref.address)
+}
+
+/// Generates a random account like `AccountProvider::new_account` and saves it in the root
+/// directory or vault, with optional name and meta.
+pub fn new_account(store: &EthStore, vault: SecretVaultRef, password: &Password, name: Option<&str>, meta: Option<&str>) -> Result<Address, Error> {
+    let keypair = Random.generate();
+    let account_ref = store.insert_account(vault, keypair.secret().clone(), password)?;
+    if let Some(name) = name {
+        store.set_name(&account_ref, name.to_owned())?;
+    }
+    if let Some(meta) = meta {
+        store.set_meta(&account_ref, meta.to_owned())?;
+    }
+    Ok(account_ref.address)
+}
+
+/// Saves a BIP-32 derived key in the root directory or vault, recording its path in the
+/// account meta as `{"hd_path": "m/..."}`.
+pub fn insert_hd(store: &EthStore, vault: SecretVaultRef, key: &ExtendedSecret, path: &DerivationPath, password: &Password) -> Result<Address, Error> {
+    let account_ref = store.insert_account(vault, key.secret().clone(), password)?;
+    store.set_meta(&account_ref, serde_json::json!({ "hd_path": path.to_string() }).to_string())?;
+    Ok(account_ref.address)
+}
+
+/// Derives a child key of the account and saves it with the same password in the root
+/// directory or vault.
+pub fn insert_derived(store: &EthStore, vault: SecretVaultRef, account: &StoreAccountRef, password: &Password, derivation: Derivation) -> Result<Address, Error> {
+    Ok(store.insert_derived(vault, account, password, derivation)?.address)
+}
+
+/// Returns the address of a child key of the account without saving it.
+pub fn derive_address(store: &EthStore, account: &StoreAccountRef, password: &Password, derivation: Derivation) -> Result<Address, Error> {
+    Ok(store.generate_derived(account, password, derivation)?)
+}
+
+/// Changes account password.
+pub fn change_pwd(store: &EthStore, account: &StoreAccountRef, old_pwd: &Password, new_pwd: &Password) -> Result<(), Error> {
+    Ok(store.change_password(account, old_pwd, new_pwd)?)
+}
+
+/// What `rekey` found or did for one account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RekeyStatus {
+    /// Already as strong as the store's KDF.
+    Current,
+    /// Weaker than the store's KDF, left as is in a dry run.
+    Weak,
+    /// Re-encrypted with the store's KDF.
+    Rekeyed,
+    /// Weaker than the store's KDF, but none of the passwords opens it.
+    NoPassword,
+}
+
+/// Account visited by `rekey` with the KDF its key was encrypted with before.
+#[derive(Debug, Clone)]
+pub struct RekeyEntry {
+    pub account: StoreAccountRef,
+    pub kdf: KdfParams,
+    pub status: RekeyStatus,
+}
+
+/// Walks the accounts in the root directory and opened vaults, and re-encrypts those weaker
+/// than the KDF the store was opened with, keeping the password that opens them. Key files
+/// are replaced atomically. With `dry_run` nothing is written and no password is needed.
+pub fn rekey(store: &EthStore, passwords: &[Password], dry_run: bool) -> Result<Vec<RekeyEntry>, Error> {
+    let target = store.kdf();
+    let mut entries = Vec::new();
+    for account in store.accounts()? {
+        let kdf = store.account_kdf(&account)?;
+        let status = if !kdf.is_weaker_than(&target) {
+            RekeyStatus::Current
+        } else if dry_run {
+            RekeyStatus::Weak
+        } else {
+            match passwords.iter().find(|password| store.test_password(&account, password).unwrap_or(false)) {
+                Some(password) => {
+                    store.change_password(&account, password, password)?;
+                    RekeyStatus::Rekeyed
+                },
+                None => RekeyStatus::NoPassword,
+            }
+        };
+        entries.push(RekeyEntry { account, kdf, status });
+    }
+    Ok(entries)
+}
+
+/// Anomalies found by `fsck` and the files it moved away, as `(from, to)` pairs.
+#[derive(Debug, Clone)]
+pub struct FsckReport {
+    pub anomalies: Vec<Anomaly>,
+    pub quarantined: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Checks the keys directory at `root` and its vaults without loading them into a store.
+/// Keys are decrypted with `passwords` and the vaults named in `vault_passwords` are opened
+/// to verify MACs and addresses. With `quarantine`, broken files and leftovers of
+/// interrupted writes are moved into that directory; if that fails part way, the error
+/// lists what was already moved.
+pub fn fsck(root: &Path, passwords: &[Password], vault_passwords: &HashMap<String, Password>, quarantine: Option<&Path>) -> Result<FsckReport, Error> {
+    let anomalies = fsck::check(root, passwords, vault_passwords)?;
+    let quarantined = match quarantine.map(|target| fsck::quarantine(root, &anomalies, target)) {
+        Some(Ok(moved)) => moved,
+        Some(Err(ref err)) if !err.moved.is_empty() => return Err(Error::Io(io::Error::new(io::ErrorKind::Other, err.to_string()))),
+        Some(Err(err)) => return Err(err.error.into()),
+        None => Vec::new(),
+    };
+    Ok(FsckReport { anomalies, quarantined })
+}
+
+/// Writes the account as a Web3 Secret Storage (v3) key file into the directory `out`,
+/// re-encrypted with `new_password` if given, and returns the file's path.
+pub fn export(store: &EthStore, account: &StoreAccountRef, password: &Password, new_password: Option<&Password>, out: &Path) -> Result<PathBuf, Error> {
+    let dst = RootDiskDirectory::create(out)?;
+    let filename = store.export_account_to(account, password, new_password, &dst)?;
+    Ok(out.join(filename))
+}
+
+/// Account visited by `export_vault` with the file it was written to, or `None` when none
+/// of the passwords opens it.
+#[derive(Debug, Clone)]
+pub struct ExportEntry {
+    pub address: Address,
+    pub path: Option<PathBuf>,
+}
+
+/// Exports every account of the root directory or an opened vault into `out`, each with
+/// the first of `passwords` that opens it.
+pub fn export_vault(store: &EthStore, vault: &SecretVaultRef, passwords: &[Password], new_password: Option<&Password>, out: &Path) -> Result<Vec<ExportEntry>, Error> {
+    let mut entries = Vec::new();
+    for account in store.accounts()?.into_iter().filter(|a| &a.vault == vault) {
+        let path = match passwords.iter().find(|password| store.test_password(&account, password).unwrap_or(false)) {
+            Some(password) => Some(export(store, &account, password, new_password, out)?),
+            None => None,
+        };
+        entries.push(ExportEntry { address: account.address, path });
+    }
+    Ok(entries)
+}
+
+/// Writes an encrypted archive of the key directory `root` and all its vaults to the new
+/// file `out`, see `backup::create`.
+pub fn backup(root: &Path, out: &Path, passphrase: &Password, kdf: KdfParams, vault_passwords: &HashMap<String, Password>) -> Result<Vec<ManifestEntry>, Error> {
+    backup::create(root, out, passphrase, kdf, vault_passwords)
+}
+
+/// Verifies the archive and, unless `verify_only`, restores it into the key directory `root`,
+/// see `backup::restore`.
+pub fn restore(archive: &Path, root: &Path, passphrase: &Password, overwrite: bool, verify_only: bool, vault_passwords: &HashMap<String, Password>) -> Result<Vec<ManifestEntry>, Error> {
+    if verify_only {
+        return backup::verify(archive, passphrase);
+    }
+    backup::restore(archive, root, passphrase, overwrite, vault_passwords)
+}
+
+/// Splits the account's secret into `count` shares, any `threshold` of which recover it.
+pub fn split_secret(store: &EthStore, account: &StoreAccountRef, password: &Password, threshold: u8, count: u8) -> Result<Vec<Share>, Error> {
+    let secret = store.raw_secret(account, password)?;
+    shamir::split(secret.as_bytes(), threshold, count)
+}
+
+/// Recovers a secret from its shares and saves it with `password` in the root directory or
+/// vault, provided it is the key of `expected`.
+pub fn combine_shares(store: &EthStore, vault: SecretVaultRef, shares: &[Share], expected: &Address, password: &Password) -> Result<Address, Error> {
+    let keypair = KeyPair::from_secret(Secret::from(shamir::combine(shares)?))
+        .map_err(|_| Error::InvalidInput("shares do not combine into a valid key, check that they belong to the same split".into()))?;
+    if keypair.address() != *expected {
+        return Err(Error::InvalidInput(format!("shares combine into the key of 0x{:x}, not 0x{:x}", keypair.address(), expected)));
+    }
+    insert(store, vault, keypair.secret().clone(), password)
+}
+
+/// Lists addresses stored in the root directory or vault.
+pub fn list(store: &EthStore, vault: &SecretVaultRef) -> Result<Vec<Address>, Error> {
+    let accounts = store.accounts()?;
+    Ok(accounts
+        .into_iter()
+        .filter(|a| &a.vault == vault)
+        .map(|a| a.address)
+        .collect())
+}
+
+/// Copies all accounts from `src` that are missing in `dst`.
+pub fn import(src: &dyn KeyDirectory, dst: &dyn KeyDirectory) -> Result<Vec<Address>, Error> {
+    Ok(import_accounts(src, dst)?)
+}
+
+/// Imports presale wallet into the root directory or vault.
+pub fn import_wallet(store: &EthStore, vault: SecretVaultRef, path: &str, password: &Password) -> Result<Address, Error> {
+    let wallet = PresaleWallet::open(path)?;
+    let kp = wallet.decrypt(password)?;
+    insert(store, vault, kp.secret().clone(), password)
+}
+
+/// Tries to open presale wallet with every password given, returns the matching one.
+pub fn find_wallet_pass(path: &str, passwords: VecDeque<Password>) -> Result<Option<Password>, Error> {
+    Ok(crack::run(passwords, path)?)
+}
+
+/// Removes account.
+pub fn remove(store: &EthStore, account: &StoreAccountRef, password: &Password) -> Result<(), Error> {
+    Ok(store.remove_account(account, password)?)
+}
+
+/// Signs 32-byte message with account.
+pub fn sign(store: &EthStore, account: &StoreAccountRef, password: &Password, message: &Message) -> Result<Signature, Error> {
+    Ok(store.sign(account, password, message)?)
+}
+
+/// Signs 32-byte message with a child key of the account, the key is never saved.
+pub fn sign_derived(store: &EthStore, account: &StoreAccountRef, password: &Password, derivation: Derivation, message: &Message) -> Result<Signature, Error> {
+    Ok(store.sign_derived(account, password, derivation, message)?)
+}
+
+/// Signs EIP-712 typed data with account.
+pub fn sign_typed_data(store: &EthStore, account: &StoreAccountRef, password: &Password, data: &TypedData) -> Result<Signature, Error> {
+    sign(store, account, password, &data.digest()?)
+}
+
+/// Signs legacy or typed transaction with account.
+pub fn sign_transaction(store: &EthStore, account: &StoreAccountRef, password: &Password, transaction: &TypedTransaction) -> Result<SignedTransaction, Error> {
+    let signature = sign(store, account, password, &transaction.signing_hash())?;
+    Ok(transaction.with_signature(&signature))
+}
+
+/// Returns account public key.
+pub fn public(store: &EthStore, account: &StoreAccountRef, password: &Password) -> Result<Public, Error> {
+    Ok(store.public(account, password)?)
+}
+
+/// Lists all vaults.
+pub fn list_vaults(store: &EthStore) -> Result<Vec<String>, Error> {
+    Ok(store.list_vaults()?)
+}
+
+/// Creates new vault.
+pub fn create_vault(store: &EthStore, name: &str, password: &Password) -> Result<(), Error> {
+    Ok(store.create_vault(name, password)?)
+}
+
+/// Changes vault password.
+pub fn change_vault_pwd(store: &EthStore, name: &str, old_pwd: &Password, new_pwd: &Password) -> Result<(), Error> {
+    check_vault_exists(store, name)?;
+    store.open_vault(name, old_pwd)?;
+    Ok(store.change_vault_password(name, new_pwd)?)
+}
+
+/// Moves account to vault from another vault or the root directory.
+pub fn move_to_vault(store: &EthStore, account: StoreAccountRef, vault: &str, vault_password: &Password) -> Result<StoreAccountRef, Error> {
+    check_vault_exists(store, vault)?;
+    store.open_vault(vault, vault_password)?;
+    Ok(store.change_account_vault(SecretVaultRef::Vault(vault.to_owned()), account)?)
+}
+
+/// Moves account from vault to the root directory.
+pub fn move_from_vault(store: &EthStore, address: Address, vault: &str, vault_password: &Password) -> Result<StoreAccountRef, Error> {
+    check_vault_exists(store, vault)?;
+    store.open_vault(vault, vault_password)?;
+    Ok(store.change_account_vault(SecretVaultRef::Root, StoreAccountRef::vault(vault, address))?)
+}
+
+/// Closes vault in `store`, failing with `VaultNotFound` if it does not exist. Vaults only
+/// stay open within one `EthStore`, nothing is persisted.
+pub fn close_vault(store: &EthStore, name: &str) -> Result<(), Error> {
+    check_vault_exists(store, name)?;
+    Ok(store.close_vault(name)?)
+}
+
+/// Deletes vault. A vault still holding accounts is only deleted when `force` is set.
+pub fn delete_vault(store: &EthStore, name: &str, password: &Password, force: bool) -> Result<(), Error> {
+    Ok(store.delete_vault(name, password, force)?)
+}
+
+/// Renames vault, keeping its password and accounts.
+pub fn rename_vault(store: &EthStore, name: &str, new_name: &str, password: &Password) -> Result<(), Error> {
+    Ok(store.rename_vault(name, new_name, password)?)
+}
+
+/// Returns vault metadata. Does not require vault password.
+pub fn get_vault_meta(store: &EthStore, name: &str) -> Result<String, Error> {
+    Ok(store.get_vault_meta(name)?)
+}
+
+/// Replaces vault metadata.
+pub fn set_vault_meta(store: &EthStore, name: &str, password: &Password, meta: &str) -> Result<(), Error> {
+    check_vault_exists(store, name)?;
+    store.open_vault(name, password)?;
+    Ok(store.set_vault_meta(name, meta)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethstore::accounts_dir::{MemoryDirectory, RootDiskDirectory};
+    use ethstore::{EthStore, KdfParams, SecretStore, SecretVaultRef, StoreAccountRef};
+    use parity_crypto::publickey::{Generator, Random};
+    use crate::ethstore_rely::exit_code;
+    use super::*;
+
+    fn store() -> EthStore {
+        EthStore::open(Box::new(MemoryDirectory::default())).expect("MemoryDirectory always load successfuly; qed")
+    }
+
+    #[test]
+    fn should_insert_list_and_remove() {
+        let store = store();
+        let keypair = Random.generate();
+        let password = "password".into();
+
+        let address = insert(&store, SecretVaultRef::Root, keypair.secret().clone(), &password).unwrap();
+        assert_eq!(address, keypair.address());
+        assert_eq!(list(&store, &SecretVaultRef::Root).unwrap(), vec![address]);
+
+        let account = StoreAccountRef::root(address);
+        assert_eq!(remove(&store, &account, &"wrong".into()).unwrap_err().exit_code(), exit_code::BAD_PASSWORD);
+        remove(&store, &account, &password).unwrap();
+        assert!(list(&store, &SecretVaultRef::Root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_create_account_with_name_and_meta() {
+        let store = store();
+        let password = "password".into();
+
+        let address = new_account(&store, SecretVaultRef::Root, &password, Some("worker"), Some("{\"k\":1}")).unwrap();
+        let account = StoreAccountRef::root(address);
+        assert_eq!(store.name(&account).unwrap(), "worker");
+        assert_eq!(store.meta(&account).unwrap(), "{\"k\":1}");
+        assert!(store.test_password(&account, &password).unwrap());
+
+        let plain = StoreAccountRef::root(new_account(&store, SecretVaultRef::Root, &password, None, None).unwrap());
+        assert_eq!(store.name(&plain).unwrap(), "");
+        assert_eq!(store.meta(&plain).unwrap(), "{}");
+    }
+
+    #[test]
+    fn should_rekey_weak_accounts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let open = |kdf| EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(dir.path()).unwrap()), kdf).unwrap();
+        let weak = KdfParams::Pbkdf2 { iterations: 1024 };
+        let target = KdfParams::Scrypt { n: 1024, r: 8, p: 1, dklen: 32 };
+
+        let old_store = open(weak);
+        let first = insert(&old_store, SecretVaultRef::Root, Random.generate().secret().clone(), &"first".into()).unwrap();
+        let second = insert(&old_store, SecretVaultRef::Root, Random.generate().secret().clone(), &"second".into()).unwrap();
+        let status = |entries: &[RekeyEntry], address: Address| entries.iter().find(|e| e.account.address == address).unwrap().status;
+
+        let store = open(target);
+        let entries = rekey(&store, &[], true).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.kdf == weak && e.status == RekeyStatus::Weak));
+
+        let entries = rekey(&store, &[Password::from("first")], false).unwrap();
+        assert_eq!(status(&entries, first), RekeyStatus::Rekeyed);
+        assert_eq!(status(&entries, second), RekeyStatus::NoPassword);
+
+        let store = open(target);
+        let entries = rekey(&store, &[Password::from("first"), Password::from("second")], false).unwrap();
+        assert_eq!(status(&entries, first), RekeyStatus::Current);
+        assert_eq!(status(&entries, second), RekeyStatus::Rekeyed);
+        assert_eq!(store.account_kdf(&StoreAccountRef::root(second)).unwrap(), target);
+        assert!(store.test_password(&StoreAccountRef::root(first), &"first".into()).unwrap());
+    }
+
+    #[test]
+    fn should_export_vault_accounts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let out = tempfile::TempDir::new().unwrap();
+        let store = EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(dir.path()).unwrap()), KdfParams::Pbkdf2 { iterations: 1024 }).unwrap();
+        let vault_password = "vault".into();
+        create_vault(&store, "team", &vault_password).unwrap();
+        let vault = open_vault(&store, Some("team"), Some(&vault_password)).unwrap();
+        let first = insert(&store, vault.clone(), Random.generate().secret().clone(), &"first".into()).unwrap();
+        let second = insert(&store, vault.clone(), Random.generate().secret().clone(), &"second".into()).unwrap();
+
+        let entries = export_vault(&store, &vault, &[Password::from("first")], Some(&"export".into()), out.path()).unwrap();
+        let path = |address: Address| entries.iter().find(|e| e.address == address).unwrap().path.clone();
+        assert!(path(second).is_none());
+        assert!(path(first).unwrap().starts_with(out.path()));
+
+        let exported = EthStore::open(Box::new(RootDiskDirectory::at(out.path()))).unwrap();
+        assert_eq!(list(&exported, &SecretVaultRef::Root).unwrap(), vec![first]);
+        assert!(exported.test_password(&StoreAccountRef::root(first), &"export".into()).unwrap());
+    }
+
+    #[test]
+    fn should_split_and_combine_secret() {
+        let (store, restored) = (store(), store());
+        let keypair = Random.generate();
+        let address = insert(&store, SecretVaultRef::Root, keypair.secret().clone(), &"password".into()).unwrap();
+        let shares = split_secret(&store, &StoreAccountRef::root(address), &"password".into(), 2, 3).unwrap();
+        assert_eq!(shares.len(), 3);
+
+        let other = Random.generate().address();
+        assert!(combine_shares(&restored, SecretVaultRef::Root, &shares[1..], &other, &"new".into()).is_err());
+        assert!(list(&restored, &SecretVaultRef::Root).unwrap().is_empty());
+        assert_eq!(combine_shares(&restored, SecretVaultRef::Root, &shares[1..], &address, &"new".into()).unwrap(), address);
+        assert!(restored.test_password(&StoreAccountRef::root(address), &"new".into()).unwrap());
+    }
+
+    #[test]
+    fn should_require_vault_password() {
+        let store = store();
+        assert!(open_vault(&store, None, None).unwrap() == SecretVaultRef::Root);
+        assert!(open_vault(&store, Some("vault"), None).is_err());
+    }
+
+    #[test]
+    fn should_set_vault_meta_and_close_vault() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(dir.path()).unwrap()), KdfParams::Pbkdf2 { iterations: 1024 }).unwrap();
+        let password = "password".into();
+        create_vault(&store, "team", &password).unwrap();
+        assert_eq!(list_vaults(&store).unwrap(), vec!["team".to_owned()]);
+        assert_eq!(get_vault_meta(&store, "team").unwrap(), "{}");
+
+        close_vault(&store, "team").unwrap();
+        assert_eq!(set_vault_meta(&store, "team", &"wrong".into(), "{\"hint\":\"x\"}").unwrap_err().exit_code(), exit_code::BAD_PASSWORD);
+        set_vault_meta(&store, "team", &password, "{\"hint\":\"first pet\"}").unwrap();
+        close_vault(&store, "team").unwrap();
+        assert_eq!(get_vault_meta(&store, "team").unwrap(), "{\"hint\":\"first pet\"}");
+        assert_eq!(close_vault(&store, "other").unwrap_err().exit_code(), exit_code::VAULT_NOT_FOUND);
+    }
+
+    #[test]
+    fn should_map_errors_to_exit_codes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = EthStore::open_with_kdf(Box::new(RootDiskDirectory::create(dir.path()).unwrap()), KdfParams::Pbkdf2 { iterations: 1024 }).unwrap();
+        let password = "password".into();
+        create_vault(&store, "team", &password).unwrap();
+        let address = insert(&store, SecretVaultRef::Root, Random.generate().secret().clone(), &password).unwrap();
+        let code = |result: Result<_, Error>| result.map(|_| ()).unwrap_err().exit_code();
+
+        assert_eq!(code(public(&store, &StoreAccountRef::root(address), &"wrong".into())), exit_code::BAD_PASSWORD);
+        assert_eq!(code(open_vault(&store, Some("team"), Some(&"wrong".into()))), exit_code::BAD_PASSWORD);
+        assert_eq!(code(public(&store, &StoreAccountRef::root(Random.generate().address()), &password)), exit_code::ACCOUNT_NOT_FOUND);
+        assert_eq!(code(open_vault(&store, Some("missing"), Some(&password))), exit_code::VAULT_NOT_FOUND);
+        assert_eq!(code(close_vault(&store, "missing")), exit_code::VAULT_NOT_FOUND);
+        assert_eq!(code(set_vault_meta(&store, "missing", &password, "{}")), exit_code::VAULT_NOT_FOUND);
+
+        let vault = open_vault(&store, Some("team"), Some(&password)).unwrap();
+        insert(&store, vault, Random.generate().secret().clone(), &password).unwrap();
+        assert_eq!(code(delete_vault(&store, "team", &password, false)), exit_code::INVALID_INPUT);
+    }
+}
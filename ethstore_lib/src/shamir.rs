@@ -0,0 +1,272 @@
+//! Shamir's secret sharing of 32-byte keys over GF(256).
+//!
+//! Every byte of the secret is the constant term of its own random polynomial of degree
+//! `threshold - 1`; share `i` holds the polynomials' values at `x = i`. Any `threshold`
+//! shares give the secret back by Lagrange interpolation at `x = 0`, fewer reveal nothing.
+//!
+//! A share is written either as hex, `threshold || index || value || checksum` where the
+//! checksum is the first 4 bytes of the SHA-256 of the rest, or as
+//! `<threshold>-<index>-<checksum>` followed by the 24-word BIP-39 mnemonic of the value,
+//! with the same checksum in hex so that threshold and index are covered too.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bip39::{Language, Mnemonic};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use rustc_hex::{FromHex, ToHex};
+use sha2::{Digest, Sha256};
+
+use crate::ethstore_rely::Error;
+use crate::hd::parse_mnemonic;
+
+/// One share of a split secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    /// Number of shares needed to recover the secret.
+    pub threshold: u8,
+    /// Point the share was taken at, from 1.
+    pub index: u8,
+    pub value: [u8; 32],
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which recover it.
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>, Error> {
+    if secret.len() != 32 {
+        return Err(Error::InvalidInput(format!("secret must be 32 bytes, not {}", secret.len())));
+    }
+    if threshold == 0 || threshold > count {
+        return Err(Error::InvalidInput(format!("threshold must be between 1 and the number of shares ({}), not {}", count, threshold)));
+    }
+
+    // coefficients[k][b] multiplies x^(k + 1) in the polynomial of byte b
+    let mut coefficients = vec![[0u8; 32]; threshold as usize - 1];
+    for coefficient in &mut coefficients {
+        OsRng.fill_bytes(coefficient);
+    }
+    let shares = (1..=count)
+        .map(|index| {
+            let mut value = [0u8; 32];
+            for (b, byte) in value.iter_mut().enumerate() {
+                // Horner's rule, from the highest coefficient down to the secret byte
+                *byte = coefficients.iter().rev().fold(0, |acc, c| mul(acc, index) ^ c[b]);
+                *byte = mul(*byte, index) ^ secret[b];
+            }
+            Share { threshold, index, value }
+        })
+        .collect();
+    for coefficient in &mut coefficients {
+        *coefficient = [0u8; 32];
+    }
+    Ok(shares)
+}
+
+/// Recovers the secret from at least `threshold` distinct shares of the same split.
+pub fn combine(shares: &[Share]) -> Result<[u8; 32], Error> {
+    let threshold = match shares.first() {
+        Some(share) => share.threshold,
+        None => return Err(Error::InvalidInput("no shares given".into())),
+    };
+    if shares.iter().any(|share| share.threshold != threshold) {
+        return Err(Error::InvalidInput("shares come from splits with different thresholds".into()));
+    }
+    let mut used: Vec<&Share> = Vec::new();
+    for share in shares {
+        match used.iter().find(|other| other.index == share.index) {
+            Some(other) if other.value != share.value => {
+                return Err(Error::InvalidInput(format!("share {} is given twice with different values", share.index)));
+            },
+            Some(_) => continue,
+            None => used.push(share),
+        }
+    }
+    if used.len() < threshold as usize {
+        return Err(Error::InvalidInput(format!("{} shares are needed, only {} distinct ones given", threshold, used.len())));
+    }
+    used.truncate(threshold as usize);
+
+    let mut secret = [0u8; 32];
+    for (j, share) in used.iter().enumerate() {
+        // Lagrange basis polynomial of share j at x = 0; subtraction is xor in GF(256)
+        let basis = used.iter()
+            .enumerate()
+            .filter(|&(m, _)| m != j)
+            .fold(1, |acc, (_, other)| mul(acc, div(other.index, other.index ^ share.index)));
+        for (byte, value) in secret.iter_mut().zip(share.value.iter()) {
+            *byte ^= mul(basis, *value);
+        }
+    }
+    Ok(secret)
+}
+
+/// Product in GF(256) with the AES polynomial x^8 + x^4 + x^3 + x + 1.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Quotient in GF(256), `b` is never zero for distinct share indexes.
+fn div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b, as the multiplicative group has order 255
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = mul(inverse, b);
+    }
+    mul(a, inverse)
+}
+
+impl Share {
+    fn checksum(&self) -> [u8; 4] {
+        let mut hasher = Sha256::new();
+        hasher.input(&[self.threshold, self.index]);
+        hasher.input(&self.value);
+        let hash = hasher.result();
+        [hash[0], hash[1], hash[2], hash[3]]
+    }
+
+    /// Hex form, with a checksum over threshold, index and value.
+    pub fn to_hex(&self) -> String {
+        let mut bytes = vec![self.threshold, self.index];
+        bytes.extend_from_slice(&self.value);
+        bytes.extend_from_slice(&self.checksum());
+        bytes.to_hex()
+    }
+
+    /// `<threshold>-<index>-<checksum>` and the BIP-39 mnemonic of the value.
+    pub fn to_words(&self) -> String {
+        let mnemonic = Mnemonic::from_entropy(&self.value, Language::English)
+            .expect("32 bytes are a valid BIP-39 entropy length; qed");
+        format!("{}-{}-{} {}", self.threshold, self.index, self.checksum().to_hex::<String>(), mnemonic.phrase())
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Share {{ threshold: {}, index: {}, .. }}", self.threshold, self.index)
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    /// Parses either form written by `to_hex` and `to_words`, verifying its checksum.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        let invalid = |reason: &str| Error::InvalidInput(format!("share: {}", reason));
+        let (share, checksum) = match s.find(char::is_whitespace) {
+            Some(split) => {
+                let header: Vec<_> = s[..split].split('-').collect();
+                let fields = match header[..] {
+                    [threshold, index, checksum] => match (threshold.parse::<u8>(), index.parse::<u8>(), checksum.from_hex::<Vec<u8>>()) {
+                        (Ok(threshold), Ok(index), Ok(checksum)) => Some((threshold, index, checksum)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let (threshold, index, checksum) = fields
+                    .ok_or_else(|| invalid("mnemonic shares start with <threshold>-<index>-<checksum>"))?;
+                let mnemonic = parse_mnemonic(&s[split..])?;
+                if mnemonic.entropy().len() != 32 {
+                    return Err(invalid("mnemonic must have 24 words"));
+                }
+                let mut value = [0u8; 32];
+                value.copy_from_slice(mnemonic.entropy());
+                (Share { threshold, index, value }, checksum)
+            },
+            None => {
+                let bytes: Vec<u8> = s.trim_start_matches("0x").from_hex().map_err(|_| invalid("not hex"))?;
+                if bytes.len() != 38 {
+                    return Err(invalid("hex shares are 38 bytes long"));
+                }
+                let mut value = [0u8; 32];
+                value.copy_from_slice(&bytes[2..34]);
+                (Share { threshold: bytes[0], index: bytes[1], value }, bytes[34..].to_vec())
+            },
+        };
+        if share.checksum()[..] != checksum[..] {
+            return Err(invalid("checksum does not match, the share is mistyped"));
+        }
+        if share.threshold == 0 || share.index == 0 {
+            return Err(invalid("threshold and index start at 1"));
+        }
+        Ok(share)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [
+        0x4d, 0x5d, 0xb4, 0x10, 0x7d, 0x23, 0x7d, 0xf6, 0xa3, 0xd5, 0x8e, 0xe5, 0xf7, 0x0a, 0xe6, 0x3d,
+        0x73, 0xd7, 0x65, 0x8d, 0x40, 0x26, 0xf2, 0xee, 0xfd, 0x2f, 0x20, 0x4c, 0x81, 0x68, 0x2c, 0xb7,
+    ];
+
+    #[test]
+    fn should_multiply_in_gf256() {
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, div(1, a)), 1, "{}", a);
+        }
+    }
+
+    #[test]
+    fn should_recover_from_any_threshold_shares() {
+        let shares = split(&SECRET, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.value != SECRET));
+        for subset in &[[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<_> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&subset).unwrap(), SECRET);
+        }
+        assert_eq!(combine(&shares).unwrap(), SECRET);
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+    }
+
+    #[test]
+    fn should_reject_invalid_parameters() {
+        assert!(split(&SECRET, 0, 3).is_err());
+        assert!(split(&SECRET, 4, 3).is_err());
+        assert!(split(&SECRET[..31], 2, 3).is_err());
+        assert_eq!(combine(&split(&SECRET, 1, 1).unwrap()).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn should_encode_and_parse_shares() {
+        for share in split(&SECRET, 2, 3).unwrap() {
+            assert_eq!(share.to_hex().parse::<Share>().unwrap(), share);
+            assert_eq!(format!("0x{}", share.to_hex()).parse::<Share>().unwrap(), share);
+            assert_eq!(share.to_words().parse::<Share>().unwrap(), share);
+            assert_eq!(share.to_words().split_whitespace().count(), 25);
+        }
+
+        let share = &split(&SECRET, 2, 3).unwrap()[0];
+        let mut mistyped = share.to_hex();
+        let last = if mistyped.ends_with('0') { "1" } else { "0" };
+        mistyped.replace_range(mistyped.len() - 1.., last);
+        assert!(mistyped.parse::<Share>().is_err());
+        assert!("2-x abandon".parse::<Share>().is_err());
+
+        // the words checksum covers threshold and index, not only the mnemonic
+        let words = share.to_words();
+        assert!(words.starts_with(&format!("2-{}-", share.index)));
+        assert!(words.replacen("2-", "3-", 1).parse::<Share>().is_err());
+        assert!(words.replacen(&format!("2-{}-", share.index), &format!("2-{}-", share.index + 1), 1).parse::<Share>().is_err());
+        let without_checksum = format!("2-{} {}", share.index, words.splitn(2, ' ').nth(1).unwrap());
+        assert!(without_checksum.parse::<Share>().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! EIP-191 personal messages and signature recovery.
+
+use ethereum_types::{Address, H256};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+use parity_crypto::publickey::{self, public_to_address, Public, Signature};
+use rustc_hex::{FromHex, ToHex};
+
+use crate::ethstore_rely::Error;
+
+/// Prefix `personal_sign` puts before the message length and the message.
+pub const PERSONAL_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n";
+
+/// Hash signed by `personal_sign`: keccak256 of the prefix, the decimal message length and the message.
+pub fn personal_hash(message: &[u8]) -> H256 {
+    let mut data = PERSONAL_PREFIX.to_vec();
+    data.extend(message.len().to_string().as_bytes());
+    data.extend(message);
+    KeccakHasher::hash(&data)
+}
+
+/// Parses a 65-byte `r || s || v` hex signature, `v` may be 0/1 or 27/28.
+pub fn parse_signature(value: &str) -> Result<Signature, Error> {
+    let invalid = || Error::InvalidInput("signature must be 65 bytes of hex".into());
+    let bytes: Vec<u8> = value.trim_start_matches("0x").from_hex().map_err(|_| invalid())?;
+    if bytes.len() != 65 {
+        return Err(invalid());
+    }
+    let v = match bytes[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        v => return Err(Error::InvalidInput(format!("signature recovery id {} must be 0, 1, 27 or 28", v))),
+    };
+    Ok(Signature::from_rsv(&H256::from_slice(&bytes[..32]), &H256::from_slice(&bytes[32..64]), v))
+}
+
+/// Formats the signature with `v` as 27 or 28, the form wallets return from `personal_sign`.
+pub fn format_rsv(signature: &Signature) -> String {
+    format!("0x{}{}{:02x}", signature.r().to_hex::<String>(), signature.s().to_hex::<String>(), signature.v() + 27)
+}
+
+/// Recovers the public key and address that signed `hash`.
+pub fn recover(signature: &Signature, hash: &H256) -> Result<(Public, Address), Error> {
+    let public = publickey::recover(signature, hash)
+        .map_err(|e| Error::InvalidInput(format!("cannot recover signer: {}", e)))?;
+    Ok((public, public_to_address(&public)))
+}
+
+/// Checks that `address` signed `hash`.
+pub fn verify(address: &Address, signature: &Signature, hash: &H256) -> Result<bool, Error> {
+    publickey::verify_address(address, signature, hash)
+        .map_err(|e| Error::InvalidInput(format!("cannot verify signature: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_crypto::publickey::{sign, KeyPair, Secret};
+
+    #[test]
+    fn should_hash_personal_message() {
+        assert_eq!(
+            format!("{:x}", personal_hash(b"hello")),
+            "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750"
+        );
+    }
+
+    #[test]
+    fn should_recover_and_verify_personal_signature() {
+        let secret: Secret = "4646464646464646464646464646464646464646464646464646464646464646".parse().unwrap();
+        let keypair = KeyPair::from_secret(secret.clone()).unwrap();
+        let hash = personal_hash("Grüße".as_bytes());
+        let signature = sign(&secret, &hash).unwrap();
+
+        let formatted = format_rsv(&signature);
+        assert!(formatted.ends_with("1b") || formatted.ends_with("1c"));
+        let parsed = parse_signature(&formatted).unwrap();
+        assert_eq!(parsed, signature);
+        assert_eq!(parse_signature(&format!("{}", signature)).unwrap(), signature);
+
+        assert_eq!(recover(&parsed, &hash).unwrap(), (*keypair.public(), keypair.address()));
+        assert!(verify(&keypair.address(), &parsed, &hash).unwrap());
+        assert!(!verify(&Address::zero(), &parsed, &hash).unwrap());
+        assert!(!verify(&keypair.address(), &parsed, &personal_hash(b"other")).unwrap());
+    }
+
+    #[test]
+    fn should_reject_malformed_signature() {
+        assert!(parse_signature("0x1234").is_err());
+        assert!(parse_signature(&format!("{}05", "00".repeat(64))).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Account record stored in the local world state.
+
+use ethereum_types::{H256, U256};
+use rlp::{DecoderError, Rlp, RlpStream};
+
+/// Keccak-256 of the empty byte string, the code hash of an account without code.
+pub const KECCAK_EMPTY: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+/// Keccak-256 of the RLP of the empty string, the root of an empty trie.
+pub const KECCAK_NULL_RLP: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+/// Nonce, balance, storage root and code hash of a single address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub nonce: U256,
+    pub balance: U256,
+    pub storage_root: H256,
+    pub code_hash: H256,
+}
+
+impl Account {
+    /// Creates an account without code and with empty storage.
+    pub fn new(balance: U256, nonce: U256) -> Self {
+        Account {
+            nonce,
+            balance,
+            storage_root: KECCAK_NULL_RLP,
+            code_hash: KECCAK_EMPTY,
+        }
+    }
+
+    /// Encodes the account the same way Ethereum stores it in the state trie.
+    pub fn rlp(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(4);
+        stream.append(&self.nonce);
+        stream.append(&self.balance);
+        stream.append(&self.storage_root);
+        stream.append(&self.code_hash);
+        stream.out()
+    }
+
+    /// Decodes an account produced by `rlp`.
+    pub fn from_rlp(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let rlp = Rlp::new(bytes);
+        if rlp.item_count()? != 4 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        Ok(Account {
+            nonce: rlp.val_at(0)?,
+            balance: rlp.val_at(1)?,
+            storage_root: rlp.val_at(2)?,
+            code_hash: rlp.val_at(3)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hash_db::Hasher;
+    use keccak_hasher::KeccakHasher;
+    use super::*;
+
+    #[test]
+    fn should_match_empty_hashes() {
+        assert_eq!(KeccakHasher::hash(&[]), KECCAK_EMPTY);
+        assert_eq!(KeccakHasher::hash(&[0x80]), KECCAK_NULL_RLP);
+    }
+
+    #[test]
+    fn should_roundtrip_rlp() {
+        let account = Account::new(1_000.into(), 7.into());
+        assert_eq!(Account::from_rlp(&account.rlp()).unwrap(), account);
+    }
+}
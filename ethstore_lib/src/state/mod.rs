@@ -0,0 +1,368 @@
+//! Persistent local world state used by `bloom-cmd account`.
+//!
+//! Accounts are RLP encoded and kept in a Merkle-Patricia state trie keyed by
+//! `keccak(address)`; every account has its own storage trie keyed by `keccak(slot)`.
+//! Trie nodes live in a `JournalDB` on top of an on-disk key-value store and the
+//! current state root is kept in a separate column. Every mutation is committed as a
+//! new journal era, nothing is ever pruned.
+//!
+//! Addresses and storage slots are also inserted as values, so the preimage of every
+//! hashed trie key can be looked up by that hash when dumping a trie.
+
+mod account;
+mod trie;
+
+use std::{fmt, io};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ethereum_types::{Address, H256, U256};
+use hash_db::{AsHashDB, HashDB, Hasher, EMPTY_PREFIX};
+use journaldb::{Algorithm, JournalDB};
+use kvdb::{DBTransaction, KeyValueDB};
+use keccak_hasher::KeccakHasher;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use rlp::Rlp;
+
+pub use self::account::{Account, KECCAK_EMPTY, KECCAK_NULL_RLP};
+pub use self::trie::{Trie, TrieBackend};
+
+/// Column holding the journaled, hash-addressed trie nodes.
+const COL_STATE: u32 = 0;
+/// Column holding the current state root.
+const COL_META: u32 = 1;
+const NUM_COLUMNS: u32 = 2;
+
+const STATE_ROOT_KEY: &[u8] = b"state_root";
+
+/// Errors raised by state operations.
+#[derive(Debug)]
+pub enum Error {
+    /// No account is stored for the address.
+    AccountNotFound(Address),
+    /// An account is already stored for the address.
+    AccountExists(Address),
+    /// Sender balance does not cover the transferred value.
+    InsufficientBalance { address: Address, balance: U256, required: U256 },
+    /// Arithmetic overflow of a balance or nonce.
+    Overflow,
+    /// Stored record could not be decoded.
+    Corrupted(String),
+    /// Underlying database failure.
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::AccountNotFound(ref address) => write!(f, "Account 0x{:x} does not exist", address),
+            Error::AccountExists(ref address) => write!(f, "Account 0x{:x} already exists", address),
+            Error::InsufficientBalance { ref address, ref balance, ref required } =>
+                write!(f, "Insufficient balance of 0x{:x}: has {}, requires {}", address, balance, required),
+            Error::Overflow => write!(f, "Balance or nonce overflow"),
+            Error::Corrupted(ref msg) => write!(f, "Corrupted state database: {}", msg),
+            Error::Io(ref err) => write!(f, "State database error: {}", err),
+        }
+    }
+}
+
+/// Default location of the state database.
+pub fn default_path() -> PathBuf {
+    let mut path = dir::default_data_pathbuf();
+    path.push("bloom");
+    path.push("state");
+    path
+}
+
+/// Changes to a single account, applied with `StateDB::apply`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountUpdate {
+    pub nonce: U256,
+    pub balance: U256,
+    /// New code, `None` keeps the stored code.
+    pub code: Option<Vec<u8>>,
+    /// Drop all stored slots before writing `storage`.
+    pub reset_storage: bool,
+    /// Slots to write, zero values clear the slot.
+    pub storage: Vec<(H256, U256)>,
+}
+
+/// Local world state.
+pub struct StateDB {
+    backing: Arc<dyn KeyValueDB>,
+    journal: Box<dyn JournalDB>,
+    trie: Trie,
+}
+
+impl StateDB {
+    /// Opens (or creates) the state database in the given directory.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let path = path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "State path is not valid UTF-8"))?;
+        let config = DatabaseConfig::with_columns(NUM_COLUMNS);
+        let db = Database::open(&config, path)?;
+        Self::with_backing(Arc::new(db))
+    }
+
+    /// Creates a state that lives in memory only.
+    pub fn in_memory() -> Self {
+        Self::with_backing(Arc::new(kvdb_memorydb::create(NUM_COLUMNS)))
+            .expect("empty in-memory database has no state root to decode; qed")
+    }
+
+    fn with_backing(backing: Arc<dyn KeyValueDB>) -> Result<Self, Error> {
+        let journal = journaldb::new(backing.clone(), Algorithm::Archive, COL_STATE);
+        let trie = match backing.get(COL_META, STATE_ROOT_KEY)? {
+            Some(ref root) if root.len() == 32 => Trie::from_root(H256::from_slice(root)),
+            Some(_) => return Err(Error::Corrupted("invalid state root".into())),
+            None => Trie::default(),
+        };
+        Ok(StateDB { backing, journal, trie })
+    }
+
+    /// Root hash of the state trie.
+    pub fn state_root(&self) -> H256 {
+        self.trie.root()
+    }
+
+    /// Returns the account stored for the address, if any.
+    pub fn account(&self, address: &Address) -> Result<Option<Account>, Error> {
+        match self.trie.get(self.journal.as_hash_db(), KeccakHasher::hash(address.as_bytes()).as_bytes())? {
+            Some(rlp) => Account::from_rlp(&rlp)
+                .map(Some)
+                .map_err(|e| Error::Corrupted(format!("account 0x{:x}: {}", address, e))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the account stored for the address or `AccountNotFound`.
+    pub fn existing_account(&self, address: &Address) -> Result<Account, Error> {
+        self.account(address)?.ok_or(Error::AccountNotFound(*address))
+    }
+
+    /// Creates an external account, fails if the address is taken.
+    pub fn create_account(&mut self, address: Address, balance: U256, nonce: U256) -> Result<Account, Error> {
+        if self.account(&address)?.is_some() {
+            return Err(Error::AccountExists(address));
+        }
+        let account = Account::new(balance, nonce);
+        self.commit(&[(address, &account)])?;
+        Ok(account)
+    }
+
+    /// Overwrites balance and nonce of an existing account.
+    pub fn modify_account(&mut self, address: Address, balance: U256, nonce: U256) -> Result<Account, Error> {
+        let mut account = self.existing_account(&address)?;
+        account.balance = balance;
+        account.nonce = nonce;
+        self.commit(&[(address, &account)])?;
+        Ok(account)
+    }
+
+    /// Moves `value` from `from` to `to` and bumps the sender nonce.
+    ///
+    /// The recipient is created if it does not exist yet. Returns both accounts after the transfer.
+    pub fn transfer(&mut self, from: Address, to: Address, value: U256) -> Result<(Account, Account), Error> {
+        let mut sender = self.existing_account(&from)?;
+        if sender.balance < value {
+            return Err(Error::InsufficientBalance { address: from, balance: sender.balance, required: value });
+        }
+        sender.balance -= value;
+        sender.nonce = sender.nonce.checked_add(U256::one()).ok_or(Error::Overflow)?;
+
+        let recipient = if from == to {
+            sender.balance += value;
+            sender.clone()
+        } else {
+            let mut recipient = self.account(&to)?.unwrap_or_else(|| Account::new(U256::zero(), U256::zero()));
+            recipient.balance = recipient.balance.checked_add(value).ok_or(Error::Overflow)?;
+            recipient
+        };
+
+        if from == to {
+            self.commit(&[(from, &sender)])?;
+        } else {
+            self.commit(&[(from, &sender), (to, &recipient)])?;
+        }
+        Ok((sender, recipient))
+    }
+
+    /// Returns the RLP encoded account as stored in the state trie.
+    pub fn account_rlp(&self, address: &Address) -> Result<Vec<u8>, Error> {
+        self.trie.get(self.journal.as_hash_db(), KeccakHasher::hash(address.as_bytes()).as_bytes())?
+            .ok_or(Error::AccountNotFound(*address))
+    }
+
+    /// Returns every non-zero storage slot of the account, ordered by hashed slot.
+    pub fn storage(&self, address: &Address) -> Result<Vec<(H256, U256)>, Error> {
+        let account = self.existing_account(address)?;
+        let db = self.journal.as_hash_db();
+        Trie::from_root(account.storage_root).entries(db)?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = H256::from_slice(&key);
+                let slot = db.get(&key, EMPTY_PREFIX)
+                    .filter(|slot| slot.len() == 32)
+                    .ok_or_else(|| Error::Corrupted(format!("missing preimage of storage key 0x{:x}", key)))?;
+                let value = Rlp::new(&value).as_val().map_err(|e| Error::Corrupted(format!("storage value: {}", e)))?;
+                Ok((H256::from_slice(&slot), value))
+            })
+            .collect()
+    }
+
+    /// Sets a storage slot of an existing account, a zero value clears the slot.
+    pub fn set_storage(&mut self, address: Address, slot: H256, value: U256) -> Result<Account, Error> {
+        let account = self.existing_account(&address)?;
+        self.apply(&[(address, Some(AccountUpdate {
+            nonce: account.nonce,
+            balance: account.balance,
+            code: None,
+            reset_storage: false,
+            storage: vec![(slot, value)],
+        }))])?;
+        self.existing_account(&address)
+    }
+
+    /// Returns the code of the account, empty for external or missing accounts.
+    pub fn code(&self, address: &Address) -> Result<Vec<u8>, Error> {
+        match self.account(address)? {
+            Some(ref account) if account.code_hash != KECCAK_EMPTY => self.journal.get(&account.code_hash, EMPTY_PREFIX)
+                .ok_or_else(|| Error::Corrupted(format!("missing code {:x} of 0x{:x}", account.code_hash, address))),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the value of a storage slot in the storage trie with given root.
+    pub fn storage_at(&self, storage_root: H256, slot: &H256) -> Result<U256, Error> {
+        match Trie::from_root(storage_root).get(self.journal.as_hash_db(), KeccakHasher::hash(slot.as_bytes()).as_bytes())? {
+            Some(value) => Rlp::new(&value).as_val().map_err(|e| Error::Corrupted(format!("storage value: {}", e))),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    /// Applies the account updates as a single new journal era. `None` removes the account.
+    pub fn apply(&mut self, updates: &[(Address, Option<AccountUpdate>)]) -> Result<(), Error> {
+        for (address, update) in updates {
+            let update = match update {
+                Some(update) => update,
+                None => {
+                    self.trie.remove(self.journal.as_hash_db_mut(), KeccakHasher::hash(address.as_bytes()).as_bytes())?;
+                    continue;
+                },
+            };
+            let mut account = self.account(address)?.unwrap_or_else(|| Account::new(U256::zero(), U256::zero()));
+            account.nonce = update.nonce;
+            account.balance = update.balance;
+
+            let db = self.journal.as_hash_db_mut();
+            if let Some(ref code) = update.code {
+                account.code_hash = if code.is_empty() { KECCAK_EMPTY } else { db.insert(EMPTY_PREFIX, code) };
+            }
+            let mut storage = Trie::from_root(if update.reset_storage { KECCAK_NULL_RLP } else { account.storage_root });
+            for (slot, value) in &update.storage {
+                let value = if value.is_zero() { Vec::new() } else { rlp::encode(value) };
+                db.insert(EMPTY_PREFIX, slot.as_bytes());
+                storage.insert(db, KeccakHasher::hash(slot.as_bytes()).as_bytes(), value)?;
+            }
+            account.storage_root = storage.root();
+            self.put_account(address, &account)?;
+        }
+        self.commit_era()
+    }
+
+    /// Writes the accounts as a single new journal era.
+    fn commit(&mut self, accounts: &[(Address, &Account)]) -> Result<(), Error> {
+        for (address, account) in accounts {
+            self.put_account(address, account)?;
+        }
+        self.commit_era()
+    }
+
+    fn put_account(&mut self, address: &Address, account: &Account) -> Result<(), Error> {
+        let db = self.journal.as_hash_db_mut();
+        db.insert(EMPTY_PREFIX, address.as_bytes());
+        self.trie.insert(db, KeccakHasher::hash(address.as_bytes()).as_bytes(), account.rlp())
+    }
+
+    fn commit_era(&mut self) -> Result<(), Error> {
+        let era = self.journal.latest_era().map_or(0, |era| era + 1);
+        let root = self.trie.root();
+        let mut batch = DBTransaction::new();
+        self.journal.journal_under(&mut batch, era, &root)?;
+        batch.put(COL_META, STATE_ROOT_KEY, root.as_bytes());
+        self.backing.write(batch)?;
+        self.journal.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_create_and_query_account() {
+        let mut state = StateDB::in_memory();
+        let address = Address::from_low_u64_be(1);
+        assert_eq!(state.account(&address).unwrap(), None);
+
+        let account = state.create_account(address, 100.into(), 1.into()).unwrap();
+        assert_eq!(state.account(&address).unwrap(), Some(account));
+        assert!(state.create_account(address, 0.into(), 0.into()).is_err());
+    }
+
+    #[test]
+    fn should_transfer_and_check_overdraft() {
+        let mut state = StateDB::in_memory();
+        let from = Address::from_low_u64_be(1);
+        let to = Address::from_low_u64_be(2);
+        state.create_account(from, 100.into(), 0.into()).unwrap();
+
+        let (sender, recipient) = state.transfer(from, to, 40.into()).unwrap();
+        assert_eq!(sender, Account::new(60.into(), 1.into()));
+        assert_eq!(recipient, Account::new(40.into(), 0.into()));
+        assert_eq!(state.existing_account(&to).unwrap(), recipient);
+
+        match state.transfer(from, to, 61.into()) {
+            Err(Error::InsufficientBalance { balance, .. }) => assert_eq!(balance, 60.into()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(state.existing_account(&from).unwrap(), sender);
+    }
+
+    #[test]
+    fn should_persist_between_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        let address = Address::from_low_u64_be(1);
+        {
+            let mut state = StateDB::open(dir.path()).unwrap();
+            state.create_account(address, 5.into(), 0.into()).unwrap();
+            state.modify_account(address, 7.into(), 3.into()).unwrap();
+        }
+        let state = StateDB::open(dir.path()).unwrap();
+        assert_eq!(state.existing_account(&address).unwrap(), Account::new(7.into(), 3.into()));
+        assert_ne!(state.state_root(), KECCAK_NULL_RLP);
+    }
+
+    #[test]
+    fn should_dump_storage() {
+        let mut state = StateDB::in_memory();
+        let address = Address::from_low_u64_be(1);
+        state.create_account(address, 0.into(), 0.into()).unwrap();
+        let root = state.state_root();
+
+        let account = state.set_storage(address, H256::from_low_u64_be(1), 42.into()).unwrap();
+        assert_ne!(account.storage_root, KECCAK_NULL_RLP);
+        assert_eq!(state.storage(&address).unwrap(), vec![(H256::from_low_u64_be(1), 42.into())]);
+
+        let account = state.set_storage(address, H256::from_low_u64_be(1), 0.into()).unwrap();
+        assert_eq!(account.storage_root, KECCAK_NULL_RLP);
+        assert!(state.storage(&address).unwrap().is_empty());
+        assert_eq!(state.state_root(), root);
+    }
+}
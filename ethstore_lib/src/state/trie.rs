@@ -0,0 +1,463 @@
+//! Merkle-Patricia trie over a `HashDB`.
+//!
+//! Nodes are encoded exactly as in Ethereum: leaves and extensions are two-item lists
+//! with a hex-prefix encoded path, branches are 17-item lists, and nodes whose encoding
+//! is shorter than 32 bytes are embedded in their parent instead of being stored by
+//! hash. The root node is always stored by hash. Old nodes are never removed, the
+//! database keeps every historical root readable.
+
+use ethereum_types::H256;
+use hash_db::{HashDB, EMPTY_PREFIX};
+use keccak_hasher::KeccakHasher;
+use kvdb::DBValue;
+use rlp::{Rlp, RlpStream};
+
+use super::{Error, KECCAK_NULL_RLP};
+
+/// Database the trie nodes live in.
+pub type TrieBackend = dyn HashDB<KeccakHasher, DBValue>;
+
+/// Reference from a parent node to a child.
+#[derive(Debug, Clone)]
+enum NodeRef {
+    Empty,
+    Hash(H256),
+    /// RLP of a node shorter than 32 bytes.
+    Inline(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Leaf(Vec<u8>, Vec<u8>),
+    Extension(Vec<u8>, NodeRef),
+    Branch(Box<[NodeRef; 16]>, Option<Vec<u8>>),
+}
+
+fn empty_children() -> Box<[NodeRef; 16]> {
+    Box::new([
+        NodeRef::Empty, NodeRef::Empty, NodeRef::Empty, NodeRef::Empty,
+        NodeRef::Empty, NodeRef::Empty, NodeRef::Empty, NodeRef::Empty,
+        NodeRef::Empty, NodeRef::Empty, NodeRef::Empty, NodeRef::Empty,
+        NodeRef::Empty, NodeRef::Empty, NodeRef::Empty, NodeRef::Empty,
+    ])
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| vec![b >> 4, b & 0x0f]).collect()
+}
+
+fn from_nibbles(nibbles: &[u8]) -> Vec<u8> {
+    nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair.get(1).cloned().unwrap_or(0)).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = a.to_vec();
+    out.extend_from_slice(b);
+    out
+}
+
+fn encode_path(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        out.push(flag << 4);
+        nibbles
+    };
+    out.extend(from_nibbles(rest));
+    out
+}
+
+fn decode_path(encoded: &[u8]) -> Result<(Vec<u8>, bool), Error> {
+    let first = *encoded.first().ok_or_else(|| Error::Corrupted("empty trie node path".into()))?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(Error::Corrupted(format!("invalid trie node path flag {}", flag)));
+    }
+    let mut nibbles = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    nibbles.extend(to_nibbles(&encoded[1..]));
+    Ok((nibbles, flag & 2 == 2))
+}
+
+fn append_ref(stream: &mut RlpStream, node_ref: &NodeRef) {
+    match *node_ref {
+        NodeRef::Empty => { stream.append_empty_data(); },
+        NodeRef::Hash(ref hash) => { stream.append(hash); },
+        NodeRef::Inline(ref rlp) => { stream.append_raw(rlp, 1); },
+    }
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    let stream = match *node {
+        Node::Empty => {
+            let mut stream = RlpStream::new();
+            stream.append_empty_data();
+            stream
+        },
+        Node::Leaf(ref path, ref value) => {
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&encode_path(path, true));
+            stream.append(value);
+            stream
+        },
+        Node::Extension(ref path, ref child) => {
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&encode_path(path, false));
+            append_ref(&mut stream, child);
+            stream
+        },
+        Node::Branch(ref children, ref value) => {
+            let mut stream = RlpStream::new_list(17);
+            for child in children.iter() {
+                append_ref(&mut stream, child);
+            }
+            match *value {
+                Some(ref value) => { stream.append(value); },
+                None => { stream.append_empty_data(); },
+            }
+            stream
+        },
+    };
+    stream.out()
+}
+
+fn decode_ref(rlp: &Rlp) -> Result<NodeRef, Error> {
+    if rlp.is_list() {
+        return Ok(NodeRef::Inline(rlp.as_raw().to_vec()));
+    }
+    let data = rlp.data().map_err(corrupted)?;
+    match data.len() {
+        0 => Ok(NodeRef::Empty),
+        32 => Ok(NodeRef::Hash(H256::from_slice(data))),
+        len => Err(Error::Corrupted(format!("invalid trie node reference of {} bytes", len))),
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Node, Error> {
+    let rlp = Rlp::new(bytes);
+    if !rlp.is_list() {
+        return match rlp.data().map_err(corrupted)?.len() {
+            0 => Ok(Node::Empty),
+            _ => Err(Error::Corrupted("trie node is not a list".into())),
+        };
+    }
+    match rlp.item_count().map_err(corrupted)? {
+        2 => {
+            let (path, leaf) = decode_path(rlp.at(0).map_err(corrupted)?.data().map_err(corrupted)?)?;
+            if leaf {
+                Ok(Node::Leaf(path, rlp.val_at(1).map_err(corrupted)?))
+            } else {
+                Ok(Node::Extension(path, decode_ref(&rlp.at(1).map_err(corrupted)?)?))
+            }
+        },
+        17 => {
+            let mut children = empty_children();
+            for (i, child) in children.iter_mut().enumerate() {
+                *child = decode_ref(&rlp.at(i).map_err(corrupted)?)?;
+            }
+            let value: Vec<u8> = rlp.val_at(16).map_err(corrupted)?;
+            Ok(Node::Branch(children, if value.is_empty() { None } else { Some(value) }))
+        },
+        count => Err(Error::Corrupted(format!("trie node with {} items", count))),
+    }
+}
+
+fn corrupted(err: rlp::DecoderError) -> Error {
+    Error::Corrupted(format!("trie node: {}", err))
+}
+
+/// Merkle-Patricia trie identified by its root hash.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trie {
+    root: H256,
+}
+
+impl Default for Trie {
+    fn default() -> Self {
+        Trie { root: KECCAK_NULL_RLP }
+    }
+}
+
+impl Trie {
+    /// Opens the trie with given root.
+    pub fn from_root(root: H256) -> Self {
+        Trie { root }
+    }
+
+    /// Current root hash.
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    /// Returns the value stored under the key.
+    pub fn get(&self, db: &TrieBackend, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut node = self.root_node(db)?;
+        let key = to_nibbles(key);
+        let mut key = &key[..];
+        loop {
+            node = match node {
+                Node::Empty => return Ok(None),
+                Node::Leaf(path, value) => return Ok(if path == key { Some(value) } else { None }),
+                Node::Extension(path, child) => {
+                    if !key.starts_with(&path) {
+                        return Ok(None);
+                    }
+                    key = &key[path.len()..];
+                    resolve(db, &child)?
+                },
+                Node::Branch(children, value) => match key.split_first() {
+                    None => return Ok(value),
+                    Some((&index, rest)) => {
+                        key = rest;
+                        resolve(db, &children[index as usize])?
+                    },
+                },
+            };
+        }
+    }
+
+    /// Stores the value under the key. An empty value removes the key.
+    pub fn insert(&mut self, db: &mut TrieBackend, key: &[u8], value: Vec<u8>) -> Result<(), Error> {
+        if value.is_empty() {
+            return self.remove(db, key);
+        }
+        let root = self.root_node(db)?;
+        let root = insert_at(db, root, &to_nibbles(key), value)?;
+        self.set_root(db, &root);
+        Ok(())
+    }
+
+    /// Removes the key.
+    pub fn remove(&mut self, db: &mut TrieBackend, key: &[u8]) -> Result<(), Error> {
+        let root = self.root_node(db)?;
+        let root = remove_at(db, root, &to_nibbles(key))?;
+        self.set_root(db, &root);
+        Ok(())
+    }
+
+    /// Returns all key/value pairs ordered by key.
+    pub fn entries(&self, db: &TrieBackend) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
+        let mut entries = Vec::new();
+        collect(db, self.root_node(db)?, Vec::new(), &mut entries)?;
+        Ok(entries)
+    }
+
+    fn root_node(&self, db: &TrieBackend) -> Result<Node, Error> {
+        if self.root == KECCAK_NULL_RLP {
+            return Ok(Node::Empty);
+        }
+        resolve(db, &NodeRef::Hash(self.root))
+    }
+
+    fn set_root(&mut self, db: &mut TrieBackend, root: &Node) {
+        self.root = match *root {
+            Node::Empty => KECCAK_NULL_RLP,
+            _ => db.insert(EMPTY_PREFIX, &encode(root)),
+        };
+    }
+}
+
+fn resolve(db: &TrieBackend, node_ref: &NodeRef) -> Result<Node, Error> {
+    match *node_ref {
+        NodeRef::Empty => Ok(Node::Empty),
+        NodeRef::Inline(ref rlp) => decode(rlp),
+        NodeRef::Hash(ref hash) => {
+            let rlp = db.get(hash, EMPTY_PREFIX).ok_or_else(|| Error::Corrupted(format!("missing trie node {:x}", hash)))?;
+            decode(&rlp)
+        },
+    }
+}
+
+fn reference(db: &mut TrieBackend, node: &Node) -> NodeRef {
+    if let Node::Empty = *node {
+        return NodeRef::Empty;
+    }
+    let rlp = encode(node);
+    if rlp.len() < 32 {
+        NodeRef::Inline(rlp)
+    } else {
+        NodeRef::Hash(db.insert(EMPTY_PREFIX, &rlp))
+    }
+}
+
+fn insert_at(db: &mut TrieBackend, node: Node, key: &[u8], value: Vec<u8>) -> Result<Node, Error> {
+    match node {
+        Node::Empty => Ok(Node::Leaf(key.to_vec(), value)),
+        Node::Leaf(path, old) => {
+            if path == key {
+                return Ok(Node::Leaf(path, value));
+            }
+            let common = common_prefix(&path, key);
+            let branch = Node::Branch(empty_children(), None);
+            let branch = insert_at(db, branch, &path[common..], old)?;
+            let branch = insert_at(db, branch, &key[common..], value)?;
+            Ok(extend(db, &key[..common], branch))
+        },
+        Node::Extension(path, child) => {
+            let common = common_prefix(&path, key);
+            if common == path.len() {
+                let child = resolve(db, &child)?;
+                let child = insert_at(db, child, &key[common..], value)?;
+                return Ok(Node::Extension(path, reference(db, &child)));
+            }
+            let mut children = empty_children();
+            let rest = &path[common + 1..];
+            children[path[common] as usize] = if rest.is_empty() {
+                child
+            } else {
+                let extension = Node::Extension(rest.to_vec(), child);
+                reference(db, &extension)
+            };
+            let branch = insert_at(db, Node::Branch(children, None), &key[common..], value)?;
+            Ok(extend(db, &key[..common], branch))
+        },
+        Node::Branch(mut children, old) => match key.split_first() {
+            None => Ok(Node::Branch(children, Some(value))),
+            Some((&index, rest)) => {
+                let child = resolve(db, &children[index as usize])?;
+                let child = insert_at(db, child, rest, value)?;
+                children[index as usize] = reference(db, &child);
+                Ok(Node::Branch(children, old))
+            },
+        },
+    }
+}
+
+/// Wraps a branch in an extension with given path, if the path is not empty.
+fn extend(db: &mut TrieBackend, path: &[u8], branch: Node) -> Node {
+    if path.is_empty() {
+        branch
+    } else {
+        Node::Extension(path.to_vec(), reference(db, &branch))
+    }
+}
+
+fn remove_at(db: &mut TrieBackend, node: Node, key: &[u8]) -> Result<Node, Error> {
+    match node {
+        Node::Empty => Ok(Node::Empty),
+        Node::Leaf(path, value) => Ok(if path == key { Node::Empty } else { Node::Leaf(path, value) }),
+        Node::Extension(path, child) => {
+            if !key.starts_with(&path) {
+                return Ok(Node::Extension(path, child));
+            }
+            let child = resolve(db, &child)?;
+            let child = remove_at(db, child, &key[path.len()..])?;
+            prefix_path(db, &path, child)
+        },
+        Node::Branch(mut children, mut value) => {
+            match key.split_first() {
+                None => value = None,
+                Some((&index, rest)) => {
+                    let child = resolve(db, &children[index as usize])?;
+                    let child = remove_at(db, child, rest)?;
+                    children[index as usize] = reference(db, &child);
+                },
+            }
+            collapse_branch(db, children, value)
+        },
+    }
+}
+
+/// Prepends `path` to the node, merging it with leaves and extensions.
+fn prefix_path(db: &mut TrieBackend, path: &[u8], node: Node) -> Result<Node, Error> {
+    Ok(match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf(rest, value) => Node::Leaf(concat(path, &rest), value),
+        Node::Extension(rest, child) => Node::Extension(concat(path, &rest), child),
+        branch @ Node::Branch(..) => extend(db, path, branch),
+    })
+}
+
+/// Replaces a branch left with a single child or only a value by an equivalent smaller node.
+fn collapse_branch(db: &mut TrieBackend, children: Box<[NodeRef; 16]>, value: Option<Vec<u8>>) -> Result<Node, Error> {
+    let mut used = children.iter().enumerate().filter(|(_, child)| match child {
+        NodeRef::Empty => false,
+        _ => true,
+    });
+    let first = used.next().map(|(i, _)| i);
+    let more = used.next().is_some();
+    match (first, more, value) {
+        (None, _, None) => Ok(Node::Empty),
+        (None, _, Some(value)) => Ok(Node::Leaf(Vec::new(), value)),
+        (Some(index), false, None) => {
+            let child = resolve(db, &children[index])?;
+            prefix_path(db, &[index as u8], child)
+        },
+        (_, _, value) => Ok(Node::Branch(children, value)),
+    }
+}
+
+fn collect(db: &TrieBackend, node: Node, prefix: Vec<u8>, entries: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<(), Error> {
+    match node {
+        Node::Empty => {},
+        Node::Leaf(path, value) => entries.push((from_nibbles(&concat(&prefix, &path)), value)),
+        Node::Extension(path, child) => collect(db, resolve(db, &child)?, concat(&prefix, &path), entries)?,
+        Node::Branch(children, value) => {
+            if let Some(value) = value {
+                entries.push((from_nibbles(&prefix), value));
+            }
+            for (index, child) in children.iter().enumerate() {
+                collect(db, resolve(db, child)?, concat(&prefix, &[index as u8]), entries)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type MemoryDB = memory_db::MemoryDB<KeccakHasher, memory_db::HashKey<KeccakHasher>, DBValue>;
+
+    #[test]
+    fn should_compute_ethereum_roots() {
+        let mut db = MemoryDB::default();
+        let mut trie = Trie::default();
+        assert_eq!(trie.root(), KECCAK_NULL_RLP);
+
+        // Known roots from the Ethereum trie test suite (`trietest.json`, "dogs").
+        trie.insert(&mut db, b"doe", b"reindeer".to_vec()).unwrap();
+        trie.insert(&mut db, b"dog", b"puppy".to_vec()).unwrap();
+        trie.insert(&mut db, b"dogglesworth", b"cat".to_vec()).unwrap();
+        assert_eq!(format!("{:x}", trie.root()), "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
+
+        assert_eq!(trie.get(&db, b"dog").unwrap(), Some(b"puppy".to_vec()));
+        assert_eq!(trie.get(&db, b"do").unwrap(), None);
+    }
+
+    #[test]
+    fn should_restore_root_after_remove() {
+        let mut db = MemoryDB::default();
+        let mut trie = Trie::default();
+        trie.insert(&mut db, b"do", b"verb".to_vec()).unwrap();
+        trie.insert(&mut db, b"horse", b"stallion".to_vec()).unwrap();
+        let root = trie.root();
+
+        trie.insert(&mut db, b"dog", b"puppy".to_vec()).unwrap();
+        trie.insert(&mut db, b"doge", b"coin".to_vec()).unwrap();
+        assert_eq!(trie.entries(&db).unwrap().len(), 4);
+
+        trie.remove(&mut db, b"doge").unwrap();
+        trie.insert(&mut db, b"dog", Vec::new()).unwrap();
+        assert_eq!(trie.root(), root);
+        assert_eq!(trie.entries(&db).unwrap(), vec![
+            (b"do".to_vec(), b"verb".to_vec()),
+            (b"horse".to_vec(), b"stallion".to_vec()),
+        ]);
+
+        trie.remove(&mut db, b"do").unwrap();
+        trie.remove(&mut db, b"horse").unwrap();
+        assert_eq!(trie.root(), KECCAK_NULL_RLP);
+    }
+}
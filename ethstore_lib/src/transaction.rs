@@ -0,0 +1,522 @@
+//! Ethereum transactions: legacy ones with EIP-155 signatures and the EIP-2718 typed
+//! envelopes for EIP-2930 access lists and EIP-1559 fees.
+
+use std::fmt;
+
+use ethereum_types::{Address, H256, U256};
+use hash_db::Hasher;
+use keccak_hasher::KeccakHasher;
+use parity_crypto::publickey::{public_to_address, recover, Signature};
+use rlp::{DecoderError, Rlp, RlpStream};
+use serde_derive::{Deserialize, Serialize};
+
+/// EIP-2718 type byte of access list transactions.
+pub const ACCESS_LIST_TYPE: u8 = 0x01;
+/// EIP-2718 type byte of dynamic fee transactions.
+pub const DYNAMIC_FEE_TYPE: u8 = 0x02;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Transaction is not valid RLP or has the wrong fields.
+    Rlp(DecoderError),
+    /// First byte is not a known transaction type.
+    UnknownType(u8),
+    /// Signature values are out of range or do not recover a sender.
+    InvalidSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Rlp(ref err) => write!(f, "malformed transaction: {}", err),
+            Error::UnknownType(kind) => write!(f, "unknown transaction type 0x{:02x}", kind),
+            Error::InvalidSignature => write!(f, "invalid transaction signature"),
+        }
+    }
+}
+
+impl From<DecoderError> for Error {
+    fn from(err: DecoderError) -> Self {
+        Error::Rlp(err)
+    }
+}
+
+/// Address and storage slots a transaction declares it will access, as in JSON-RPC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+pub type AccessList = Vec<AccessListItem>;
+
+/// Unsigned legacy transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    /// EIP-155 chain id, `None` signs without replay protection.
+    pub chain_id: Option<u64>,
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas: U256,
+    /// Receiver, `None` deploys a contract.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+/// Unsigned EIP-2930 transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessListTransaction {
+    pub chain_id: u64,
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas: U256,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+}
+
+/// Unsigned EIP-1559 transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicFeeTransaction {
+    pub chain_id: u64,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas: U256,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+}
+
+/// Unsigned transaction of any supported type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedTransaction {
+    Legacy(Transaction),
+    AccessList(AccessListTransaction),
+    DynamicFee(DynamicFeeTransaction),
+}
+
+/// Signed transaction, ready to be broadcast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedTransaction {
+    /// Encoded transaction: RLP for legacy ones, type byte and RLP payload otherwise.
+    pub raw: Vec<u8>,
+    /// Transaction hash, keccak of `raw`.
+    pub hash: H256,
+}
+
+/// Transaction decoded from its raw form, with the sender recovered from the signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTransaction {
+    pub transaction: TypedTransaction,
+    pub signature: Signature,
+    pub sender: Address,
+    pub hash: H256,
+}
+
+fn append_to(s: &mut RlpStream, to: &Option<Address>) {
+    match *to {
+        Some(ref to) => s.append(to),
+        None => s.append_empty_data(),
+    };
+}
+
+fn append_access_list(s: &mut RlpStream, access_list: &[AccessListItem]) {
+    s.begin_list(access_list.len());
+    for item in access_list {
+        s.begin_list(2);
+        s.append(&item.address);
+        s.begin_list(item.storage_keys.len());
+        for key in &item.storage_keys {
+            s.append(key);
+        }
+    }
+}
+
+fn decode_to(rlp: &Rlp, index: usize) -> Result<Option<Address>, DecoderError> {
+    let to = rlp.at(index)?;
+    if to.is_empty() { Ok(None) } else { to.as_val().map(Some) }
+}
+
+fn decode_access_list(rlp: &Rlp, index: usize) -> Result<AccessList, DecoderError> {
+    rlp.at(index)?
+        .iter()
+        .map(|item| -> Result<_, DecoderError> {
+            Ok(AccessListItem { address: item.val_at(0)?, storage_keys: item.list_at(1)? })
+        })
+        .collect()
+}
+
+fn expect_fields(rlp: &Rlp, count: usize) -> Result<(), DecoderError> {
+    if rlp.item_count()? == count { Ok(()) } else { Err(DecoderError::RlpIncorrectListLen) }
+}
+
+fn typed_payload(kind: u8, s: RlpStream) -> Vec<u8> {
+    let mut raw = vec![kind];
+    raw.extend(s.out());
+    raw
+}
+
+fn signature_values(s: &mut RlpStream, v: U256, signature: &Signature) {
+    s.append(&v);
+    s.append(&U256::from_big_endian(signature.r()));
+    s.append(&U256::from_big_endian(signature.s()));
+}
+
+/// Reads `v, r, s` starting at `index`, `v` is returned as is.
+fn decode_signature_values(rlp: &Rlp, index: usize) -> Result<(U256, H256, H256), Error> {
+    let v: U256 = rlp.val_at(index)?;
+    let r: U256 = rlp.val_at(index + 1)?;
+    let s: U256 = rlp.val_at(index + 2)?;
+    let mut r_bytes = H256::zero();
+    let mut s_bytes = H256::zero();
+    r.to_big_endian(r_bytes.as_bytes_mut());
+    s.to_big_endian(s_bytes.as_bytes_mut());
+    Ok((v, r_bytes, s_bytes))
+}
+
+impl Transaction {
+    fn append_fields(&self, s: &mut RlpStream) {
+        s.append(&self.nonce);
+        s.append(&self.gas_price);
+        s.append(&self.gas);
+        append_to(s, &self.to);
+        s.append(&self.value);
+        s.append(&self.data);
+    }
+
+    /// Hash to sign. With a chain id, `chain_id, 0, 0` are appended as EIP-155 requires.
+    pub fn signing_hash(&self) -> H256 {
+        let mut s = RlpStream::new();
+        match self.chain_id {
+            Some(chain_id) => {
+                s.begin_list(9);
+                self.append_fields(&mut s);
+                s.append(&chain_id);
+                s.append(&0u8);
+                s.append(&0u8);
+            },
+            None => {
+                s.begin_list(6);
+                self.append_fields(&mut s);
+            },
+        }
+        KeccakHasher::hash(&s.out())
+    }
+
+    /// Attaches a signature of `signing_hash()`.
+    pub fn with_signature(&self, signature: &Signature) -> SignedTransaction {
+        let recovery_id = U256::from(signature.v());
+        let v = match self.chain_id {
+            Some(chain_id) => recovery_id + U256::from(chain_id) * 2 + 35,
+            None => recovery_id + 27,
+        };
+        let mut s = RlpStream::new_list(9);
+        self.append_fields(&mut s);
+        signature_values(&mut s, v, signature);
+        SignedTransaction::new(s.out())
+    }
+
+    fn decode(rlp: &Rlp) -> Result<(Self, Signature), Error> {
+        expect_fields(rlp, 9)?;
+        let (v, r, s) = decode_signature_values(rlp, 6)?;
+        let (chain_id, recovery_id) = if v == 27.into() || v == 28.into() {
+            (None, v.low_u32() - 27)
+        } else if v >= 35.into() && v <= U256::from(u64::max_value()) {
+            let v = v.low_u64() - 35;
+            (Some(v / 2), (v % 2) as u32)
+        } else {
+            return Err(Error::InvalidSignature);
+        };
+        let transaction = Transaction {
+            chain_id,
+            nonce: rlp.val_at(0)?,
+            gas_price: rlp.val_at(1)?,
+            gas: rlp.val_at(2)?,
+            to: decode_to(rlp, 3)?,
+            value: rlp.val_at(4)?,
+            data: rlp.val_at(5)?,
+        };
+        Ok((transaction, Signature::from_rsv(&r, &s, recovery_id as u8)))
+    }
+}
+
+impl AccessListTransaction {
+    fn append_fields(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.gas_price);
+        s.append(&self.gas);
+        append_to(s, &self.to);
+        s.append(&self.value);
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+
+    /// Hash to sign: keccak of the type byte followed by the unsigned payload.
+    pub fn signing_hash(&self) -> H256 {
+        let mut s = RlpStream::new_list(8);
+        self.append_fields(&mut s);
+        KeccakHasher::hash(&typed_payload(ACCESS_LIST_TYPE, s))
+    }
+
+    /// Attaches a signature of `signing_hash()`.
+    pub fn with_signature(&self, signature: &Signature) -> SignedTransaction {
+        let mut s = RlpStream::new_list(11);
+        self.append_fields(&mut s);
+        signature_values(&mut s, signature.v().into(), signature);
+        SignedTransaction::new(typed_payload(ACCESS_LIST_TYPE, s))
+    }
+
+    fn decode(rlp: &Rlp) -> Result<(Self, Signature), Error> {
+        expect_fields(rlp, 11)?;
+        let transaction = AccessListTransaction {
+            chain_id: rlp.val_at(0)?,
+            nonce: rlp.val_at(1)?,
+            gas_price: rlp.val_at(2)?,
+            gas: rlp.val_at(3)?,
+            to: decode_to(rlp, 4)?,
+            value: rlp.val_at(5)?,
+            data: rlp.val_at(6)?,
+            access_list: decode_access_list(rlp, 7)?,
+        };
+        Ok((transaction, typed_signature(rlp, 8)?))
+    }
+}
+
+impl DynamicFeeTransaction {
+    fn append_fields(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.max_priority_fee_per_gas);
+        s.append(&self.max_fee_per_gas);
+        s.append(&self.gas);
+        append_to(s, &self.to);
+        s.append(&self.value);
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+
+    /// Hash to sign: keccak of the type byte followed by the unsigned payload.
+    pub fn signing_hash(&self) -> H256 {
+        let mut s = RlpStream::new_list(9);
+        self.append_fields(&mut s);
+        KeccakHasher::hash(&typed_payload(DYNAMIC_FEE_TYPE, s))
+    }
+
+    /// Attaches a signature of `signing_hash()`.
+    pub fn with_signature(&self, signature: &Signature) -> SignedTransaction {
+        let mut s = RlpStream::new_list(12);
+        self.append_fields(&mut s);
+        signature_values(&mut s, signature.v().into(), signature);
+        SignedTransaction::new(typed_payload(DYNAMIC_FEE_TYPE, s))
+    }
+
+    fn decode(rlp: &Rlp) -> Result<(Self, Signature), Error> {
+        expect_fields(rlp, 12)?;
+        let transaction = DynamicFeeTransaction {
+            chain_id: rlp.val_at(0)?,
+            nonce: rlp.val_at(1)?,
+            max_priority_fee_per_gas: rlp.val_at(2)?,
+            max_fee_per_gas: rlp.val_at(3)?,
+            gas: rlp.val_at(4)?,
+            to: decode_to(rlp, 5)?,
+            value: rlp.val_at(6)?,
+            data: rlp.val_at(7)?,
+            access_list: decode_access_list(rlp, 8)?,
+        };
+        Ok((transaction, typed_signature(rlp, 9)?))
+    }
+}
+
+/// Signature of a typed transaction, whose `v` is the plain y parity.
+fn typed_signature(rlp: &Rlp, index: usize) -> Result<Signature, Error> {
+    let (v, r, s) = decode_signature_values(rlp, index)?;
+    if v > U256::one() {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(Signature::from_rsv(&r, &s, v.low_u32() as u8))
+}
+
+impl SignedTransaction {
+    fn new(raw: Vec<u8>) -> Self {
+        SignedTransaction { hash: KeccakHasher::hash(&raw), raw }
+    }
+}
+
+impl TypedTransaction {
+    /// EIP-2718 type byte, `0` for legacy transactions.
+    pub fn kind(&self) -> u8 {
+        match *self {
+            TypedTransaction::Legacy(_) => 0,
+            TypedTransaction::AccessList(_) => ACCESS_LIST_TYPE,
+            TypedTransaction::DynamicFee(_) => DYNAMIC_FEE_TYPE,
+        }
+    }
+
+    pub fn signing_hash(&self) -> H256 {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.signing_hash(),
+            TypedTransaction::AccessList(ref tx) => tx.signing_hash(),
+            TypedTransaction::DynamicFee(ref tx) => tx.signing_hash(),
+        }
+    }
+
+    pub fn with_signature(&self, signature: &Signature) -> SignedTransaction {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.with_signature(signature),
+            TypedTransaction::AccessList(ref tx) => tx.with_signature(signature),
+            TypedTransaction::DynamicFee(ref tx) => tx.with_signature(signature),
+        }
+    }
+}
+
+/// Decodes a raw legacy or typed transaction and recovers its sender.
+pub fn decode(raw: &[u8]) -> Result<DecodedTransaction, Error> {
+    let (transaction, signature) = match raw.first() {
+        None => return Err(DecoderError::RlpIsTooShort.into()),
+        Some(&byte) if byte >= 0xc0 => {
+            let (tx, signature) = Transaction::decode(&Rlp::new(raw))?;
+            (TypedTransaction::Legacy(tx), signature)
+        },
+        Some(&ACCESS_LIST_TYPE) => {
+            let (tx, signature) = AccessListTransaction::decode(&Rlp::new(&raw[1..]))?;
+            (TypedTransaction::AccessList(tx), signature)
+        },
+        Some(&DYNAMIC_FEE_TYPE) => {
+            let (tx, signature) = DynamicFeeTransaction::decode(&Rlp::new(&raw[1..]))?;
+            (TypedTransaction::DynamicFee(tx), signature)
+        },
+        Some(&kind) => return Err(Error::UnknownType(kind)),
+    };
+    if !signature.is_valid() {
+        return Err(Error::InvalidSignature);
+    }
+    let public = recover(&signature, &transaction.signing_hash()).map_err(|_| Error::InvalidSignature)?;
+    Ok(DecodedTransaction {
+        transaction,
+        signature,
+        sender: public_to_address(&public),
+        hash: KeccakHasher::hash(raw),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_crypto::publickey::{sign, KeyPair, Secret};
+
+    fn secret() -> Secret {
+        "4646464646464646464646464646464646464646464646464646464646464646".parse().unwrap()
+    }
+
+    // Example transaction from the EIP-155 specification.
+    fn transaction() -> Transaction {
+        Transaction {
+            chain_id: Some(1),
+            nonce: 9.into(),
+            gas_price: U256::from(20) * U256::exp10(9),
+            gas: 21000.into(),
+            to: Some("3535353535353535353535353535353535353535".parse().unwrap()),
+            value: U256::exp10(18),
+            data: Vec::new(),
+        }
+    }
+
+    fn access_list() -> AccessList {
+        vec![AccessListItem {
+            address: "0000000000000000000000000000000000000002".parse().unwrap(),
+            storage_keys: vec![H256::zero(), H256::from_low_u64_be(1)],
+        }]
+    }
+
+    fn sign_and_decode(tx: TypedTransaction) -> DecodedTransaction {
+        let signature = sign(&secret(), &tx.signing_hash()).unwrap();
+        let signed = tx.with_signature(&signature);
+        let decoded = decode(&signed.raw).unwrap();
+        assert_eq!(decoded.transaction, tx);
+        assert_eq!(decoded.signature, signature);
+        assert_eq!(decoded.sender, KeyPair::from_secret(secret()).unwrap().address());
+        assert_eq!(decoded.hash, signed.hash);
+        decoded
+    }
+
+    #[test]
+    fn should_sign_eip155_transaction() {
+        let tx = transaction();
+        let hash = tx.signing_hash();
+        assert_eq!(format!("{:x}", hash), "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53");
+
+        let signed = tx.with_signature(&sign(&secret(), &hash).unwrap());
+        assert_eq!(
+            rustc_hex::ToHex::to_hex::<String>(&signed.raw[..]),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        assert_eq!(signed.hash, KeccakHasher::hash(&signed.raw));
+        sign_and_decode(TypedTransaction::Legacy(tx));
+    }
+
+    #[test]
+    fn should_encode_contract_creation_without_chain_id() {
+        let tx = Transaction { chain_id: None, to: None, ..transaction() };
+        assert_ne!(tx.signing_hash(), transaction().signing_hash());
+
+        let signature = sign(&secret(), &tx.signing_hash()).unwrap();
+        let signed = tx.with_signature(&signature);
+        let rlp = Rlp::new(&signed.raw);
+        assert_eq!(rlp.item_count().unwrap(), 9);
+        assert!(rlp.at(3).unwrap().is_empty());
+        assert_eq!(rlp.val_at::<u8>(6).unwrap(), 27 + signature.v());
+        sign_and_decode(TypedTransaction::Legacy(tx));
+    }
+
+    #[test]
+    fn should_round_trip_access_list_transaction() {
+        let tx = AccessListTransaction {
+            chain_id: 5,
+            nonce: 1.into(),
+            gas_price: 10.into(),
+            gas: 30000.into(),
+            to: Some("3535353535353535353535353535353535353535".parse().unwrap()),
+            value: 1.into(),
+            data: vec![0xab],
+            access_list: access_list(),
+        };
+        let signed = tx.with_signature(&sign(&secret(), &tx.signing_hash()).unwrap());
+        assert_eq!(signed.raw[0], ACCESS_LIST_TYPE);
+        assert_eq!(Rlp::new(&signed.raw[1..]).item_count().unwrap(), 11);
+        sign_and_decode(TypedTransaction::AccessList(tx));
+    }
+
+    #[test]
+    fn should_round_trip_dynamic_fee_transaction() {
+        let tx = DynamicFeeTransaction {
+            chain_id: 1,
+            nonce: 0.into(),
+            max_priority_fee_per_gas: U256::exp10(9),
+            max_fee_per_gas: U256::from(100) * U256::exp10(9),
+            gas: 21000.into(),
+            to: None,
+            value: 0.into(),
+            data: vec![0x60, 0x00],
+            access_list: access_list(),
+        };
+        let decoded = sign_and_decode(TypedTransaction::DynamicFee(tx));
+        assert_eq!(decoded.transaction.kind(), DYNAMIC_FEE_TYPE);
+    }
+
+    #[test]
+    fn should_reject_unknown_type_and_parse_json_access_list() {
+        assert!(matches!(decode(&[0x03, 0xc0]), Err(Error::UnknownType(0x03))));
+        assert!(matches!(decode(&[]), Err(Error::Rlp(_))));
+
+        let json = r#"[{"address":"0x0000000000000000000000000000000000000002","storageKeys":[
+            "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "0x0000000000000000000000000000000000000000000000000000000000000001"]}]"#;
+        assert_eq!(serde_json::from_str::<AccessList>(json).unwrap(), access_list());
+    }
+}
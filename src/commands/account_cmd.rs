@@ -3,6 +3,12 @@ use ethereum_types::{H160, H256, U256};
 use std::fmt;
 use std::collections::BTreeMap;
 use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
+use ethstore_lib::ethstore_rely::Error;
+use ethstore_lib::state::Account;
+use serde_json::json;
+
+use crate::output::Output;
+use super::{open_state, parse_address, parse_u256};
 
 // target/debug/bloom-cmd account create --address 59a5208b32e627891c389ebafc644145224006e8 --value 10 --nonce 12
 // target/debug/bloom-cmd account query --address 59a5208b32e627891c389ebafc644145224006e8
@@ -10,6 +16,10 @@ use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("..."
 
 #[derive(Debug, Clone, StructOpt)]
 pub struct AccountCmd {
+	/// State database directory, defaults to <data dir>/bloom/state
+	#[structopt(long = "db", global = true)]
+	db: Option<String>,
+
 	#[structopt(subcommand)]
 	cmd: Command
 }
@@ -74,46 +84,82 @@ enum Command {
 }
 
 
+fn account_output(address: &H160, account: &Account) -> Output {
+	let address = format!("0x{:x}", address);
+	Output::new(
+		format!("address: {}\nnonce: {}\nbalance: {}\ncode_hash: 0x{:x}\nstorage_root: 0x{:x}",
+			address, account.nonce, account.balance, account.code_hash, account.storage_root),
+		json!({
+			"address": address,
+			"nonce": account.nonce.to_string(),
+			"balance": account.balance.to_string(),
+			"code_hash": format!("0x{:x}", account.code_hash),
+			"storage_root": format!("0x{:x}", account.storage_root),
+		}),
+	)
+}
+
 impl AccountCmd {
-	pub fn run(&self, mut backend: &str) {
+	pub fn run(&self) -> Result<Output, Error> {
 		match &self.cmd {
 			Command::Query {address, storage_trie} => {
-
-				println!("Query {:#?}", backend);
-				println!(" address=={:#?}\n storage_trie=={:#?}\n", address, storage_trie);
+				let address = parse_address("--address argument", address)?;
+				let state = open_state(&self.db)?;
+				let account = state.existing_account(&address)?;
+				let rlp = format!("0x{}", hex::encode(state.account_rlp(&address)?));
+				let state_root = format!("0x{:x}", state.state_root());
+
+				let output = account_output(&address, &account);
+				let mut text = format!("{}\nrlp: {}\nstate_root: {}", output.text(), rlp, state_root);
+				let mut json = output.json().clone();
+				json["rlp"] = json!(rlp);
+				json["state_root"] = json!(state_root);
+
+				if *storage_trie {
+					let storage = state.storage(&address)?;
+					text.push_str("\nstorage:");
+					for (slot, value) in &storage {
+						text.push_str(&format!("\n  0x{:x}: {}", slot, value));
+					}
+					json["storage"] = storage.iter()
+						.map(|(slot, value)| (format!("0x{:x}", slot), json!(value.to_string())))
+						.collect::<serde_json::Map<_, _>>()
+						.into();
+				}
+				Ok(Output::new(text, json))
 			},
 
 			Command::Create {address,value,nonce} => {
-				let from = H160::from_str(address).expect("--address argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
-				let nonce = U256::from_dec_str(nonce.as_str()).expect("--nonce argument must be a valid number");
-				println!("Create {:#?}", backend);
-				println!(" address=={:#?}\n value=={:#?}\n nonce=={:#?}\n", address, value, nonce);
-
+				let address = parse_address("--address argument", address)?;
+				let value = parse_u256("--value argument", value)?;
+				let nonce = parse_u256("--nonce argument", nonce)?;
+				let account = open_state(&self.db)?.create_account(address, value, nonce)?;
+				Ok(account_output(&address, &account))
 			},
 
 			Command::Modify {address, value, nonce} => {
-				let from = H160::from_str(address).expect("--address argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
-				let nonce = U256::from_dec_str(nonce.as_str()).expect("--nonce argument must be a valid number");
-				println!("Modify {:#?}", backend);
-				println!(" address=={:#?}\n value=={:#?}\n nonce=={:#?}\n", address, value, nonce);
-
+				let address = parse_address("--address argument", address)?;
+				let value = parse_u256("--value argument", value)?;
+				let nonce = parse_u256("--nonce argument", nonce)?;
+				let account = open_state(&self.db)?.modify_account(address, value, nonce)?;
+				Ok(account_output(&address, &account))
 			},
 
 			Command::Transfer {from, to, value} => {
-
-				let from = H160::from_str(from).expect("--from argument must be a valid address");
-				let to  = H160::from_str(to).expect("--to argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
-
-				println!("Transfer {:#?}", backend);
-				println!(" from=={:#?}\n to=={:#?}\n value=={:#?}\n", from, to, value);
+				let from = parse_address("--from argument", from)?;
+				let to  = parse_address("--to argument", to)?;
+				let value = parse_u256("--value argument", value)?;
+				let (sender, recipient) = open_state(&self.db)?.transfer(from, to, value)?;
+				let sender = account_output(&from, &sender);
+				let recipient = account_output(&to, &recipient);
+				Ok(Output::new(
+					format!("{}\n\n{}", sender.text(), recipient.text()),
+					json!({ "from": sender.json(), "to": recipient.json() }),
+				))
 			},
 			Command::Test_one{foo}=> {
-				let foo=U256::from_dec_str(foo.as_str()).expect("--foo argument must be a value");
-				println!("Test_one {:#?}",backend);
-				println!("foo = {:#?}",foo);
+				let foo = parse_u256("--foo argument", foo)?;
+				Ok(Output::new(foo.to_string(), json!({ "foo": foo.to_string() })))
 			}
 		}
 	}
@@ -3,19 +3,35 @@ use ethereum_types::{H160, U256, H256};
 use hex;
 use structopt::StructOpt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
 use std::collections::BTreeMap;
+use ethstore_lib::ethstore_rely::Error;
+use ethstore_lib::abi::{self, Contract, Function, Value};
+use ethstore_lib::evm::{self, Receipt, Status, Transaction};
+use serde_json::json;
+
+use crate::output::Output;
+use super::{open_state, parse_address, parse_bytes, parse_u256};
 
 /*
 target/debug/bloom-cmd contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 100000 --gas-price 0 --code-file ./code-file
 target/debug/bloom-cmd contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 100000 --gas-price 0 --code 000000
 target/debug/bloom-cmd contract call --from 0000000000000000000000000000000000000001  --to 0000000000000000000000000000000000000002 --value 0 --gas 100000 --gas-price 0 --data 000000
+target/debug/bloom-cmd contract call --from 0000000000000000000000000000000000000001  --to 0000000000000000000000000000000000000002 --value 0 --gas 100000 --gas-price 0 --abi ./token.abi --function transfer --args 0000000000000000000000000000000000000003 100
 */
 
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct ContractCmd {
+    /// State database directory, defaults to <data dir>/bloom/state
+    #[structopt(long = "db", global = true)]
+    db: Option<String>,
+
+    /// The chain id returned by the CHAINID instruction
+    #[structopt(long = "chain-id", default_value = "1", global = true)]
+    chain_id: u64,
+
     #[structopt(subcommand)]
     cmd: Command
 }
@@ -49,6 +65,14 @@ enum Command {
         #[structopt(long = "code-file")]
         code_file: Option<String>,
 
+        /// The contract JSON ABI file, used to encode constructor arguments
+        #[structopt(long = "abi")]
+        abi: Option<String>,
+
+        /// The constructor arguments, appended to the code
+        #[structopt(long = "args", requires = "abi", allow_hyphen_values = true)]
+        args: Vec<String>,
+
     },
 
     /// Message call
@@ -81,81 +105,181 @@ enum Command {
         #[structopt(long = "data-file")]
         data_file: Option<String>,
 
+        /// The contract JSON ABI file, used to encode the call and decode its results
+        #[structopt(long = "abi")]
+        abi: Option<String>,
+
+        /// The function to call, encoded with --abi instead of --data
+        #[structopt(long = "function", requires = "abi", conflicts_with_all = &["data", "data_file"])]
+        function: Option<String>,
+
+        /// The function arguments
+        #[structopt(long = "args", requires = "function", allow_hyphen_values = true)]
+        args: Vec<String>,
+
+    }
+}
+
+
+/// Returns the inline value if given, otherwise the contents of the file, if given.
+fn inline_or_file(inline: &Option<String>, file: &Option<String>) -> Result<Option<String>, Error> {
+    match (inline, file) {
+        (Some(value), _) => Ok(Some(value.clone())),
+        (None, Some(file)) => {
+            let mut contents = String::new();
+            File::open(file)
+                .and_then(|mut f| f.read_to_string(&mut contents))
+                .map_err(|e| io::Error::new(e.kind(), format!("Error reading '{}': {}", file, e)))?;
+            Ok(Some(contents))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+fn value_json(value: &Value) -> serde_json::Value {
+    json!({
+        "name": value.name,
+        "type": value.kind,
+        "value": abi::format_token(&value.token),
+    })
+}
+
+fn value_text(value: &Value) -> String {
+    if value.name.is_empty() {
+        format!("({}) {}", value.kind, abi::format_token(&value.token))
+    } else {
+        format!("{} ({}) {}", value.name, value.kind, abi::format_token(&value.token))
     }
 }
 
+/// Prints the receipt, decoding the return data with `function` and the logs with `contract` when given.
+fn receipt_output(receipt: &Receipt, state_root: &H256, contract: Option<&Contract>, function: Option<&Function>) -> Output {
+    let (status, error) = match receipt.status {
+        Status::Success => ("success", None),
+        Status::Revert => ("revert", None),
+        Status::Failure(ref msg) => ("failure", Some(msg.clone())),
+    };
+    let output = format!("0x{}", hex::encode(&receipt.output));
+    let contract_address = receipt.contract_address.map(|a| format!("0x{:x}", a));
+    let returns = match (&receipt.status, function) {
+        (Status::Success, Some(function)) => abi::decode_output(function, &receipt.output).ok(),
+        _ => None,
+    };
+    let events: Vec<_> = receipt.logs.iter()
+        .map(|log| contract.and_then(|contract| abi::decode_log(contract, log)))
+        .collect();
+    let logs: Vec<_> = receipt.logs.iter().zip(&events).map(|(log, event)| json!({
+        "address": format!("0x{:x}", log.address),
+        "topics": log.topics.iter().map(|t| format!("0x{:x}", t)).collect::<Vec<_>>(),
+        "data": format!("0x{}", hex::encode(&log.data)),
+        "event": event.as_ref().map(|event| json!({
+            "name": event.name,
+            "params": event.params.iter().map(value_json).collect::<Vec<_>>(),
+        })),
+    })).collect();
+
+    let mut text = format!("status: {}", status);
+    if let Some(ref error) = error {
+        text.push_str(&format!("\nerror: {}", error));
+    }
+    if let Some(ref reason) = receipt.revert_reason {
+        text.push_str(&format!("\nrevert_reason: {}", reason));
+    }
+    if let Some(ref address) = contract_address {
+        text.push_str(&format!("\ncontract_address: {}", address));
+    }
+    text.push_str(&format!("\noutput: {}", output));
+    if let Some(ref returns) = returns {
+        for value in returns {
+            text.push_str(&format!("\n  returns: {}", value_text(value)));
+        }
+    }
+    text.push_str(&format!("\ngas_used: {}\nstate_root: 0x{:x}", receipt.gas_used, state_root));
+    for (i, (log, event)) in receipt.logs.iter().zip(&events).enumerate() {
+        text.push_str(&format!("\nlog {}: address 0x{:x}", i, log.address));
+        if let Some(event) = event {
+            text.push_str(&format!("\n  event: {}", event.name));
+            for param in &event.params {
+                text.push_str(&format!("\n    {}", value_text(param)));
+            }
+        }
+        for topic in &log.topics {
+            text.push_str(&format!("\n  topic: 0x{:x}", topic));
+        }
+        text.push_str(&format!("\n  data: 0x{}", hex::encode(&log.data)));
+    }
+
+    Output::new(text, json!({
+        "status": status,
+        "error": error,
+        "revert_reason": receipt.revert_reason,
+        "contract_address": contract_address,
+        "output": output,
+        "returns": returns.map(|values| values.iter().map(value_json).collect::<Vec<_>>()),
+        "gas_used": receipt.gas_used,
+        "logs": logs,
+        "state_root": format!("0x{:x}", state_root),
+    }))
+}
 
 impl ContractCmd {
-    pub fn run(&self, mut backend: &str) {
+    pub fn run(&self) -> Result<Output, Error> {
         match &self.cmd {
-            Command::Deploy {from,value,gas,gas_price,code,code_file} => {
-
-                let from = H160::from_str(from).expect("From should be a valid address");
-                let value = U256::from_dec_str(value.as_str()).expect("Value is invalid");
-                let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
-                let gas_limit = *gas;
-
-                let mut contents = String::new();
-
-                let code = match code {
-                    Some(c) => {
-                        Ok(c)
-                    }
-                    None => {
-                        let ret = match code_file {
-                            Some(file) => {
-                                let mut f = File::open(file).expect(" code file not found");
-
-                                f.read_to_string(&mut contents)
-                                    .expect("something went wrong reading the file");
-                                Ok(&contents)
-                            }
-
-                            None => {
-                                Err(())
-                            }
-                        };
-                        ret
-                    }
-                }.expect("--code or --code-file must be provided one of them ");
-                println!("Deploy {:#?}", backend);
-                println!(" from=={:#?}\n value=={:#?}\n gas=={:#?}\n gas_price=={:#?}\n code=={:#?}\n code_file=={:#?}\n",
-                         from, value, gas, gas_price, code, code_file);
+            Command::Deploy {from,value,gas,gas_price,code,code_file,abi: abi_file,args} => {
+
+                let from = parse_address("--from", from)?;
+                let value = parse_u256("--value", value)?;
+                let gas_price = parse_u256("--gas-price", gas_price)?;
+
+                let code = inline_or_file(code, code_file)?
+                    .ok_or_else(|| Error::InvalidInput("--code or --code-file must be provided".into()))?;
+                let code = parse_bytes("contract code", &code)?;
+                let contract = abi_file.as_ref().map(|path| abi::load(path)).transpose()?;
+                let code = match contract {
+                    Some(ref contract) => abi::encode_constructor(contract, code, args)?,
+                    None => code,
+                };
+
+                let mut state = open_state(&self.db)?;
+                let receipt = evm::transact(&mut state, &Transaction {
+                    from,
+                    to: None,
+                    value,
+                    gas: u64::from(*gas),
+                    gas_price,
+                    data: code,
+                    chain_id: self.chain_id,
+                })?;
+                Ok(receipt_output(&receipt, &state.state_root(), contract.as_ref(), None))
             }
 
-            Command::Call {from,value,to,gas,gas_price,data,data_file} => {
-                let from = H160::from_str(from).expect("From should be a valid address");
-                let to = H160::from_str(to).expect("To should be a valid address");
-                let value = U256::from_dec_str(value.as_str()).expect("Value is invalid");
-                let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
-                let gas_limit = *gas;
-
-                let mut contents = String::new();
-
-                let data = match data {
-                    Some(d) => {
-                        Ok(d)
-                    }
-                    None => {
-                        let ret = match data_file {
-                            Some(file) => {
-                                let mut f = File::open(file).expect(" data file not found");
-
-                                f.read_to_string(&mut contents)
-                                    .expect("something went wrong reading the file");
-                                Ok(&contents)
-                            }
-
-                            None => {
-                                Err(())
-                            }
-                        };
-                        ret
-                    }
-                }.unwrap_or(&contents);
-                println!("Call {:#?}", backend);
-                println!(" from=={:#?}\n to=={:#?}\n value=={:#?}\n gas=={:#?}\n gas_price=={:#?}\n data=={:#?}\n data_file=={:#?}\n",
-                         from, to, value, gas, gas_price, data, data_file);
+            Command::Call {from,value,to,gas,gas_price,data,data_file,abi: abi_file,function,args} => {
+                let from = parse_address("--from", from)?;
+                let to = parse_address("--to", to)?;
+                let value = parse_u256("--value", value)?;
+                let gas_price = parse_u256("--gas-price", gas_price)?;
+
+                let contract = abi_file.as_ref().map(|path| abi::load(path)).transpose()?;
+                let function = match (&contract, function) {
+                    (Some(contract), Some(name)) => Some(abi::function(contract, name, args.len())?),
+                    _ => None,
+                };
+                let data = match function {
+                    Some(function) => abi::encode_call(function, args)?,
+                    None => parse_bytes("call data", &inline_or_file(data, data_file)?.unwrap_or_default())?,
+                };
+
+                let mut state = open_state(&self.db)?;
+                let receipt = evm::transact(&mut state, &Transaction {
+                    from,
+                    to: Some(to),
+                    value,
+                    gas: u64::from(*gas),
+                    gas_price,
+                    data,
+                    chain_id: self.chain_id,
+                })?;
+                Ok(receipt_output(&receipt, &state.state_root(), contract.as_ref(), function))
             }
         }
     }
@@ -1,145 +1,45 @@
-extern crate serde_derive;
 extern crate ethstore_lib;
 extern crate ethstore;
-extern crate tempfile;
 
-use tempfile::Builder;
-use std::collections::VecDeque;
-use std::io::Read;
-use std::{env, process, fs, fmt};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use structopt::StructOpt;
-use ethereum_types::{H160, H256, U256};
-use std::collections::BTreeMap;
-use std::str::FromStr;
+use structopt::clap::AppSettings;
 
+use ethstore_lib::backup::ManifestEntry;
+use ethstore_lib::derivation;
+use ethstore_lib::eip712::TypedData;
 use ethstore_lib::ethstore_rely::*;
-use ethstore_lib::crack;
-use docopt::Docopt;
-use ethstore::{EthStore, SimpleSecretStore, SecretStore, import_accounts, PresaleWallet, SecretVaultRef, StoreAccountRef};
+use ethstore_lib::password::{Password, PasswordSource};
+use ethstore_lib::service;
+use ethstore_lib::shamir::Share;
+use ethstore_lib::signature::{format_rsv, parse_signature, personal_hash, recover, verify};
+use ethstore::{EthStore, KdfParams, SecretVaultRef, StoreAccountRef};
+use parity_crypto::publickey::{Address, Message, Public, Secret};
+use serde_json::json;
+
+use crate::output::Output;
+use super::{parse_address, parse_bytes, KeyDirFlag, PasswordFlags, VaultFlags, PASSWORD_FLAGS};
 
 // target/debug/bloom-cmd ethstore insert 7d29fab185a33e2cd955812397354c472d2b84615b645aa135ff539f6b0d70d5 password.txt [--dir somedir]
+// target/debug/bloom-cmd ethstore new password.txt [--count 10] [--name worker] [--dir somedir] [--kdf scrypt:n=262144,r=8,p=1,dklen=32]
 // target/debug/bloom-cmd ethstore change-pwd a8fa5dd30a87bb9e3288d604eb74949c515ab66e old_pwd.txt new_pwd.txt [--dir somedir]
 // target/debug/bloom-cmd ethstore list [--dir somedir]
-
-fn execute<S, I>(command: I) -> Result<String, Error> where I: IntoIterator<Item=S>, S: AsRef<str> {
-    let args: Args = Docopt::new(USAGE)
-        .and_then(|d| d.argv(command).deserialize())?;
-    let store = EthStore::open(key_dir(&args.flag_dir, None)?)?;
-
-    // 获取ethstore的二级子命令
-    let args_num = getsubcmd(&args);
-
-    return match args_num {
-        EthstoreNUM::cmd_insert => {
-            let secret = args.arg_secret.parse().map_err(|_| ethstore::Error::InvalidSecret)?;
-            let password = load_password(&args.arg_password)?;
-            let vault_ref = open_args_vault(&store, &args)?;
-            let account_ref = store.insert_account(vault_ref, secret, &password)?;
-            Ok(format!("0x{:x}", account_ref.address))
-        },
-        EthstoreNUM::cmd_change_pwd => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let old_pwd = load_password(&args.arg_old_pwd)?;
-            let new_pwd = load_password(&args.arg_new_pwd)?;
-            let account_ref = open_args_vault_account(&store, address, &args)?;
-            let ok = store.change_password(&account_ref, &old_pwd, &new_pwd).is_ok();
-            Ok(format!("{}", ok))
-        },
-        EthstoreNUM::cmd_list => {
-            let vault_ref = open_args_vault(&store, &args)?;
-            let accounts = store.accounts()?;
-            let accounts: Vec<_> = accounts
-                .into_iter()
-                .filter(|a| &a.vault == &vault_ref)
-                .map(|a| a.address)
-                .collect();
-            Ok(format_accounts(&accounts))
-        },
-        EthstoreNUM::cmd_import => {
-            let password = match args.arg_password.as_ref() {
-                "" => None,
-                _ => Some(load_password(&args.arg_password)?)
-            };
-            let src = key_dir(&args.flag_src, password)?;
-            let dst = key_dir(&args.flag_dir, None)?;
-
-            let accounts = import_accounts(&*src, &*dst)?;
-            Ok(format_accounts(&accounts))
-        },
-        EthstoreNUM::cmd_import_wallet => {
-            let wallet = PresaleWallet::open(&args.arg_path)?;
-            let password = load_password(&args.arg_password)?;
-            let kp = wallet.decrypt(&password)?;
-            let vault_ref = open_args_vault(&store, &args)?;
-            let account_ref = store.insert_account(vault_ref, kp.secret().clone(), &password)?;
-            Ok(format!("0x{:x}", account_ref.address))
-        },
-        EthstoreNUM::cmd_find_wallet_pass => {
-            let passwords = load_password(&args.arg_password)?;
-            let passwords = passwords.as_str().lines().map(|line| str::to_owned(line).into()).collect::<VecDeque<_>>();
-            crack::run(passwords, &args.arg_path)?;
-            Ok(format!("Password not found."))
-        },
-        EthstoreNUM::cmd_remove => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let password = load_password(&args.arg_password)?;
-            let account_ref = open_args_vault_account(&store, address, &args)?;
-            let ok = store.remove_account(&account_ref, &password).is_ok();
-            Ok(format!("{}", ok))
-        },
-        EthstoreNUM::cmd_sign => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let message = args.arg_message.parse().map_err(|_| ethstore::Error::InvalidMessage)?;
-            let password = load_password(&args.arg_password)?;
-            let account_ref = open_args_vault_account(&store, address, &args)?;
-            let signature = store.sign(&account_ref, &password, &message)?;
-            Ok(format!("0x{}", signature))
-        },
-        EthstoreNUM::cmd_public => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let password = load_password(&args.arg_password)?;
-            let account_ref = open_args_vault_account(&store, address, &args)?;
-            let public = store.public(&account_ref, &password)?;
-            Ok(format!("0x{:x}", public))
-        },
-        EthstoreNUM::cmd_list_vaults => {
-            let vaults = store.list_vaults()?;
-            Ok(format_vaults(&vaults))
-        },
-        EthstoreNUM::cmd_create_vault => {
-            let password = load_password(&args.arg_password)?;
-            store.create_vault(&args.arg_vault, &password)?;
-            Ok("OK".to_owned())
-        },
-        EthstoreNUM::cmd_change_vault_pwd => {
-            let old_pwd = load_password(&args.arg_old_pwd)?;
-            let new_pwd = load_password(&args.arg_new_pwd)?;
-            store.open_vault(&args.arg_vault, &old_pwd)?;
-            store.change_vault_password(&args.arg_vault, &new_pwd)?;
-            Ok("OK".to_owned())
-        },
-        EthstoreNUM::cmd_move_to_vault => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let password = load_password(&args.arg_password)?;
-            let account_ref = open_args_vault_account(&store, address, &args)?;
-            store.open_vault(&args.arg_vault, &password)?;
-            store.change_account_vault(SecretVaultRef::Vault(args.arg_vault), account_ref)?;
-            Ok("OK".to_owned())
-        },
-        EthstoreNUM::cmd_move_from_vault => {
-            let address = args.arg_address.parse().map_err(|_| ethstore::Error::InvalidAccount)?;
-            let password = load_password(&args.arg_password)?;
-            store.open_vault(&args.arg_vault, &password)?;
-            store.change_account_vault(SecretVaultRef::Root, StoreAccountRef::vault(&args.arg_vault, address))?;
-            Ok("OK".to_owned())
-        },
-        EthstoreNUM::cmd_null => {
-            Ok(format!("{}", USAGE))
-        }
-    };
-
-}
+// target/debug/bloom-cmd ethstore rekey --kdf scrypt [--dry-run | --passwords passwords.txt] [--dir somedir]
+// target/debug/bloom-cmd ethstore export a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt ./exported [--new-pwd export_pwd.txt] [--vault team --vault-pwd vault_pwd.txt]
+// target/debug/bloom-cmd ethstore export-all ./exported --passwords passwords.txt [--new-pwd export_pwd.txt] [--vault team --vault-pwd vault_pwd.txt]
+// target/debug/bloom-cmd ethstore split a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt --threshold 2 --shares 3 [--words]
+// target/debug/bloom-cmd ethstore combine a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt share1.txt share2.txt [--vault team --vault-pwd vault_pwd.txt]
+// target/debug/bloom-cmd ethstore sign a8fa5dd30a87bb9e3288d604eb74949c515ab66e --password-env ACCOUNT_PWD "hello" --personal
+// target/debug/bloom-cmd ethstore backup keystore.backup passphrase.txt [--kdf scrypt] [--vault team --vault-pwd vault_pwd.txt] [--dir somedir]
+// target/debug/bloom-cmd ethstore restore keystore.backup passphrase.txt [--overwrite | --verify-only] [--vault team --vault-pwd vault_pwd.txt] [--dir somedir]
+// target/debug/bloom-cmd ethstore delete-vault team vault_pwd.txt [--force]
+// target/debug/bloom-cmd ethstore rename-vault team treasury vault_pwd.txt
+// target/debug/bloom-cmd ethstore fsck [--passwords passwords.txt] [--vault team --vault-pwd vault_pwd.txt] [--quarantine ./broken] [--dir somedir]
+// target/debug/bloom-cmd ethstore derive a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt "m/0/1'" [--save] [--save-vault team --save-vault-pwd vault_pwd.txt]
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct EthstoreCmd {
@@ -151,241 +51,901 @@ pub struct EthstoreCmd {
 enum Command {
     Insert{
         secret:String,
-        password :String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
+    /// Generate random accounts and store them, print their addresses
+    New{
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// Account name, numbered from 1 when --count is above 1
+        #[structopt(long = "name")]
+        name: Option<String>,
+        /// Account meta, usually a JSON object
+        #[structopt(long = "meta")]
+        meta: Option<String>,
+        /// Number of accounts to generate
+        #[structopt(long = "count", default_value = "1")]
+        count: usize,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
     Change_pwd{
         address:String,
-        old_pwd:String,
-        new_pwd:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Current password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        old_pwd: Option<PasswordSource>,
+        /// New password file
+        new_pwd: PasswordSource,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     List{
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+    },
+    /// Re-encrypt the keys weaker than --kdf, in the root directory and --vault, with their own password, or report them with --dry-run
+    Rekey{
+        /// File of the account passwords to try, one per line
+        #[structopt(long = "passwords", required_unless = "dry-run")]
+        passwords: Option<PasswordSource>,
+        /// Only print the KDF of each key and whether it would be re-encrypted
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+    },
+    /// Check key files and vaults for anomalies, decrypting the keys of --vault too, and move broken files away with --quarantine
+    Fsck{
+        /// File of the account passwords to verify keys with, one per line
+        #[structopt(long = "passwords")]
+        passwords: Option<PasswordSource>,
+        /// Directory to move unreadable keys, keys with a forged address and leftover temporary files to
+        #[structopt(long = "quarantine")]
+        quarantine: Option<String>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+    },
+    /// Split an account's secret into shares, any --threshold of which recover it
+    Split{
+        address:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// Number of shares needed to recover the secret
+        #[structopt(long = "threshold")]
+        threshold: u8,
+        /// Number of shares to create
+        #[structopt(long = "shares")]
+        shares: u8,
+        /// Print shares as <threshold>-<index>-<checksum> and 24 mnemonic words instead of hex
+        #[structopt(long = "words")]
+        words: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Recover a secret from split shares and store it in the root directory or --vault, checking it is the key of <address>
+    Combine{
+        address:String,
+        /// Password file for the recovered account, left out when one of the --password-* flags is used
+        password: Option<PasswordSource>,
+        /// Share files, one share each, in hex or mnemonic form
+        shares: Vec<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Write the key directory with all vaults and metadata files to an encrypted archive, listing the key addresses of the root directory and --vault
+    Backup{
+        /// Archive file to create
+        out: String,
+        /// Backup passphrase file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        passphrase: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Verify an archive made by backup and restore it into the key directory, checking the keys of an existing --vault for duplicates
+    Restore{
+        /// Archive file to restore
+        archive: String,
+        /// Backup passphrase file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        passphrase: Option<PasswordSource>,
+        /// Replace existing files and accounts with the archived ones
+        #[structopt(long = "overwrite")]
+        overwrite: bool,
+        /// Only decrypt the archive and check its checksums
+        #[structopt(long = "verify-only")]
+        verify_only: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     Import{
-        #[structopt(long = "src", default_value = "0")]
+        /// Import source: parity, parity-(chain), geth, geth-test or a path
+        #[structopt(long = "src", default_value = "geth")]
         src: String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
     },
     Import_wallet{
         path:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     Find_wallet_pass{
         path:String,
-        password:String,
+        /// File of candidate passwords, one per line
+        password: PasswordSource,
+    },
+    /// Write an account as a standard v3 key file, readable by geth and MetaMask, into a directory
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
+    Export{
+        address:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// Directory to write the key file to, created if missing
+        out: String,
+        /// Password file to re-encrypt the exported key with, instead of the account password
+        #[structopt(long = "new-pwd")]
+        new_pwd: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Export every account of the root directory, or of --vault, as v3 key files into a directory
+    Export_all{
+        /// Directory to write the key files to, created if missing
+        out: String,
+        /// File of the account passwords to try, one per line
+        #[structopt(long = "passwords")]
+        passwords: PasswordSource,
+        /// Password file to re-encrypt the exported keys with, instead of their own passwords
+        #[structopt(long = "new-pwd")]
+        new_pwd: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
     },
     Remove{
         address:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
     Sign{
         address:String,
-        password:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// 32-byte hex hash, or any text with --personal
         message:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Sign the message with the EIP-191 personal_sign prefix
+        #[structopt(long = "personal")]
+        personal: bool,
+        /// Read the --personal message as hex bytes instead of UTF-8 text
+        #[structopt(long = "hex", requires = "personal")]
+        hex: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Print the address of a key derived from an account, optionally store the key
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
+    Derive{
+        address:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// soft:<hash>, hard:<hash> or an index path like m/0/1' (' marks a hard step)
+        derivation:String,
+        /// Store the derived key with the account password, next to the account or in --save-vault
+        #[structopt(long = "save")]
+        save: bool,
+        /// Vault to store the derived key in
+        #[structopt(long = "save-vault", requires = "save")]
+        save_vault: Option<String>,
+        /// Password file of --save-vault
+        #[structopt(long = "save-vault-pwd", requires = "save-vault")]
+        save_vault_pwd: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Sign with a key derived from an account without storing the key
+    Sign_derived{
+        address:String,
+        /// Password file, left out when one of the --password-* flags is used
+        password: Option<String>,
+        /// soft:<hash>, hard:<hash> or an index path like m/0/1' (' marks a hard step)
+        derivation: Option<String>,
+        /// 32-byte hex hash, or any text with --personal
+        message: Option<String>,
+        /// Sign the message with the EIP-191 personal_sign prefix
+        #[structopt(long = "personal")]
+        personal: bool,
+        /// Read the --personal message as hex bytes instead of UTF-8 text
+        #[structopt(long = "hex", requires = "personal")]
+        hex: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Sign an EIP-712 typed data JSON document
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
+    Sign_typed_data{
+        address:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// JSON file with types, primaryType, domain and message
+        file:String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Check that the address signed the message, print the recovered signer
+    Verify{
+        address:String,
+        /// 65-byte r, s, v hex signature
+        signature:String,
+        /// 32-byte hex hash, or any text with --personal
+        message:String,
+        /// Verify an EIP-191 personal_sign signature
+        #[structopt(long = "personal")]
+        personal: bool,
+        /// Read the --personal message as hex bytes instead of UTF-8 text
+        #[structopt(long = "hex", requires = "personal")]
+        hex: bool,
+    },
+    /// Recover the address and public key that signed the message
+    Recover{
+        /// 65-byte r, s, v hex signature
+        signature:String,
+        /// 32-byte hex hash, or any text with --personal
+        message:String,
+        /// Recover from an EIP-191 personal_sign signature
+        #[structopt(long = "personal")]
+        personal: bool,
+        /// Read the --personal message as hex bytes instead of UTF-8 text
+        #[structopt(long = "hex", requires = "personal")]
+        hex: bool,
     },
     Public{
         address:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     List_vaults{
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
     },
     Create_vault{
         vault:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
     Change_vault_pwd{
         vault:String,
-        old_pwd:String,
-        new_pwd:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Current password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        old_pwd: Option<PasswordSource>,
+        /// New password file
+        new_pwd: PasswordSource,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     Move_to_vault{
         address:String,
         vault:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file of <vault>, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        /// Vault the account is currently stored in
+        #[structopt(long = "vault")]
+        from_vault: Option<String>,
+        /// Vault password file, required when --vault is set
+        #[structopt(long = "vault-pwd")]
+        vault_pwd: Option<PasswordSource>,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
     Move_from_vault{
         address:String,
         vault:String,
-        password:String,
-        #[structopt(long = "dir", default_value = "0")]
-        dir: String,
+        /// Password file of <vault>, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Check that the vault exists; every invocation opens the keystore afresh, so no vault stays open between commands
+    Close_vault{
+        vault:String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+    },
+    Delete_vault{
+        vault:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// Delete the vault even if it still holds accounts
+        #[structopt(long = "force")]
+        force: bool,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    Rename_vault{
+        vault:String,
+        new_name:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    Get_vault_meta{
+        vault:String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+    },
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
+    Set_vault_meta{
+        vault:String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        meta:String,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
     },
 }
 
-impl EthstoreCmd {
-    pub fn run(&self, mut backend: &str) {
-        // 设置默认存储路径
-        let dir = Builder::new().prefix("test-vault").tempdir().unwrap();
-        let default_dir: &str = dir.path().to_str().unwrap();
+pub fn open_store(dir: &str) -> Result<EthStore, Error> {
+    open_store_with_kdf(dir, KdfParams::default())
+}
 
-        match &self.cmd {
-            Command::Insert { secret, password ,dir} => {
-                println!("Query {:#?}", backend);
+/// Opens the key directory, new and re-encrypted keys are written with `kdf`.
+pub fn open_store_with_kdf(dir: &str, kdf: KdfParams) -> Result<EthStore, Error> {
+    Ok(EthStore::open_with_kdf(key_dir(dir, None)?, kdf)?)
+}
 
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
+/// Filesystem path of the key directory, for commands working on its files.
+fn key_dir_path(dir: &str) -> Result<PathBuf, Error> {
+    key_dir(dir, None)?.path().cloned()
+        .ok_or_else(|| Error::InvalidInput(format!("'{}' is not a key directory on disk", dir)))
+}
 
-                let command = vec!["ethstore","insert", secret, password, "--dir",dir_str]
-                        .into_iter()
-                        .map(Into::into)
-                        .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}", result);
-            },
-            Command::Change_pwd {address,old_pwd,new_pwd,dir} => {
-                println!("Change-pwd {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","change-pwd",address, old_pwd, new_pwd,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::List {dir} => {
-                println!("List {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","list","--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Import {src,dir} => {
-                println!("Import {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","import","--src",src,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Import_wallet {path,password,dir} => {
-                println!("Import_wallet {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","import-wallet", path, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Find_wallet_pass {path,password} => {
-                println!("Find_wallet_pass {:#?}", backend);
-                let command = vec!["ethstore","find-wallet-pass", path, password]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Remove {address, password,dir} => {
-                println!("Remove {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","remove", address, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Sign {address,password,message,dir} => {
-                println!("Sign {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","sign", address, password, message,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Public {address, password,dir} => {
-                println!("Public {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","public", address, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::List_vaults {dir} => {
-                println!("List_vaults {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","list-vaults","--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Create_vault {vault,password,dir} => {
-                println!("Create_vault {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","create-vault", vault, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Change_vault_pwd {vault,old_pwd,new_pwd,dir} => {
-                println!("Change_vault_pwd {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","change-vault-pwd", vault, old_pwd, new_pwd,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Move_to_vault {address, vault, password,dir} => {
-                println!("Move_to_vault {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","move-to-vault", address, vault, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            },
-            Command::Move_from_vault {address, vault, password,dir} => {
-                println!("Move_from_vault {:#?}", backend);
-                let dir_str =  if dir.eq("0") {default_dir} else {dir};
-                let command = vec!["ethstore","move-from-vault", address, vault, password,"--dir",dir_str]
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<String>>();
-                let result = execute(command).unwrap();
-                println!("{}",result);
-            }
-        }
+/// Password of `--vault` by vault name, for commands reading vault files directly.
+fn vault_passwords(flags: &VaultFlags) -> Result<HashMap<String, Password>, Error> {
+    let mut passwords = HashMap::new();
+    if let Some(ref vault) = flags.vault {
+        let source = flags.vault_pwd.as_ref()
+            .ok_or_else(|| Error::InvalidInput(format!("password for vault '{}' is required", vault)))?;
+        passwords.insert(vault.clone(), source.read("Vault password")?);
+    }
+    Ok(passwords)
+}
+
+fn address_output(address: &Address) -> Output {
+    let address = format!("0x{:x}", address);
+    Output::new(address.clone(), json!({ "address": address }))
+}
+
+fn accounts_output(key: &str, accounts: &[Address]) -> Output {
+    let addresses: Vec<String> = accounts.iter().map(|a| format!("0x{:x}", a)).collect();
+    Output::new(format_accounts(accounts), json!({ key: addresses }))
+}
+
+/// Hash to sign or verify: the message itself, or its EIP-191 hash with `--personal`.
+fn message_hash(message: &str, personal: bool, hex: bool) -> Result<Message, Error> {
+    if !personal {
+        return Ok(message.parse().map_err(|_| ethstore::Error::InvalidMessage)?);
     }
+    Ok(personal_hash(&personal_message(message, hex)?))
 }
 
+fn personal_message(message: &str, hex: bool) -> Result<Vec<u8>, Error> {
+    if hex { parse_bytes("<message>", message) } else { Ok(message.as_bytes().to_vec()) }
+}
+
+fn rekey_output(target: &KdfParams, entries: &[service::RekeyEntry]) -> Output {
+    let status = |status: service::RekeyStatus| match status {
+        service::RekeyStatus::Current => "current",
+        service::RekeyStatus::Weak => "weak",
+        service::RekeyStatus::Rekeyed => "rekeyed",
+        service::RekeyStatus::NoPassword => "no-password",
+    };
+    let vault = |vault: &SecretVaultRef| match vault {
+        SecretVaultRef::Root => None,
+        SecretVaultRef::Vault(name) => Some(name.clone()),
+    };
+    let text: Vec<_> = entries.iter()
+        .map(|e| format!("0x{:x} {} {} {}", e.account.address, vault(&e.account.vault).unwrap_or_else(|| "-".into()), e.kdf, status(e.status)))
+        .collect();
+    let json: Vec<_> = entries.iter()
+        .map(|e| json!({
+            "address": format!("0x{:x}", e.account.address),
+            "vault": vault(&e.account.vault),
+            "kdf": e.kdf.to_string(),
+            "status": status(e.status),
+        }))
+        .collect();
+    Output::new(text.join("\n"), json!({ "kdf": target.to_string(), "accounts": json }))
+}
+
+fn export_output(entries: &[service::ExportEntry]) -> Output {
+    let path = |e: &service::ExportEntry| e.path.as_ref().map(|path| path.display().to_string());
+    let text: Vec<_> = entries.iter()
+        .map(|e| format!("0x{:x} {}", e.address, path(e).unwrap_or_else(|| "no-password".into())))
+        .collect();
+    let json: Vec<_> = entries.iter()
+        .map(|e| json!({ "address": format!("0x{:x}", e.address), "path": path(e) }))
+        .collect();
+    Output::new(text.join("\n"), json!({ "exported": json }))
+}
 
+fn manifest_output(manifest: &[ManifestEntry]) -> Output {
+    let address = |e: &ManifestEntry| e.address.map(|address| format!("0x{:x}", address));
+    let text: Vec<_> = manifest.iter()
+        .map(|e| format!("{} {} {}", e.path, address(e).unwrap_or_else(|| "-".into()), e.sha256))
+        .collect();
+    let json: Vec<_> = manifest.iter()
+        .map(|e| json!({ "path": e.path, "vault": e.vault, "address": address(e), "sha256": e.sha256 }))
+        .collect();
+    Output::new(text.join("\n"), json!({ "files": json }))
+}
+
+fn fsck_output(report: &service::FsckReport) -> Output {
+    let mut text: Vec<_> = report.anomalies.iter()
+        .map(|a| format!("{} {}: {}", a.severity, a.path.display(), a.message))
+        .collect();
+    text.extend(report.quarantined.iter().map(|(from, to)| format!("moved {} to {}", from.display(), to.display())));
+    let anomalies: Vec<_> = report.anomalies.iter()
+        .map(|a| json!({
+            "path": a.path.display().to_string(),
+            "severity": a.severity.to_string(),
+            "kind": a.kind.to_string(),
+            "message": a.message,
+        }))
+        .collect();
+    let quarantined: Vec<_> = report.quarantined.iter()
+        .map(|(from, to)| json!({ "from": from.display().to_string(), "to": to.display().to_string() }))
+        .collect();
+    let text = if text.is_empty() { "no anomalies found".to_owned() } else { text.join("\n") };
+    Output::new(text, json!({ "anomalies": anomalies, "quarantined": quarantined }))
+}
 
+fn signer_output(public: &Public, address: &Address, valid: Option<bool>) -> Output {
+    let public = format!("0x{:x}", public);
+    let address = format!("0x{:x}", address);
+    let mut text = format!("address: {}\npublic: {}", address, public);
+    let mut json = json!({ "address": address, "public": public });
+    if let Some(valid) = valid {
+        text = format!("valid: {}\n{}", valid, text);
+        json["valid"] = valid.into();
+    }
+    Output::new(text, json)
+}
+
+pub fn open_vault(store: &EthStore, flags: &VaultFlags) -> Result<SecretVaultRef, Error> {
+    let vault_pwd = match flags.vault_pwd {
+        Some(ref source) if flags.vault.is_some() => Some(source.read("Vault password")?),
+        _ => None,
+    };
+    service::open_vault(store, flags.vault.as_ref().map(String::as_str), vault_pwd.as_ref())
+}
 
+impl EthstoreCmd {
+    pub fn run(&self, kdf: KdfParams) -> Result<Output, Error> {
+        match &self.cmd {
+            Command::Insert { secret, password, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let secret: Secret = secret.parse().map_err(|_| ethstore::Error::InvalidSecret)?;
+                let password = password_flags.or_file(password)?.read_new("Account password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let address = service::insert(&store, vault_ref, secret, &password)?;
+                Ok(address_output(&address))
+            },
+            Command::New { password, name, meta, count, dir_flag, vault_flags, password_flags } => {
+                if *count == 0 {
+                    return Err(Error::InvalidInput("--count must be at least 1".into()));
+                }
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read_new("Account password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let mut addresses = Vec::with_capacity(*count);
+                for i in 1..=*count {
+                    let name = match name {
+                        Some(name) if *count > 1 => Some(format!("{} {}", name, i)),
+                        _ => name.clone(),
+                    };
+                    addresses.push(service::new_account(&store, vault_ref.clone(), &password, name.as_deref(), meta.as_deref())?);
+                }
+                if *count == 1 {
+                    return Ok(address_output(&addresses[0]));
+                }
+                let addresses: Vec<_> = addresses.iter().map(|a| format!("0x{:x}", a)).collect();
+                Ok(Output::new(addresses.join("\n"), json!({ "accounts": addresses })))
+            },
+            Command::Change_pwd { address, old_pwd, new_pwd, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let old_pwd = password_flags.or_file(old_pwd)?.read("Current account password")?;
+                let new_pwd = new_pwd.read_new("New account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                service::change_pwd(&store, &account_ref, &old_pwd, &new_pwd)?;
+                Ok(Output::ok())
+            },
+            Command::List { dir_flag, vault_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let accounts = service::list(&store, &vault_ref)?;
+                Ok(accounts_output("accounts", &accounts))
+            },
+            Command::Rekey { passwords, dry_run, dir_flag, vault_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                open_vault(&store, vault_flags)?;
+                let passwords = match passwords {
+                    Some(source) if !*dry_run => source.read_lines("Account passwords")?,
+                    _ => Vec::new(),
+                };
+                let entries = service::rekey(&store, &passwords, *dry_run)?;
+                Ok(rekey_output(&store.kdf(), &entries))
+            },
+            Command::Fsck { passwords, quarantine, dir_flag, vault_flags } => {
+                let root = key_dir_path(&dir_flag.dir)?;
+                let passwords = match passwords {
+                    Some(source) => source.read_lines("Account passwords")?,
+                    None => Vec::new(),
+                };
+                let vault_passwords = vault_passwords(vault_flags)?;
+                let report = service::fsck(&root, &passwords, &vault_passwords, quarantine.as_ref().map(Path::new))?;
+                Ok(fsck_output(&report))
+            },
+            Command::Split { address, password, threshold, shares, words, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let shares = service::split_secret(&store, &account_ref, &password, *threshold, *shares)?;
+                let shares: Vec<_> = shares.iter()
+                    .map(|share| if *words { share.to_words() } else { share.to_hex() })
+                    .collect();
+                Ok(Output::new(shares.join("\n"), json!({
+                    "address": format!("0x{:x}", address),
+                    "threshold": threshold,
+                    "shares": shares,
+                })))
+            },
+            Command::Combine { address, password, shares, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let (password, shares): (_, Vec<_>) = match password_flags.source() {
+                    // without a password file the first positional argument is a share
+                    Some(source) => (source, password.iter().chain(shares).cloned().collect()),
+                    None => (password_flags.or_file(password)?, shares.clone()),
+                };
+                if shares.is_empty() {
+                    return Err(Error::InvalidInput("at least one share file is required".into()));
+                }
+                let shares = shares.iter()
+                    .enumerate()
+                    .map(|(i, source)| source.read(&format!("Share {}", i + 1))?.as_str().parse::<Share>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                let password = password.read_new("Account password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let address = service::combine_shares(&store, vault_ref, &shares, &address, &password)?;
+                Ok(address_output(&address))
+            },
+            Command::Backup { out, passphrase, dir_flag, vault_flags, password_flags } => {
+                let root = key_dir_path(&dir_flag.dir)?;
+                let vault_passwords = vault_passwords(vault_flags)?;
+                let passphrase = password_flags.or_file(passphrase)?.read_new("Backup passphrase")?;
+                let manifest = service::backup(&root, Path::new(out), &passphrase, kdf, &vault_passwords)?;
+                Ok(manifest_output(&manifest))
+            },
+            Command::Restore { archive, passphrase, overwrite, verify_only, dir_flag, vault_flags, password_flags } => {
+                let root = key_dir_path(&dir_flag.dir)?;
+                let vault_passwords = vault_passwords(vault_flags)?;
+                let passphrase = password_flags.or_file(passphrase)?.read("Backup passphrase")?;
+                let manifest = service::restore(Path::new(archive), &root, &passphrase, *overwrite, *verify_only, &vault_passwords)?;
+                Ok(manifest_output(&manifest))
+            },
+            Command::Import { src, dir_flag } => {
+                let src = key_dir(src, None)?;
+                let dst = key_dir(&dir_flag.dir, None)?;
+                let accounts = service::import(&*src, &*dst)?;
+                Ok(accounts_output("imported", &accounts))
+            },
+            Command::Import_wallet { path, password, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read("Wallet password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let address = service::import_wallet(&store, vault_ref, path, &password)?;
+                Ok(address_output(&address))
+            },
+            Command::Find_wallet_pass { path, password } => {
+                let passwords = password.read_raw("Password to try")?;
+                let passwords = passwords.lines().map(|line| str::to_owned(line).into()).collect::<VecDeque<_>>();
+                match service::find_wallet_pass(path, passwords)? {
+                    Some(found) => Ok(Output::new(format!("Found password: {}", found.as_str()), json!({ "password": found.as_str() }))),
+                    None => Ok(Output::new("Password not found.", json!({ "password": null }))),
+                }
+            },
+            Command::Export { address, password, out, new_pwd, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let new_pwd = new_pwd.as_ref().map(|source| source.read_new("Export password")).transpose()?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let path = service::export(&store, &account_ref, &password, new_pwd.as_ref(), Path::new(out))?;
+                let path = path.display().to_string();
+                Ok(Output::new(path.clone(), json!({ "address": format!("0x{:x}", address), "path": path })))
+            },
+            Command::Export_all { out, passwords, new_pwd, dir_flag, vault_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let passwords = passwords.read_lines("Account passwords")?;
+                let new_pwd = new_pwd.as_ref().map(|source| source.read_new("Export password")).transpose()?;
+                let entries = service::export_vault(&store, &vault_ref, &passwords, new_pwd.as_ref(), Path::new(out))?;
+                Ok(export_output(&entries))
+            },
+            Command::Remove { address, password, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                service::remove(&store, &account_ref, &password)?;
+                Ok(Output::ok())
+            },
+            Command::Sign { address, password, message, personal, hex, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let hash = message_hash(message, *personal, *hex)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let signature = service::sign(&store, &account_ref, &password, &hash)?;
+                let signature = if *personal { format_rsv(&signature) } else { format!("0x{}", signature) };
+                Ok(Output::new(signature.clone(), json!({ "signature": signature })))
+            },
+            Command::Derive { address, password, derivation, save, save_vault, save_vault_pwd, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let derivation = derivation::parse(derivation)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let account_ref = StoreAccountRef::new(vault_ref.clone(), address);
+                let derived = if !*save {
+                    service::derive_address(&store, &account_ref, &password, derivation)?
+                } else if save_vault.is_some() {
+                    let save_ref = open_vault(&store, &VaultFlags { vault: save_vault.clone(), vault_pwd: save_vault_pwd.clone() })?;
+                    service::insert_derived(&store, save_ref, &account_ref, &password, derivation)?
+                } else {
+                    service::insert_derived(&store, vault_ref, &account_ref, &password, derivation)?
+                };
+                Ok(address_output(&derived))
+            },
+            Command::Sign_derived { address, password, derivation, message, personal, hex, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                // without a password file the positional arguments shift by one
+                let (password, derivation, message) = match (password_flags.source(), password, derivation, message) {
+                    (None, Some(password), Some(derivation), Some(message)) => (PasswordSource::File(password.clone()), derivation, message),
+                    (Some(source), Some(derivation), Some(message), None) => (source, derivation, message),
+                    _ => return Err(Error::InvalidInput("expected <password> <derivation> <message>, or <derivation> <message> with one of the --password-* flags".into())),
+                };
+                let derivation = derivation::parse(derivation)?;
+                let hash = message_hash(message, *personal, *hex)?;
+                let password = password.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let signature = service::sign_derived(&store, &account_ref, &password, derivation, &hash)?;
+                let signature = if *personal { format_rsv(&signature) } else { format!("0x{}", signature) };
+                Ok(Output::new(signature.clone(), json!({ "signature": signature })))
+            },
+            Command::Sign_typed_data { address, password, file, dir_flag, vault_flags, password_flags } => {
+                let json = fs::read_to_string(file)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Error reading '{}': {}", file, e)))?;
+                let data = TypedData::from_json(&json)?;
+                let domain_separator = format!("0x{:x}", data.domain_separator()?);
+                let struct_hash = data.struct_hash()?.map(|hash| format!("0x{:x}", hash));
+                let digest = format!("0x{:x}", data.digest()?);
+
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let signature = format_rsv(&service::sign_typed_data(&store, &account_ref, &password, &data)?);
+
+                let mut text = format!("signature: {}\ndigest: {}\ndomain_separator: {}", signature, digest, domain_separator);
+                if let Some(ref hash) = struct_hash {
+                    text.push_str(&format!("\nstruct_hash: {}", hash));
+                }
+                Ok(Output::new(text, json!({
+                    "signature": signature,
+                    "digest": digest,
+                    "domain_separator": domain_separator,
+                    "struct_hash": struct_hash,
+                })))
+            },
+            Command::Verify { address, signature, message, personal, hex } => {
+                let address = parse_address("<address>", address)?;
+                let signature = parse_signature(signature)?;
+                let hash = message_hash(message, *personal, *hex)?;
+                let (public, signer) = recover(&signature, &hash)?;
+                let valid = verify(&address, &signature, &hash)?;
+                Ok(signer_output(&public, &signer, Some(valid)))
+            },
+            Command::Recover { signature, message, personal, hex } => {
+                let signature = parse_signature(signature)?;
+                let hash = message_hash(message, *personal, *hex)?;
+                let (public, signer) = recover(&signature, &hash)?;
+                Ok(signer_output(&public, &signer, None))
+            },
+            Command::Public { address, password, dir_flag, vault_flags, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let public = format!("0x{:x}", service::public(&store, &account_ref, &password)?);
+                Ok(Output::new(public.clone(), json!({ "public": public })))
+            },
+            Command::List_vaults { dir_flag } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let vaults = service::list_vaults(&store)?;
+                Ok(Output::new(format_vaults(&vaults), json!({ "vaults": vaults })))
+            },
+            Command::Create_vault { vault, password, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read_new("Vault password")?;
+                service::create_vault(&store, vault, &password)?;
+                Ok(Output::ok())
+            },
+            Command::Change_vault_pwd { vault, old_pwd, new_pwd, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let old_pwd = password_flags.or_file(old_pwd)?.read("Current vault password")?;
+                let new_pwd = new_pwd.read_new("New vault password")?;
+                service::change_vault_pwd(&store, vault, &old_pwd, &new_pwd)?;
+                Ok(Output::ok())
+            },
+            Command::Move_to_vault { address, vault, password, dir_flag, from_vault, vault_pwd, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Vault password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, &VaultFlags { vault: from_vault.clone(), vault_pwd: vault_pwd.clone() })?, address);
+                service::move_to_vault(&store, account_ref, vault, &password)?;
+                Ok(Output::ok())
+            },
+            Command::Move_from_vault { address, vault, password, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Vault password")?;
+                service::move_from_vault(&store, address, vault, &password)?;
+                Ok(Output::ok())
+            },
+            Command::Close_vault { vault, dir_flag } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                service::close_vault(&store, vault)?;
+                Ok(Output::ok())
+            },
+            Command::Delete_vault { vault, password, force, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read("Vault password")?;
+                service::delete_vault(&store, vault, &password, *force)?;
+                Ok(Output::ok())
+            },
+            Command::Rename_vault { vault, new_name, password, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read("Vault password")?;
+                service::rename_vault(&store, vault, new_name, &password)?;
+                Ok(Output::ok())
+            },
+            Command::Get_vault_meta { vault, dir_flag } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let meta = service::get_vault_meta(&store, vault)?;
+                Ok(Output::new(meta.clone(), json!({ "vault": vault, "meta": meta })))
+            },
+            Command::Set_vault_meta { vault, password, meta, dir_flag, password_flags } => {
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read("Vault password")?;
+                service::set_vault_meta(&store, vault, &password, meta)?;
+                Ok(Output::ok())
+            },
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use structopt::StructOpt;
+
+use ethstore::KdfParams;
+use ethstore_lib::ethstore_rely::Error;
+use ethstore_lib::hd::{self, DerivationPath, ExtendedSecret};
+use ethstore_lib::password::PasswordSource;
+use ethstore_lib::service;
+use serde_json::json;
+
+use crate::output::Output;
+use super::ethstore_cmd::{open_store_with_kdf, open_vault};
+use super::{KeyDirFlag, PasswordFlags, VaultFlags, PASSWORD_FLAGS};
+
+// target/debug/bloom-cmd hd mnemonic --words 24
+// target/debug/bloom-cmd hd derive mnemonic.txt --path "m/44'/60'/0'/0/0" --count 5
+// target/debug/bloom-cmd hd import mnemonic.txt password.txt --count 5 [--kdf scrypt] [--vault team --vault-pwd vault_pwd.txt]
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct HdCmd {
+    #[structopt(subcommand)]
+    cmd: Command
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum Command {
+    /// Generate a new BIP-39 mnemonic
+    Mnemonic {
+        /// Number of words: 12, 15, 18, 21 or 24
+        #[structopt(long = "words", default_value = "12")]
+        words: usize,
+    },
+    /// Check the words and checksum of a BIP-39 mnemonic
+    Validate {
+        /// Mnemonic file
+        mnemonic: PasswordSource,
+    },
+    /// List the addresses derived from a mnemonic
+    Derive {
+        /// Mnemonic file
+        mnemonic: PasswordSource,
+        /// BIP-39 passphrase file, none by default
+        #[structopt(long = "passphrase")]
+        passphrase: Option<PasswordSource>,
+        /// BIP-32 path of the first address
+        #[structopt(long = "path", default_value = "m/44'/60'/0'/0/0")]
+        path: String,
+        /// Number of addresses, increasing the last path index
+        #[structopt(long = "count", default_value = "1")]
+        count: u32,
+    },
+    /// Derive keys from a mnemonic and store them encrypted in the keystore
+    Import {
+        /// Mnemonic file
+        mnemonic: PasswordSource,
+        /// Password file for the stored accounts, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// BIP-39 passphrase file, none by default
+        #[structopt(long = "passphrase")]
+        passphrase: Option<PasswordSource>,
+        /// BIP-32 path of the first account
+        #[structopt(long = "path", default_value = "m/44'/60'/0'/0/0")]
+        path: String,
+        /// Number of accounts, increasing the last path index
+        #[structopt(long = "count", default_value = "1")]
+        count: u32,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+}
+
+/// Reads the mnemonic and passphrase and derives `count` keys starting at `path`.
+fn derive_keys(mnemonic: &PasswordSource, passphrase: &Option<PasswordSource>, path: &str, count: u32) -> Result<Vec<(DerivationPath, ExtendedSecret)>, Error> {
+    if count == 0 {
+        return Err(Error::InvalidInput("--count must be at least 1".into()));
+    }
+    let path: DerivationPath = path.parse()?;
+    let mnemonic = hd::parse_mnemonic(mnemonic.read("Mnemonic")?.as_str())?;
+    let passphrase = match passphrase {
+        Some(source) => source.read("BIP-39 passphrase")?.as_str().to_owned(),
+        None => String::new(),
+    };
+    let master = ExtendedSecret::master(&hd::seed(&mnemonic, &passphrase))?;
+    (0..count)
+        .map(|i| -> Result<_, Error> {
+            let path = path.offset(i)?;
+            let key = master.derive(&path)?;
+            Ok((path, key))
+        })
+        .collect()
+}
+
+fn accounts_output(accounts: &[(DerivationPath, String)]) -> Output {
+    let text: Vec<_> = accounts.iter().map(|(path, address)| format!("{} {}", path, address)).collect();
+    let json: Vec<_> = accounts.iter().map(|(path, address)| json!({ "path": path.to_string(), "address": address })).collect();
+    Output::new(text.join("\n"), json!({ "accounts": json }))
+}
+
+impl HdCmd {
+    pub fn run(&self, kdf: KdfParams) -> Result<Output, Error> {
+        match &self.cmd {
+            Command::Mnemonic { words } => {
+                let mnemonic = hd::generate_mnemonic(*words)?;
+                Ok(Output::new(mnemonic.phrase(), json!({ "mnemonic": mnemonic.phrase() })))
+            },
+            Command::Validate { mnemonic } => {
+                hd::parse_mnemonic(mnemonic.read("Mnemonic")?.as_str())?;
+                Ok(Output::ok())
+            },
+            Command::Derive { mnemonic, passphrase, path, count } => {
+                let accounts: Vec<_> = derive_keys(mnemonic, passphrase, path, *count)?
+                    .into_iter()
+                    .map(|(path, key)| (path, format!("0x{:x}", key.address())))
+                    .collect();
+                Ok(accounts_output(&accounts))
+            },
+            Command::Import { mnemonic, password, passphrase, path, count, dir_flag, vault_flags, password_flags } => {
+                let keys = derive_keys(mnemonic, passphrase, path, *count)?;
+                let store = open_store_with_kdf(&dir_flag.dir, kdf)?;
+                let password = password_flags.or_file(password)?.read_new("Account password")?;
+                let vault_ref = open_vault(&store, vault_flags)?;
+                let accounts = keys.into_iter()
+                    .map(|(path, key)| {
+                        let address = service::insert_hd(&store, vault_ref.clone(), &key, &path, &password)?;
+                        Ok((path, format!("0x{:x}", address)))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                Ok(accounts_output(&accounts))
+            },
+        }
+    }
+}
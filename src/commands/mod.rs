@@ -1,45 +1,132 @@
 mod account_cmd;
 mod contract_cmd;
 mod ethstore_cmd;
+mod hd_cmd;
+mod tx_cmd;
 
 
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 use structopt::StructOpt;
 use account_cmd::AccountCmd;
 use contract_cmd::ContractCmd;
 use ethstore_cmd::EthstoreCmd;
+use hd_cmd::HdCmd;
+use tx_cmd::TxCmd;
 
 use ethereum_types::{U256, H160};
+use ethstore::KdfParams;
+use ethstore_lib::ethstore_rely::Error;
+use ethstore_lib::password::PasswordSource;
+use ethstore_lib::state::{self, StateDB};
+use crate::output::Output;
 
 #[derive(Debug, Clone, StructOpt)]
 pub enum Subcommand {
 	Account(AccountCmd),
 	Contract(ContractCmd),
 	Ethstore(EthstoreCmd),
+	Hd(HdCmd),
+	Tx(TxCmd),
 }
 
 impl Subcommand {
 
-	pub fn run(&self) {
+	/// Runs the command, keys it writes are encrypted with `kdf`.
+	pub fn run(&self, kdf: KdfParams) -> Result<Output, Error> {
+		match self {
+			Subcommand::Account(cmd) => cmd.run(),
+			Subcommand::Contract(cmd) => cmd.run(),
+			Subcommand::Ethstore(cmd) => cmd.run(kdf),
+			Subcommand::Hd(cmd) => cmd.run(kdf),
+			Subcommand::Tx(cmd) => cmd.run(),
+		}
+	}
 
-		let backend = "some backend args";
+}
 
-		match self {
-			Subcommand::Account(cmd) => {
-				println!("##Subcommand: Account##");
-				cmd.run(backend);
-			}
-			Subcommand::Contract(cmd) => {
-				println!("##Subcommand: Contract##");
-				cmd.run(backend);
-			}
-			Subcommand::Ethstore(cmd)=> {
-				println!("##Subcommand: Ethstore##");
-				cmd.run(backend);
-			}
+/// Flags that give the password some other way than the password file argument; that
+/// argument is `required_unless_one` and `conflicts_with_all` of these.
+pub const PASSWORD_FLAGS: &[&str] = &["password-prompt", "password-stdin", "password-env", "password-fd"];
+
+/// Password sources other than a password file, for the main password of a command.
+#[derive(Debug, Clone, StructOpt)]
+pub struct PasswordFlags {
+	/// Ask for the password on the terminal instead of reading a password file
+	#[structopt(long = "password-prompt", conflicts_with_all = &["password-stdin", "password-env", "password-fd"])]
+	password_prompt: bool,
+	/// Read the password from standard input
+	#[structopt(long = "password-stdin", conflicts_with_all = &["password-env", "password-fd"])]
+	password_stdin: bool,
+	/// Read the password from environment variable VAR
+	#[structopt(long = "password-env", value_name = "VAR", conflicts_with = "password-fd")]
+	password_env: Option<String>,
+	/// Read the password from the open file descriptor N (unix only)
+	#[structopt(long = "password-fd", value_name = "N")]
+	password_fd: Option<i32>,
+}
+
+impl PasswordFlags {
+	/// Source given by one of the flags.
+	pub fn source(&self) -> Option<PasswordSource> {
+		if self.password_prompt {
+			Some(PasswordSource::Prompt)
+		} else if self.password_stdin {
+			Some(PasswordSource::Stdin)
+		} else if let Some(ref var) = self.password_env {
+			Some(PasswordSource::Env(var.clone()))
+		} else {
+			self.password_fd.map(PasswordSource::Fd)
 		}
+	}
 
+	/// Source given by one of the flags, or else the password file argument `file`.
+	pub fn or_file(&self, file: &Option<PasswordSource>) -> Result<PasswordSource, Error> {
+		self.source()
+			.or_else(|| file.clone())
+			.ok_or_else(|| Error::InvalidInput("a password file or one of --password-prompt, --password-stdin, --password-env, --password-fd is required".into()))
 	}
+}
+
+/// Key directory a command works on.
+#[derive(Debug, Clone, StructOpt)]
+pub struct KeyDirFlag {
+	/// Key directory: parity, parity-(chain), geth, geth-test or a path
+	#[structopt(long = "dir", default_value = "parity")]
+	pub dir: String,
+}
+
+/// Vault a command works in instead of the root directory, with its password.
+#[derive(Debug, Clone, StructOpt)]
+pub struct VaultFlags {
+	/// Vault to use in this operation
+	#[structopt(long = "vault")]
+	pub vault: Option<String>,
+	/// Vault password file, required when --vault is set
+	#[structopt(long = "vault-pwd")]
+	pub vault_pwd: Option<PasswordSource>,
+}
+
+/// Parses a hex address, `what` names the argument in the error message.
+pub fn parse_address(what: &str, value: &str) -> Result<H160, Error> {
+	value.parse().map_err(|_| Error::InvalidInput(format!("{} must be a valid address", what)))
+}
+
+/// Parses a decimal number, `what` names the argument in the error message.
+pub fn parse_u256(what: &str, value: &str) -> Result<U256, Error> {
+	U256::from_dec_str(value).map_err(|_| Error::InvalidInput(format!("{} must be a valid number", what)))
+}
+
+/// Parses hex bytes with optional `0x` prefix, whitespace is ignored.
+pub fn parse_bytes(what: &str, value: &str) -> Result<Vec<u8>, Error> {
+	let value: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+	let value = value.trim_start_matches("0x");
+	hex::decode(value).map_err(|_| Error::InvalidInput(format!("{} must be valid hex", what)))
+}
 
+/// Opens the state database given with `--db`, or the default one.
+pub fn open_state(db: &Option<String>) -> Result<StateDB, Error> {
+	let path = db.as_ref().map(PathBuf::from).unwrap_or_else(state::default_path);
+	Ok(StateDB::open(&path)?)
 }
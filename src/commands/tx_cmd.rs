@@ -0,0 +1,213 @@
+use std::fs;
+use std::io;
+
+use structopt::StructOpt;
+
+use ethstore::StoreAccountRef;
+use ethstore_lib::ethstore_rely::Error;
+use ethstore_lib::password::PasswordSource;
+use ethstore_lib::service;
+use ethstore_lib::transaction::{
+    self, AccessList, AccessListTransaction, DecodedTransaction, DynamicFeeTransaction, SignedTransaction, Transaction, TypedTransaction,
+};
+use serde_json::json;
+
+use crate::output::Output;
+use super::ethstore_cmd::{open_store, open_vault};
+use super::{parse_address, parse_bytes, parse_u256, KeyDirFlag, PasswordFlags, VaultFlags, PASSWORD_FLAGS};
+
+// target/debug/bloom-cmd tx sign a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt --nonce 0 --gas-price 1000000000 --gas 21000 --to 0000000000000000000000000000000000000002 --value 1 --chain-id 1
+// target/debug/bloom-cmd tx sign a8fa5dd30a87bb9e3288d604eb74949c515ab66e password.txt --type 2 --nonce 0 --max-fee 2000000000 --max-priority-fee 1000000000 --gas 21000 --to 0000000000000000000000000000000000000002 --access-list access-list.json
+// target/debug/bloom-cmd tx decode 0xf86c...
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct TxCmd {
+    #[structopt(subcommand)]
+    cmd: Command
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum Command {
+    /// Sign a legacy, access list (type 1) or dynamic fee (type 2) transaction offline
+    Sign {
+        /// The signing account address
+        address: String,
+        /// Password file, or use one of the --password-* flags
+        #[structopt(required_unless_one = PASSWORD_FLAGS, conflicts_with_all = PASSWORD_FLAGS)]
+        password: Option<PasswordSource>,
+        /// The transaction type: 0 (legacy), 1 (EIP-2930) or 2 (EIP-1559)
+        #[structopt(long = "type", default_value = "0", possible_values = &["0", "1", "2"])]
+        kind: u8,
+        /// The sender account nonce
+        #[structopt(long = "nonce")]
+        nonce: String,
+        /// The gas price (Wei), for type 0 and 1
+        #[structopt(long = "gas-price")]
+        gas_price: Option<String>,
+        /// The maximum fee per gas (Wei), for type 2
+        #[structopt(long = "max-fee")]
+        max_fee: Option<String>,
+        /// The maximum priority fee per gas (Wei), for type 2
+        #[structopt(long = "max-priority-fee")]
+        max_priority_fee: Option<String>,
+        /// The gas limit
+        #[structopt(long = "gas")]
+        gas: String,
+        /// The receiver address, omit to deploy a contract
+        #[structopt(long = "to")]
+        to: Option<String>,
+        /// The value (Wei) to transfer
+        #[structopt(long = "value", default_value = "0")]
+        value: String,
+        /// The input data or contract code in hex
+        #[structopt(long = "data")]
+        data: Option<String>,
+        /// JSON access list file, for type 1 and 2
+        #[structopt(long = "access-list")]
+        access_list: Option<String>,
+        /// The chain id the transaction is valid on
+        #[structopt(long = "chain-id", default_value = "1")]
+        chain_id: u64,
+        #[structopt(flatten)]
+        dir_flag: KeyDirFlag,
+        #[structopt(flatten)]
+        vault_flags: VaultFlags,
+        #[structopt(flatten)]
+        password_flags: PasswordFlags,
+    },
+    /// Decode a raw signed transaction and recover its sender
+    Decode {
+        /// The raw transaction in hex
+        raw: String,
+    },
+}
+
+/// Reads a JSON access list: `[{"address": "0x..", "storageKeys": ["0x.."]}]`.
+fn load_access_list(path: &str) -> Result<AccessList, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Error reading '{}': {}", path, e)))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| Error::InvalidInput(format!("--access-list is not a valid access list: {}", e)))
+}
+
+fn required<'a>(what: &str, kind: u8, value: &'a Option<String>) -> Result<&'a str, Error> {
+    value.as_ref()
+        .map(String::as_str)
+        .ok_or_else(|| Error::InvalidInput(format!("{} is required for type {} transactions", what, kind)))
+}
+
+fn rejected(what: &str, kind: u8, value: &Option<String>) -> Result<(), Error> {
+    match value {
+        Some(_) => Err(Error::InvalidInput(format!("{} is not used by type {} transactions", what, kind))),
+        None => Ok(()),
+    }
+}
+
+fn decoded_output(decoded: &DecodedTransaction) -> Output {
+    let mut fields = vec![("type", decoded.transaction.kind().to_string())];
+    let (chain_id, nonce, gas, to, value, data, access_list) = match decoded.transaction {
+        TypedTransaction::Legacy(ref tx) => {
+            fields.push(("gas_price", tx.gas_price.to_string()));
+            (tx.chain_id, tx.nonce, tx.gas, tx.to, tx.value, &tx.data, None)
+        },
+        TypedTransaction::AccessList(ref tx) => {
+            fields.push(("gas_price", tx.gas_price.to_string()));
+            (Some(tx.chain_id), tx.nonce, tx.gas, tx.to, tx.value, &tx.data, Some(&tx.access_list))
+        },
+        TypedTransaction::DynamicFee(ref tx) => {
+            fields.push(("max_priority_fee_per_gas", tx.max_priority_fee_per_gas.to_string()));
+            fields.push(("max_fee_per_gas", tx.max_fee_per_gas.to_string()));
+            (Some(tx.chain_id), tx.nonce, tx.gas, tx.to, tx.value, &tx.data, Some(&tx.access_list))
+        },
+    };
+    if let Some(chain_id) = chain_id {
+        fields.push(("chain_id", chain_id.to_string()));
+    }
+    fields.push(("nonce", nonce.to_string()));
+    fields.push(("gas", gas.to_string()));
+    if let Some(to) = to {
+        fields.push(("to", format!("0x{:x}", to)));
+    }
+    fields.push(("value", value.to_string()));
+    fields.push(("data", format!("0x{}", hex::encode(data))));
+    fields.push(("from", format!("0x{:x}", decoded.sender)));
+    fields.push(("hash", format!("0x{:x}", decoded.hash)));
+
+    let mut text: Vec<_> = fields.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+    let mut json = serde_json::Map::new();
+    for (key, value) in fields {
+        json.insert(key.to_owned(), value.into());
+    }
+    json.insert("to".into(), to.map(|to| format!("0x{:x}", to)).into());
+    if let Some(access_list) = access_list {
+        for item in access_list {
+            text.push(format!("access: 0x{:x}", item.address));
+            text.extend(item.storage_keys.iter().map(|key| format!("  0x{:x}", key)));
+        }
+        json.insert("access_list".into(), json!(access_list));
+    }
+    Output::new(text.join("\n"), json.into())
+}
+
+fn signed_output(signed: &SignedTransaction) -> Output {
+    let raw = format!("0x{}", hex::encode(&signed.raw));
+    let hash = format!("0x{:x}", signed.hash);
+    Output::new(format!("raw: {}\nhash: {}", raw, hash), json!({ "raw": raw, "hash": hash }))
+}
+
+impl TxCmd {
+    pub fn run(&self) -> Result<Output, Error> {
+        match &self.cmd {
+            Command::Sign {
+                address, password, kind, nonce, gas_price, max_fee, max_priority_fee, gas, to, value, data, access_list, chain_id, dir_flag, vault_flags, password_flags
+            } => {
+                let kind = *kind;
+                let chain_id = *chain_id;
+                let nonce = parse_u256("--nonce", nonce)?;
+                let gas = parse_u256("--gas", gas)?;
+                let to = to.as_ref().map(|to| parse_address("--to", to)).transpose()?;
+                let value = parse_u256("--value", value)?;
+                let data = parse_bytes("--data", data.as_ref().map_or("", String::as_str))?;
+                let transaction = match kind {
+                    0 => {
+                        rejected("--max-fee", kind, max_fee)?;
+                        rejected("--max-priority-fee", kind, max_priority_fee)?;
+                        rejected("--access-list", kind, access_list)?;
+                        let gas_price = parse_u256("--gas-price", required("--gas-price", kind, gas_price)?)?;
+                        TypedTransaction::Legacy(Transaction { chain_id: Some(chain_id), nonce, gas_price, gas, to, value, data })
+                    },
+                    1 => {
+                        rejected("--max-fee", kind, max_fee)?;
+                        rejected("--max-priority-fee", kind, max_priority_fee)?;
+                        let gas_price = parse_u256("--gas-price", required("--gas-price", kind, gas_price)?)?;
+                        let access_list = access_list.as_ref().map(|path| load_access_list(path)).transpose()?.unwrap_or_default();
+                        TypedTransaction::AccessList(AccessListTransaction { chain_id, nonce, gas_price, gas, to, value, data, access_list })
+                    },
+                    _ => {
+                        rejected("--gas-price", kind, gas_price)?;
+                        let max_fee_per_gas = parse_u256("--max-fee", required("--max-fee", kind, max_fee)?)?;
+                        let max_priority_fee_per_gas = parse_u256("--max-priority-fee", required("--max-priority-fee", kind, max_priority_fee)?)?;
+                        if max_priority_fee_per_gas > max_fee_per_gas {
+                            return Err(Error::InvalidInput("--max-priority-fee must not exceed --max-fee".into()));
+                        }
+                        let access_list = access_list.as_ref().map(|path| load_access_list(path)).transpose()?.unwrap_or_default();
+                        TypedTransaction::DynamicFee(DynamicFeeTransaction {
+                            chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas, to, value, data, access_list,
+                        })
+                    },
+                };
+
+                let store = open_store(&dir_flag.dir)?;
+                let address = parse_address("<address>", address)?;
+                let password = password_flags.or_file(password)?.read("Account password")?;
+                let account_ref = StoreAccountRef::new(open_vault(&store, vault_flags)?, address);
+                let signed = service::sign_transaction(&store, &account_ref, &password, &transaction)?;
+                Ok(signed_output(&signed))
+            },
+            Command::Decode { raw } => {
+                let raw = parse_bytes("<raw>", raw)?;
+                Ok(decoded_output(&transaction::decode(&raw)?))
+            },
+        }
+    }
+}
@@ -1,67 +1,77 @@
 mod commands;
+mod output;
 
 use commands::Subcommand; //自定义
 use structopt::StructOpt; //官方
+use structopt::clap::ErrorKind;
+use std::{env, process};
 
 extern crate ethstore_lib;
-use ethstore_lib::ethstore_rely::*;
+use ethstore::KdfParams;
+use ethstore_lib::ethstore_rely::Error;
+use output::OutputFormat;
 
 
 #[derive(Debug, StructOpt)]
 pub struct Cli {
+	/// Output format: text or json
+	#[structopt(long = "output", default_value = "text", global = true)]
+	pub output: OutputFormat,
+
+	/// Key derivation for keys written by the command: pbkdf2, scrypt, or with parameters
+	/// such as pbkdf2:c=262144 or scrypt:n=4096,p=6
+	#[structopt(long = "kdf", default_value = "pbkdf2", global = true)]
+	pub kdf: KdfParams,
+
 	#[structopt(subcommand)]
 	pub subcmd: Option<Subcommand>,
 }
 
 
 fn main() {
-	let cli = Cli::from_args();
-	//println!("{:#?}", cli);
+	let args: Vec<String> = env::args().collect();
+	let cli = match Cli::from_iter_safe(&args) {
+		Ok(cli) => cli,
+		Err(err) => match output_format(&args) {
+			OutputFormat::Json if err.kind != ErrorKind::HelpDisplayed && err.kind != ErrorKind::VersionDisplayed => {
+				let message = err.message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_owned();
+				fail(&Error::InvalidInput(message), OutputFormat::Json)
+			},
+			_ => err.exit(),
+		},
+	};
 
-	if let Some(ref subcmd) = cli.subcmd {
-		subcmd.run();
-	} else {
-		println!("{:#?}", cli);
+	match cli.subcmd {
+		Some(ref subcmd) => match subcmd.run(cli.kdf) {
+			Ok(output) => output.print(cli.output),
+			Err(err) => fail(&err, cli.output),
+		},
+		None => {
+			if cli.output == OutputFormat::Text {
+				let _ = Cli::clap().print_help();
+				println!();
+			}
+			fail(&Error::InvalidInput("a subcommand is required".into()), cli.output)
+		},
 	}
-
 }
 
-/***
-
-Cli {
-    subcmd: Some(
-        Contract(
-            ContractCmd {
-                cmd: Call {
-                    from: "0000000000000000000000000000000000000001",
-                    value: "0",
-                    to: "0000000000000000000000000000000000000002",
-                    gas: 100000,
-                    gas_price: "0",
-                    data: Some(
-                        "000000",
-                    ),
-                    data_file: Some(
-                        "abc.txt",
-                    ),
-                },
-            },
-        ),
-    ),
+/// Prints the error and exits with its code.
+fn fail(err: &Error, format: OutputFormat) -> ! {
+	output::print_error(err, format);
+	process::exit(err.exit_code())
 }
-##Subcommand: Contract##
-Call "some backend args"
- from==0x0000000000000000000000000000000000000001
- to==0x0000000000000000000000000000000000000002
- value==0
- gas==100000
- gas_price==0
- data=="000000"
- data_file==Some(
-    "abc.txt",
-)
- ***/
-
-
-
 
+/// Format asked for with `--output`, for arguments that could not be parsed.
+fn output_format(args: &[String]) -> OutputFormat {
+	args.iter()
+		.enumerate()
+		.filter_map(|(i, arg)| if arg == "--output" {
+			args.get(i + 1).map(String::as_str)
+		} else {
+			arg.strip_prefix("--output=")
+		})
+		.last()
+		.and_then(|format| format.parse().ok())
+		.unwrap_or(OutputFormat::Text)
+}
@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use ethstore_lib::ethstore_rely::Error;
+
+/// Output format selected with the global `--output` flag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+	/// Human readable text, one value per line.
+	Text,
+	/// A single JSON document per invocation.
+	Json,
+}
+
+impl FromStr for OutputFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(OutputFormat::Text),
+			"json" => Ok(OutputFormat::Json),
+			other => Err(format!("Invalid output format: {} (expected text or json)", other)),
+		}
+	}
+}
+
+/// Result of a command, renderable in every `OutputFormat`.
+#[derive(Debug)]
+pub struct Output {
+	text: String,
+	json: Value,
+}
+
+impl Output {
+	/// Creates output from its text and JSON renderings.
+	pub fn new<T: Into<String>>(text: T, json: Value) -> Self {
+		Output {
+			text: text.into(),
+			json,
+		}
+	}
+
+	/// Output of a command that only reports success.
+	pub fn ok() -> Self {
+		Output::new("OK", json!({ "ok": true }))
+	}
+
+	/// Text rendering.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	/// JSON rendering.
+	pub fn json(&self) -> &Value {
+		&self.json
+	}
+
+	/// Prints the output to stdout.
+	pub fn print(&self, format: OutputFormat) {
+		match format {
+			OutputFormat::Text => if !self.text.is_empty() {
+				println!("{}", self.text);
+			},
+			OutputFormat::Json => println!("{}", self.json),
+		}
+	}
+}
+
+/// Prints the error: as text to stderr, or as a JSON document to stdout.
+pub fn print_error(err: &Error, format: OutputFormat) {
+	match format {
+		OutputFormat::Text => eprintln!("error: {}", err),
+		OutputFormat::Json => println!("{}", json!({
+			"error": {
+				"code": err.exit_code(),
+				"message": err.to_string(),
+			}
+		})),
+	}
+}
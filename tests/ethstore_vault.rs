@@ -0,0 +1,39 @@
+use std::fs;
+use std::process::{Command, Output};
+
+const SECRET: &str = "7d29fab185a33e2cd955812397354c472d2b84615b645aa135ff539f6b0d70d5";
+const ADDRESS: &str = "a8fa5dd30a87bb9e3288d604eb74949c515ab66e";
+
+fn run(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_bloom-cmd"))
+		.arg("ethstore")
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+fn stdout(output: Output) -> String {
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn vault_flags_and_meta() {
+	let dir = tempfile::tempdir().unwrap();
+	let keys = dir.path().join("keys");
+	let keys = keys.to_str().unwrap();
+	let password = dir.path().join("password");
+	fs::write(&password, "password\n").unwrap();
+	let password = password.to_str().unwrap();
+
+	stdout(run(&["create-vault", "team", password, "--dir", keys]));
+	stdout(run(&["insert", SECRET, password, "--dir", keys, "--vault", "team", "--vault-pwd", password]));
+	assert!(stdout(run(&["list", "--dir", keys, "--vault", "team", "--vault-pwd", password])).contains(ADDRESS));
+	assert!(!stdout(run(&["list", "--dir", keys])).contains(ADDRESS));
+
+	stdout(run(&["set-vault-meta", "team", password, "{\"hint\":\"first pet\"}", "--dir", keys]));
+	assert!(stdout(run(&["get-vault-meta", "team", "--dir", keys])).contains("{\"hint\":\"first pet\"}"));
+
+	stdout(run(&["close-vault", "team", "--dir", keys]));
+	assert!(!run(&["close-vault", "other", "--dir", keys]).status.success());
+}
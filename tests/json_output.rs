@@ -0,0 +1,61 @@
+use std::fs;
+use std::process::{Command, Output};
+
+use serde_json::{json, Value};
+
+fn run_json(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_bloom-cmd"))
+		.args(&["--output", "json"])
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+/// Parses stdout, failing unless it holds exactly one JSON document.
+fn document(output: &Output) -> Value {
+	serde_json::from_slice(&output.stdout)
+		.unwrap_or_else(|e| panic!("stdout is not a single JSON document ({}): {}", e, String::from_utf8_lossy(&output.stdout)))
+}
+
+fn assert_error(output: &Output, code: i32) {
+	assert_eq!(output.status.code(), Some(code));
+	let document = document(output);
+	assert_eq!(document["error"]["code"], json!(code), "{}", document);
+	assert!(document["error"]["message"].is_string(), "{}", document);
+}
+
+#[test]
+fn json_output_is_one_document() {
+	let dir = tempfile::tempdir().unwrap();
+	let keys = dir.path().join("keys");
+	let keys = keys.to_str().unwrap();
+	let password = dir.path().join("password");
+	fs::write(&password, "password\n").unwrap();
+	let password = password.to_str().unwrap();
+	let wrong = dir.path().join("wrong");
+	fs::write(&wrong, "wrong\n").unwrap();
+	let wrong = wrong.to_str().unwrap();
+
+	let output = run_json(&["ethstore", "create-vault", "team", password, "--dir", keys]);
+	assert!(output.status.success());
+	assert_eq!(document(&output), json!({ "ok": true }));
+
+	let output = run_json(&["ethstore", "list-vaults", "--dir", keys]);
+	assert!(output.status.success());
+	assert_eq!(document(&output), json!({ "vaults": ["team"] }));
+
+	let output = run_json(&["ethstore", "list", "--dir", keys, "--vault", "team", "--vault-pwd", wrong]);
+	assert_error(&output, 2);
+
+	let output = run_json(&["ethstore", "public", "a8fa5dd30a87bb9e3288d604eb74949c515ab66e", password, "--dir", keys]);
+	assert_error(&output, 3);
+
+	let output = run_json(&["ethstore", "list", "--dir", keys, "--vault", "missing", "--vault-pwd", password]);
+	assert_error(&output, 4);
+
+	let output = run_json(&["ethstore", "sign"]);
+	assert_error(&output, 5);
+
+	let output = run_json(&[]);
+	assert_error(&output, 5);
+}